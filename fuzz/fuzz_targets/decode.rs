@@ -0,0 +1,23 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use sketches_rust::{DDSketch, EncodedSketchView};
+
+fuzz_target!(|data: &[u8]| {
+    let _ = DDSketch::decode_from(data);
+    let _ = DDSketch::decode_strict(data);
+    let _ = DDSketch::from_proto_bytes(data);
+    if let Ok(view) = EncodedSketchView::new(data) {
+        let _ = view.get_min();
+        let _ = view.get_max();
+        let _ = view.get_value_at_quantile(0.5);
+    }
+    if let Ok(sketch) = DDSketch::decode(data) {
+        let _ = sketch.get_min();
+        let _ = sketch.get_max();
+        let _ = sketch.get_value_at_quantile(0.99);
+        let encoded = sketch.encode().unwrap();
+        assert_eq!(encoded.len(), sketch.encoded_len());
+        assert!(DDSketch::decode(&encoded).is_ok());
+    }
+});
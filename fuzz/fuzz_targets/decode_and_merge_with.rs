@@ -0,0 +1,30 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use sketches_rust::DDSketch;
+
+fn sketch() -> DDSketch {
+    let mut sketch = DDSketch::unbounded_dense(2e-2).unwrap();
+    sketch.accept(1.0);
+    sketch.accept(-1.0);
+    sketch
+}
+
+fuzz_target!(|data: &[u8]| {
+    // Starting with the mapping of the sketch, the bytes get past the mapping check.
+    let mut prefixed = sketch().encode().unwrap()[..17].to_vec();
+    prefixed.extend_from_slice(data);
+
+    for bytes in [data, prefixed.as_slice()] {
+        let mut unbounded = sketch();
+        if unbounded.decode_and_merge_with(bytes).is_ok() {
+            let _ = unbounded.get_value_at_quantile(0.5);
+            let _ = unbounded.encode().unwrap();
+        }
+        let mut collapsing = DDSketch::collapsing_lowest_dense(2e-2, 128).unwrap();
+        if collapsing.decode_and_merge_with_reader(bytes).is_ok() {
+            let _ = collapsing.get_value_at_quantile(0.5);
+        }
+        let _ = DDSketch::merge_all_encoded([bytes, bytes]);
+    }
+});
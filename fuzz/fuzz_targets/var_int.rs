@@ -0,0 +1,27 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use sketches_rust::fuzzing;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok((value, len)) = fuzzing::decode_unsigned_var_long(data) {
+        let encoded = fuzzing::encode_unsigned_var_long(value);
+        assert_eq!(encoded.len(), fuzzing::unsigned_var_long_encoded_length(value));
+        assert!(encoded.len() <= len);
+        let decoded = fuzzing::decode_unsigned_var_long(&encoded).unwrap();
+        assert_eq!((value, encoded.len()), decoded);
+    }
+    if let Ok((value, len)) = fuzzing::decode_signed_var_long(data) {
+        let encoded = fuzzing::encode_signed_var_long(value);
+        assert_eq!(encoded.len(), fuzzing::signed_var_long_encoded_length(value));
+        assert!(encoded.len() <= len);
+        let decoded = fuzzing::decode_signed_var_long(&encoded).unwrap();
+        assert_eq!((value, encoded.len()), decoded);
+    }
+    if let Ok((value, _)) = fuzzing::decode_var_double(data) {
+        let encoded = fuzzing::encode_var_double(value);
+        assert_eq!(encoded.len(), fuzzing::var_double_encoded_length(value));
+        let decoded = fuzzing::decode_var_double(&encoded).unwrap();
+        assert_eq!(encoded.len(), decoded.1);
+    }
+});
@@ -0,0 +1,144 @@
+use crate::error::Error;
+use crate::index_mapping::{IndexMapping, IndexMappingLayout};
+use crate::sketch::{DDSketch, Extensions, OutOfRangePolicy};
+use crate::store::StoreType;
+
+/// Configures and creates `DDSketch` instances.
+///
+/// By default, sketches use the cubically interpolated mapping with no index offset and
+/// unbounded dense stores for both signs.
+///
+/// ```rust
+///     use sketches_rust::{DDSketchBuilder, IndexMappingLayout, StoreType};
+///     let mut d = DDSketchBuilder::new(0.01)
+///         .layout(IndexMappingLayout::LOG)
+///         .negative_store(StoreType::CollapsingLowestDense(128))
+///         .positive_store(StoreType::CollapsingLowestDense(2048))
+///         .zero_threshold(1e-9)
+///         .build()
+///         .unwrap();
+///     d.accept(1.0);
+///     assert_eq!(d.get_count(), 1.0);
+/// ```
+#[derive(Clone, Debug)]
+pub struct DDSketchBuilder {
+    layout: IndexMappingLayout,
+    relative_accuracy: f64,
+    index_offset: f64,
+    negative_store: StoreType,
+    positive_store: StoreType,
+    zero_threshold: f64,
+    out_of_range_policy: OutOfRangePolicy,
+    exact_summary_statistics: bool,
+    extensions: Extensions,
+}
+
+impl DDSketchBuilder {
+    pub fn new(relative_accuracy: f64) -> DDSketchBuilder {
+        DDSketchBuilder {
+            layout: IndexMappingLayout::LogCubic,
+            relative_accuracy,
+            index_offset: 0.0,
+            negative_store: StoreType::UnboundedDense,
+            positive_store: StoreType::UnboundedDense,
+            zero_threshold: 0.0,
+            out_of_range_policy: OutOfRangePolicy::default(),
+            exact_summary_statistics: false,
+            extensions: Extensions::default(),
+        }
+    }
+
+    pub fn layout(mut self, layout: IndexMappingLayout) -> DDSketchBuilder {
+        self.layout = layout;
+        self
+    }
+
+    pub fn index_offset(mut self, index_offset: f64) -> DDSketchBuilder {
+        self.index_offset = index_offset;
+        self
+    }
+
+    /// Uses the same kind of store for both negative and positive values.
+    pub fn store(self, store: StoreType) -> DDSketchBuilder {
+        self.negative_store(store).positive_store(store)
+    }
+
+    pub fn negative_store(mut self, store: StoreType) -> DDSketchBuilder {
+        self.negative_store = store;
+        self
+    }
+
+    pub fn positive_store(mut self, store: StoreType) -> DDSketchBuilder {
+        self.positive_store = store;
+        self
+    }
+
+    /// Values whose magnitude is lower than or equal to the threshold are counted as zero.
+    /// Merging sketches with different thresholds keeps the larger one.
+    pub fn zero_threshold(mut self, zero_threshold: f64) -> DDSketchBuilder {
+        self.zero_threshold = zero_threshold;
+        self
+    }
+
+    pub fn out_of_range_policy(mut self, out_of_range_policy: OutOfRangePolicy) -> DDSketchBuilder {
+        self.out_of_range_policy = out_of_range_policy;
+        self
+    }
+
+    /// Tracks the exact count, sum, extremes and central moments of the accepted values.
+    pub fn exact_summary_statistics(mut self, enabled: bool) -> DDSketchBuilder {
+        self.exact_summary_statistics = enabled;
+        self
+    }
+
+    /// Writes a positive zero threshold into the encoding, so that decoding the sketch restores
+    /// it. Other implementations don't know about the threshold and fail to decode such
+    /// sketches; without it, they decode the values below the threshold as zeros.
+    pub fn encode_zero_threshold(mut self, enabled: bool) -> DDSketchBuilder {
+        self.extensions.zero_threshold = enabled;
+        self
+    }
+
+    /// Writes the counts of the dropped, NaN and infinite values into the encoding. Other
+    /// implementations don't know about these counts and fail to decode such sketches.
+    pub fn encode_value_counters(mut self, enabled: bool) -> DDSketchBuilder {
+        self.extensions.value_counters = enabled;
+        self
+    }
+
+    /// Lets the encoding split the bins of each store into several blocks when it makes it
+    /// smaller, which mostly happens to the bins of raw unweighted samples, whose counts are then
+    /// left out. This crate decodes such sketches, other implementations may not.
+    pub fn encode_compact_bins(mut self, enabled: bool) -> DDSketchBuilder {
+        self.extensions.compact_bins = enabled;
+        self
+    }
+
+    pub fn build(&self) -> Result<DDSketch, Error> {
+        if !(self.zero_threshold >= 0.0 && self.zero_threshold.is_finite()) {
+            return Err(Error::InvalidArgument(
+                "The zero threshold must be a non-negative finite value.",
+            ));
+        }
+        let index_mapping = IndexMapping::with_relative_accuracy_offset(
+            self.layout,
+            self.relative_accuracy,
+            self.index_offset,
+        )?;
+        Ok(DDSketch::with_stores(
+            index_mapping,
+            self.negative_store.build()?,
+            self.positive_store.build()?,
+            self.zero_threshold,
+            self.out_of_range_policy,
+            self.exact_summary_statistics,
+            self.extensions,
+        ))
+    }
+
+    /// Builds a sketch from a configuration that `build` has already accepted once.
+    pub(crate) fn build_validated(&self) -> DDSketch {
+        self.build()
+            .expect("The builder has already been validated.")
+    }
+}
@@ -0,0 +1,149 @@
+use crate::builder::DDSketchBuilder;
+use crate::error::Error;
+use crate::sketch::DDSketch;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+static NEXT_THREAD_SHARD: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    static THREAD_SHARD: usize = NEXT_THREAD_SHARD.fetch_add(1, Ordering::Relaxed);
+}
+
+/// A sketch that can be fed from many threads at once.
+///
+/// Values are recorded into one of several shards, each of them a `DDSketch` behind its own
+/// lock. Threads are spread over the shards so that they rarely compete for the same lock, and
+/// the shards are merged whenever the whole distribution is queried.
+///
+/// ```rust
+///     use sketches_rust::{ConcurrentDDSketch, DDSketch};
+///     use std::sync::Arc;
+///     let d = Arc::new(ConcurrentDDSketch::new(DDSketch::builder(0.02)).unwrap());
+///     let handles: Vec<_> = (0..4)
+///         .map(|_| {
+///             let d = Arc::clone(&d);
+///             std::thread::spawn(move || d.accept(1.0))
+///         })
+///         .collect();
+///     for handle in handles {
+///         handle.join().unwrap();
+///     }
+///     assert_eq!(d.get_count(), 4.0);
+/// ```
+pub struct ConcurrentDDSketch {
+    builder: DDSketchBuilder,
+    shards: Box<[Mutex<DDSketch>]>,
+}
+
+impl ConcurrentDDSketch {
+    /// Creates a sketch with as many shards as the available parallelism.
+    pub fn new(builder: DDSketchBuilder) -> Result<ConcurrentDDSketch, Error> {
+        let num_shards = std::thread::available_parallelism()
+            .map(|n| n.get())
+            .unwrap_or(1);
+        ConcurrentDDSketch::with_shards(builder, num_shards)
+    }
+
+    pub fn with_shards(
+        builder: DDSketchBuilder,
+        num_shards: usize,
+    ) -> Result<ConcurrentDDSketch, Error> {
+        if num_shards == 0 {
+            return Err(Error::InvalidArgument(
+                "The number of shards must be positive.",
+            ));
+        }
+        let shards = (0..num_shards)
+            .map(|_| builder.build().map(Mutex::new))
+            .collect::<Result<Vec<_>, Error>>()?;
+        Ok(ConcurrentDDSketch {
+            builder,
+            shards: shards.into_boxed_slice(),
+        })
+    }
+
+    pub fn accept(&self, value: f64) {
+        self.accept_with_count(value, 1.0);
+    }
+
+    pub fn accept_with_count(&self, value: f64, count: f64) {
+        self.lock_any_shard().accept_with_count(value, count);
+    }
+
+    pub fn merge_with(&self, other: &DDSketch) -> Result<(), Error> {
+        self.lock_any_shard().merge_with(other)
+    }
+
+    pub fn decode_and_merge_with(&self, bytes: &[u8]) -> Result<(), Error> {
+        self.lock_any_shard().decode_and_merge_with(bytes)
+    }
+
+    pub fn clear(&self) {
+        for shard in self.shards.iter() {
+            lock(shard).clear();
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.shards.iter().all(|shard| lock(shard).is_empty())
+    }
+
+    pub fn get_count(&self) -> f64 {
+        self.shards
+            .iter()
+            .map(|shard| lock(shard).get_count())
+            .sum()
+    }
+
+    pub fn get_sum(&self) -> Option<f64> {
+        self.merged().get_sum()
+    }
+
+    pub fn get_min(&self) -> Option<f64> {
+        self.merged().get_min()
+    }
+
+    pub fn get_max(&self) -> Option<f64> {
+        self.merged().get_max()
+    }
+
+    pub fn get_average(&self) -> Option<f64> {
+        self.merged().get_average()
+    }
+
+    pub fn get_value_at_quantile(&self, quantile: f64) -> Option<f64> {
+        self.merged().get_value_at_quantile(quantile)
+    }
+
+    pub fn encode(&self) -> Result<Vec<u8>, Error> {
+        self.merged().encode()
+    }
+
+    /// Merges the shards into a regular sketch. Each shard is locked in turn, so values
+    /// accepted concurrently may or may not be part of the result.
+    pub fn merged(&self) -> DDSketch {
+        let mut sketch = self.builder.build_validated();
+        for shard in self.shards.iter() {
+            sketch.merge_with_same_mapping(&lock(shard));
+        }
+        sketch
+    }
+
+    /// Locks the shard of the current thread, or any other shard that is free at the moment,
+    /// and waits for the former if all of them are busy.
+    fn lock_any_shard(&self) -> MutexGuard<'_, DDSketch> {
+        let home = THREAD_SHARD.with(|shard| *shard) % self.shards.len();
+        for i in 0..self.shards.len() {
+            let shard = &self.shards[(home + i) % self.shards.len()];
+            if let Ok(guard) = shard.try_lock() {
+                return guard;
+            }
+        }
+        lock(&self.shards[home])
+    }
+}
+
+fn lock(shard: &Mutex<DDSketch>) -> MutexGuard<'_, DDSketch> {
+    shard.lock().unwrap_or_else(PoisonError::into_inner)
+}
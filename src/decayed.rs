@@ -0,0 +1,154 @@
+use crate::builder::DDSketchBuilder;
+use crate::clock::{Clock, SystemClock};
+use crate::error::Error;
+use crate::sketch::DDSketch;
+use std::time::Duration;
+
+/// Once the weight of new values exceeds `exp(RENORMALIZATION_THRESHOLD)`, the landmark is moved
+/// to the present and the recorded weights are scaled down accordingly.
+const RENORMALIZATION_THRESHOLD: f64 = 64.0;
+
+/// A sketch whose values lose half of their weight every half-life, so that quantiles, counts
+/// and averages reflect the recent values more than the old ones.
+///
+/// The sketch implements forward decay: a value accepted at time `t` is recorded with the weight
+/// `exp(λ (t - L))` relative to a landmark `L`, with `λ = ln 2 / half_life`, so that the counts
+/// already recorded never have to be updated as time goes by. Queries divide the weights by
+/// `exp(λ (now - L))`. As the weights grow exponentially, the landmark is periodically moved
+/// forward and the recorded counts scaled down to avoid overflowing.
+///
+/// ```rust
+///     use sketches_rust::{DDSketch, DecayedDDSketch, ManualClock};
+///     use std::sync::Arc;
+///     use std::time::Duration;
+///     let clock = Arc::new(ManualClock::default());
+///     let mut d = DecayedDDSketch::with_clock(
+///         DDSketch::builder(0.02),
+///         Duration::from_secs(60),
+///         Arc::clone(&clock),
+///     )
+///     .unwrap();
+///     d.accept(1.0);
+///     clock.advance(Duration::from_secs(60));
+///     assert!((d.get_count() - 0.5).abs() < 1e-9);
+/// ```
+pub struct DecayedDDSketch<C: Clock = SystemClock> {
+    builder: DDSketchBuilder,
+    sketch: DDSketch,
+    clock: C,
+    half_life: Duration,
+    decay_rate: f64,
+    landmark: f64,
+}
+
+impl DecayedDDSketch<SystemClock> {
+    pub fn new(
+        builder: DDSketchBuilder,
+        half_life: Duration,
+    ) -> Result<DecayedDDSketch<SystemClock>, Error> {
+        DecayedDDSketch::with_clock(builder, half_life, SystemClock)
+    }
+}
+
+impl<C: Clock> DecayedDDSketch<C> {
+    pub fn with_clock(
+        builder: DDSketchBuilder,
+        half_life: Duration,
+        clock: C,
+    ) -> Result<DecayedDDSketch<C>, Error> {
+        if half_life.is_zero() {
+            return Err(Error::InvalidArgument("The half-life must be positive."));
+        }
+        let sketch = builder.build()?;
+        let landmark = clock.now().as_secs_f64();
+        Ok(DecayedDDSketch {
+            builder,
+            sketch,
+            clock,
+            half_life,
+            decay_rate: std::f64::consts::LN_2 / half_life.as_secs_f64(),
+            landmark,
+        })
+    }
+
+    pub fn get_half_life(&self) -> Duration {
+        self.half_life
+    }
+
+    pub fn accept(&mut self, value: f64) {
+        self.accept_with_count(value, 1.0);
+    }
+
+    pub fn accept_with_count(&mut self, value: f64, count: f64) {
+        let mut exponent = self.decay_rate * (self.clock.now().as_secs_f64() - self.landmark);
+        if exponent > RENORMALIZATION_THRESHOLD {
+            self.sketch.multiply_counts((-exponent).exp());
+            self.landmark += exponent / self.decay_rate;
+            exponent = 0.0;
+        }
+        self.sketch.accept_with_count(value, count * exponent.exp());
+    }
+
+    pub fn clear(&mut self) {
+        self.sketch.clear();
+        self.landmark = self.clock.now().as_secs_f64();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sketch.is_empty()
+    }
+
+    /// Returns the sum of the weights of the values, each of them having decayed since it was
+    /// accepted.
+    pub fn get_count(&self) -> f64 {
+        self.sketch.get_count() * self.decay()
+    }
+
+    pub fn get_sum(&self) -> Option<f64> {
+        Some(self.sketch.get_sum()? * self.decay())
+    }
+
+    pub fn get_average(&self) -> Option<f64> {
+        self.sketch.get_average()
+    }
+
+    /// Returns the lowest value accepted since the sketch was created or cleared. Unlike the
+    /// counts and quantiles, the extremes don't decay: a value keeps being the minimum however
+    /// small its weight has become.
+    pub fn get_min(&self) -> Option<f64> {
+        self.sketch.get_min()
+    }
+
+    /// Returns the highest value accepted since the sketch was created or cleared, see `get_min`.
+    pub fn get_max(&self) -> Option<f64> {
+        self.sketch.get_max()
+    }
+
+    /// Returns the value at `quantile` of the values weighted by their decayed counts, a value
+    /// accepted just now counting as one.
+    pub fn get_value_at_quantile(&self, quantile: f64) -> Option<f64> {
+        if !(0.0..=1.0).contains(&quantile) {
+            return None;
+        }
+
+        let count = self.get_count();
+        if count <= 0.0 {
+            return None;
+        }
+
+        let rank = quantile * (count - 1.0).max(0.0);
+        self.sketch.get_value_at_rank(rank / self.decay())
+    }
+
+    /// Returns a regular sketch holding the decayed weights of the values as of now.
+    pub fn to_sketch(&self) -> DDSketch {
+        let mut sketch = self.builder.build_validated();
+        sketch.merge_with_same_mapping(&self.sketch);
+        sketch.multiply_counts(self.decay());
+        sketch
+    }
+
+    fn decay(&self) -> f64 {
+        (-self.decay_rate * (self.clock.now().as_secs_f64() - self.landmark)).exp()
+    }
+}
@@ -0,0 +1,122 @@
+use crate::error::Error;
+use crate::sketch::DDSketch;
+use std::iter::Peekable;
+
+/// Returns the Kolmogorov-Smirnov statistic between the distributions of two sketches, that is
+/// the largest absolute difference between their cumulative distribution functions, or `None`
+/// if either sketch is empty.
+pub fn kolmogorov_smirnov_distance(a: &DDSketch, b: &DDSketch) -> Result<Option<f64>, Error> {
+    let mut distance = 0.0;
+    let walked = walk_in_lockstep(a, b, |_value, cdf_a, cdf_b, _next_value| {
+        distance = f64::max(distance, f64::abs(cdf_a - cdf_b));
+    })?;
+    Ok(walked.then_some(distance))
+}
+
+/// Returns the Wasserstein-1 (Earth Mover's) distance between the distributions of two sketches,
+/// in the unit of the values, or `None` if either sketch is empty.
+pub fn wasserstein_distance(a: &DDSketch, b: &DDSketch) -> Result<Option<f64>, Error> {
+    let mut distance = 0.0;
+    let walked = walk_in_lockstep(a, b, |value, cdf_a, cdf_b, next_value| {
+        if let Some(next_value) = next_value {
+            distance += f64::abs(cdf_a - cdf_b) * (next_value - value);
+        }
+    })?;
+    Ok(walked.then_some(distance))
+}
+
+/// Returns, for each quantile, the relative difference `|qa - qb| / max(|qa|, |qb|)` between the
+/// values of both sketches at that quantile, or `None` if either sketch is empty.
+pub fn quantile_relative_differences(
+    a: &DDSketch,
+    b: &DDSketch,
+    quantiles: &[f64],
+) -> Result<Option<Vec<f64>>, Error> {
+    check_mappings(a, b)?;
+    if a.is_empty() || b.is_empty() {
+        return Ok(None);
+    }
+
+    let mut differences = Vec::with_capacity(quantiles.len());
+    for quantile in quantiles {
+        let (value_a, value_b) = match (
+            a.get_value_at_quantile(*quantile),
+            b.get_value_at_quantile(*quantile),
+        ) {
+            (Some(value_a), Some(value_b)) => (value_a, value_b),
+            _ => {
+                return Err(Error::InvalidArgument(
+                    "The quantile must be between 0 and 1.",
+                ))
+            }
+        };
+        let scale = f64::max(f64::abs(value_a), f64::abs(value_b));
+        if scale == 0.0 {
+            differences.push(0.0);
+        } else {
+            differences.push(f64::abs(value_a - value_b) / scale);
+        }
+    }
+    Ok(Some(differences))
+}
+
+fn check_mappings(a: &DDSketch, b: &DDSketch) -> Result<(), Error> {
+    if a.get_index_mapping() != b.get_index_mapping() {
+        return Err(Error::UnmatchedMapping {
+            expected: a.get_index_mapping().clone(),
+            actual: b.get_index_mapping().clone(),
+        });
+    }
+    Ok(())
+}
+
+/// Walks the bins of both sketches in ascending order of value. As both sketches share the same
+/// mapping, bins of the same index have the same representative value and are visited together.
+/// For every distinct value, `visit` is called with the value, the cumulative distribution
+/// functions of both sketches at that value and the next distinct value, if any.
+///
+/// Returns `false` without visiting anything if either sketch is empty.
+fn walk_in_lockstep<F>(a: &DDSketch, b: &DDSketch, mut visit: F) -> Result<bool, Error>
+where
+    F: FnMut(f64, f64, f64, Option<f64>),
+{
+    check_mappings(a, b)?;
+    let count_a = a.get_count();
+    let count_b = b.get_count();
+    if count_a <= 0.0 || count_b <= 0.0 {
+        return Ok(false);
+    }
+
+    let mut bins_a = a.get_bins().peekable();
+    let mut bins_b = b.get_bins().peekable();
+    let mut n_a = 0.0;
+    let mut n_b = 0.0;
+    while let Some(value) = next_value(&mut bins_a, &mut bins_b) {
+        if let Some(bin) = bins_a.next_if(|bin| bin.0 == value) {
+            n_a += bin.1;
+        }
+        if let Some(bin) = bins_b.next_if(|bin| bin.0 == value) {
+            n_b += bin.1;
+        }
+        visit(
+            value,
+            n_a / count_a,
+            n_b / count_b,
+            next_value(&mut bins_a, &mut bins_b),
+        );
+    }
+    Ok(true)
+}
+
+fn next_value<A, B>(bins_a: &mut Peekable<A>, bins_b: &mut Peekable<B>) -> Option<f64>
+where
+    A: Iterator<Item = (f64, f64)>,
+    B: Iterator<Item = (f64, f64)>,
+{
+    match (bins_a.peek(), bins_b.peek()) {
+        (Some(bin_a), Some(bin_b)) => Some(f64::min(bin_a.0, bin_b.0)),
+        (Some(bin_a), None) => Some(bin_a.0),
+        (None, Some(bin_b)) => Some(bin_b.0),
+        (None, None) => None,
+    }
+}
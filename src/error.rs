@@ -1,22 +1,105 @@
+use crate::index_mapping::IndexMapping;
 use std::{fmt, io};
 
 #[derive(Debug)]
+#[non_exhaustive]
 pub enum Error {
     InvalidArgument(&'static str),
-    IoError(io::ErrorKind),
+    /// The index mappings of sketches that were to be combined, the one of the sketch combined
+    /// into and the other one.
+    UnmatchedMapping {
+        expected: IndexMapping,
+        actual: IndexMapping,
+    },
+    /// A relative accuracy out of the open interval between 0 and 1.
+    InvalidRelativeAccuracy(f64),
+    /// The marker of a flag that is not part of the encoding.
+    UnknownFlag(u8),
+    /// An encoding that ends before the value starting at `offset`.
+    Truncated {
+        offset: usize,
+    },
+    /// An index that does not fit in the range of the bins.
+    IndexOutOfRange(i64),
+    /// An encoding that exceeds the `DecodeLimits` it is decoded with.
+    LimitExceeded(&'static str),
+    Io(io::Error),
+    /// An invalid encoding, along with the byte offset where it went wrong and the marker of the
+    /// flag being decoded, if any. `DDSketch::decode_strict` rejects more encodings than the
+    /// other decoders do.
+    InvalidEncoding {
+        offset: usize,
+        flag: Option<u8>,
+        reason: &'static str,
+    },
 }
 
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
             Error::InvalidArgument(arg) => write!(f, "Invalid argument: {}", arg),
-            Error::IoError(ref cause) => write!(f, "Io Error: {}", cause),
+            Error::UnmatchedMapping {
+                ref expected,
+                ref actual,
+            } => write!(
+                f,
+                "Unmatched index mapping: expected {:?}, got {:?}",
+                expected, actual
+            ),
+            Error::InvalidRelativeAccuracy(relative_accuracy) => write!(
+                f,
+                "Invalid relative accuracy: {}, it must be between 0 and 1",
+                relative_accuracy
+            ),
+            Error::UnknownFlag(marker) => write!(f, "Unknown flag: {:#04x}", marker),
+            Error::Truncated { offset } => write!(f, "Truncated encoding at byte {}", offset),
+            Error::IndexOutOfRange(index) => write!(f, "Index out of range: {}", index),
+            Error::LimitExceeded(limit) => write!(f, "Decode limit exceeded: {}", limit),
+            Error::Io(ref cause) => write!(f, "Io Error: {}", cause),
+            Error::InvalidEncoding {
+                offset,
+                flag: Some(flag),
+                reason,
+            } => write!(
+                f,
+                "Invalid encoding at byte {}, flag {:#04x}: {}",
+                offset, flag, reason
+            ),
+            Error::InvalidEncoding {
+                offset,
+                flag: None,
+                reason,
+            } => write!(f, "Invalid encoding at byte {}: {}", offset, reason),
+        }
+    }
+}
+
+impl Error {
+    /// Returns the error met while decoding the value at `offset`, an invalid argument being
+    /// turned into an `InvalidEncoding` there.
+    pub(crate) fn at(self, offset: usize, flag: Option<u8>) -> Error {
+        match self {
+            Error::InvalidArgument(reason) => Error::InvalidEncoding {
+                offset,
+                flag,
+                reason,
+            },
+            error => error,
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(cause) => Some(cause),
+            _ => None,
         }
     }
 }
 
 impl From<io::Error> for Error {
     fn from(error: io::Error) -> Self {
-        Error::IoError(error.kind())
+        Error::Io(error)
     }
 }
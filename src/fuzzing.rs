@@ -0,0 +1,60 @@
+//! The variable-length encoding helpers, on byte slices, for the fuzz targets. They are not part
+//! of the API and may change at any time.
+
+use crate::error::Error;
+use crate::input::Input;
+use crate::output::Output;
+use crate::serde;
+
+/// Decodes a value from the start of `bytes`, and returns it along with the number of bytes read.
+fn decode<T>(
+    bytes: &[u8],
+    decode: fn(&mut Input) -> Result<T, Error>,
+) -> Result<(T, usize), Error> {
+    let mut input = Input::wrap(bytes);
+    let value = decode(&mut input)?;
+    Ok((value, input.position()))
+}
+
+fn encode<T>(value: T, encode: fn(&mut Output, T) -> Result<(), Error>) -> Vec<u8> {
+    let mut output = Output::with_capacity(9);
+    // Writing to a vector doesn't fail.
+    let _ = encode(&mut output, value);
+    output.trim()
+}
+
+pub fn decode_unsigned_var_long(bytes: &[u8]) -> Result<(i64, usize), Error> {
+    decode(bytes, serde::decode_unsigned_var_long)
+}
+
+pub fn decode_signed_var_long(bytes: &[u8]) -> Result<(i64, usize), Error> {
+    decode(bytes, serde::decode_signed_var_long)
+}
+
+pub fn decode_var_double(bytes: &[u8]) -> Result<(f64, usize), Error> {
+    decode(bytes, serde::decode_var_double)
+}
+
+pub fn encode_unsigned_var_long(value: i64) -> Vec<u8> {
+    encode(value, serde::encode_unsigned_var_long)
+}
+
+pub fn encode_signed_var_long(value: i64) -> Vec<u8> {
+    encode(value, serde::encode_signed_var_long)
+}
+
+pub fn encode_var_double(value: f64) -> Vec<u8> {
+    encode(value, serde::encode_var_double)
+}
+
+pub fn unsigned_var_long_encoded_length(value: i64) -> usize {
+    serde::unsigned_var_long_encoded_length(value) as usize
+}
+
+pub fn signed_var_long_encoded_length(value: i64) -> usize {
+    serde::signed_var_long_encoded_length(value) as usize
+}
+
+pub fn var_double_encoded_length(value: f64) -> usize {
+    serde::var_double_encoded_length(value) as usize
+}
@@ -3,7 +3,7 @@ use crate::{serde, Error};
 
 use crate::output::Output;
 
-#[derive(PartialEq, Debug)]
+#[derive(Clone, PartialEq, Debug)]
 pub enum IndexMapping {
     LogarithmicMapping(f64, f64, f64, f64),
     CubicallyInterpolatedMapping(f64, f64, f64, f64),
@@ -17,6 +17,7 @@ const CUBICALLY_INTERPOLATED_MAPPING_CORRECTING_FACTOR: f64 =
 const CUBICALLY_INTERPOLATED_MAPPING_BASE: f64 = 2.0;
 const LOGARITHMIC_MAPPING_CORRECTING_FACTOR: f64 = 1.0;
 const LOGARITHMIC_MAPPING_BASE: f64 = std::f64::consts::E;
+const SIGNIFICAND_MASK: i64 = 0x000fffffffffffff;
 
 impl IndexMapping {
     pub fn layout(&self) -> IndexMappingLayout {
@@ -181,12 +182,11 @@ impl IndexMapping {
         self.lower_bound(index) * (1.0 + self.relative_accuracy())
     }
 
-    fn lower_bound(&self, index: i32) -> f64 {
+    pub(crate) fn lower_bound(&self, index: i32) -> f64 {
         self.log_inverse((index as f64 - self.index_offset()) / self.multiplier())
     }
 
-    #[allow(dead_code)]
-    fn upper_bound(&self, index: i32) -> f64 {
+    pub(crate) fn upper_bound(&self, index: i32) -> f64 {
         self.lower_bound(index + 1)
     }
 
@@ -201,7 +201,7 @@ impl IndexMapping {
     }
 
     pub(crate) fn max_indexable_value(&self) -> f64 {
-        f64::max(
+        f64::min(
             f64::powf(
                 2.0,
                 (i32::MAX as f64 - self.index_offset()) / self.multiplier() - 1.0,
@@ -210,6 +210,26 @@ impl IndexMapping {
         )
     }
 
+    /// Returns the mapping that gives the value `factor * v` the index that this mapping gives
+    /// `v`, if the change can be absorbed by the index offset: for any positive factor with the
+    /// logarithmic mapping, and for powers of two only with the cubically interpolated one.
+    pub(crate) fn scaled(&self, factor: f64) -> Option<IndexMapping> {
+        if !factor.is_finite() || factor <= 0.0 {
+            return None;
+        }
+        let is_exact = match self {
+            IndexMapping::LogarithmicMapping(..) => true,
+            IndexMapping::CubicallyInterpolatedMapping(..) => {
+                factor.is_normal() && factor.to_bits() & (SIGNIFICAND_MASK as u64) == 0
+            }
+        };
+        if !is_exact {
+            return None;
+        }
+        let index_offset = self.index_offset() - self.log(factor) * self.multiplier();
+        IndexMapping::with_gamma_offset(self.layout(), self.gamma(), index_offset).ok()
+    }
+
     pub fn encode(&self, output: &mut Output) -> Result<(), Error> {
         self.layout().to_flag().encode(output)?;
         output.write_double_le(self.gamma())?;
@@ -221,23 +241,25 @@ impl IndexMapping {
         index_layout: IndexMappingLayout,
         relative_accuracy: f64,
     ) -> Result<IndexMapping, Error> {
-        if relative_accuracy <= 0.0 || relative_accuracy >= 1.0 {
-            return Err(Error::InvalidArgument(
-                "The relative accuracy must be between 0 and 1.",
-            ));
+        IndexMapping::with_relative_accuracy_offset(index_layout, relative_accuracy, 0.0)
+    }
+
+    pub fn with_relative_accuracy_offset(
+        index_layout: IndexMappingLayout,
+        relative_accuracy: f64,
+        index_offset: f64,
+    ) -> Result<IndexMapping, Error> {
+        if !index_offset.is_finite() {
+            return Err(Error::InvalidArgument("The index offset must be finite."));
+        }
+        if !(relative_accuracy > 0.0 && relative_accuracy < 1.0) {
+            return Err(Error::InvalidRelativeAccuracy(relative_accuracy));
         }
 
         match index_layout {
             IndexMappingLayout::LOG => {
-                if relative_accuracy <= 0.0 || relative_accuracy >= 1.0 {
-                    return Err(Error::InvalidArgument(
-                        "The relative accuracy must be between 0 and 1.",
-                    ));
-                }
-
                 let gamma =
                     calculate_gamma(relative_accuracy, LOGARITHMIC_MAPPING_CORRECTING_FACTOR);
-                let index_offset: f64 = 0.0;
                 let multiplier = LOGARITHMIC_MAPPING_BASE.ln() / (gamma - 1.0).ln_1p();
                 let relative_accuracy = calculate_relative_accuracy(gamma, 1.0);
                 Ok(IndexMapping::LogarithmicMapping(
@@ -253,7 +275,6 @@ impl IndexMapping {
                     relative_accuracy,
                     CUBICALLY_INTERPOLATED_MAPPING_CORRECTING_FACTOR,
                 );
-                let index_offset: f64 = 0.0;
 
                 let multiplier = CUBICALLY_INTERPOLATED_MAPPING_BASE.ln() / (gamma - 1.0).ln_1p();
                 let relative_accuracy = calculate_relative_accuracy(
@@ -267,8 +288,21 @@ impl IndexMapping {
                     relative_accuracy,
                 ))
             }
-            _ => Err(Error::InvalidArgument("Unsupported IndexLayout")),
+            _ => Err(Error::InvalidArgument("Unsupported IndexLayout.")),
+        }
+    }
+
+    /// Creates a mapping as `with_gamma_offset` does, after checking the parameters, which may
+    /// come from an untrusted encoding.
+    pub(crate) fn with_checked_gamma_offset(
+        index_layout: IndexMappingLayout,
+        gamma: f64,
+        index_offset: f64,
+    ) -> Result<IndexMapping, Error> {
+        if !gamma.is_finite() || gamma <= 1.0 || !index_offset.is_finite() {
+            return Err(Error::InvalidArgument("Invalid IndexMapping parameters."));
         }
+        IndexMapping::with_gamma_offset(index_layout, gamma, index_offset)
     }
 
     pub fn with_gamma_offset(
@@ -303,11 +337,14 @@ impl IndexMapping {
                 ))
             }
 
-            _ => Err(Error::InvalidArgument("Unsupported IndexLayout")),
+            _ => Err(Error::InvalidArgument("Unsupported IndexLayout.")),
         }
     }
 }
 
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Clone, Copy, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
 pub enum IndexMappingLayout {
     LOG = 0,
     LogLinear = 1,
@@ -325,7 +362,7 @@ impl IndexMappingLayout {
             2 => Ok(IndexMappingLayout::LogQuadratic),
             3 => Ok(IndexMappingLayout::LogCubic),
             4 => Ok(IndexMappingLayout::LogQuartic),
-            _ => Err(Error::InvalidArgument("Unknown Index Flag.")),
+            _ => Err(Error::UnknownFlag(flag.get_marker())),
         }
     }
 
@@ -381,6 +418,30 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_max_indexable_value() {
+        for layout in [LOG, LogCubic] {
+            for gamma in TEST_GAMMAS {
+                for index_offset in TEST_INDEX_OFFSETS {
+                    let index_mapping =
+                        IndexMapping::with_gamma_offset(layout, gamma, index_offset).unwrap();
+                    let max_indexable_value = index_mapping.max_indexable_value();
+                    assert!(max_indexable_value.is_finite());
+                    let index = index_mapping.log(max_indexable_value) * index_mapping.multiplier()
+                        + index_mapping.index_offset();
+                    assert!(index < i32::MAX as f64);
+                }
+            }
+            // With a fine enough accuracy, the largest values overflow the indexes before the
+            // doubles, and the bound is set by the indexes.
+            let index_mapping = IndexMapping::with_gamma_offset(layout, 1.0 + 1e-8, 0.0).unwrap();
+            let largest_value = f64::MAX / (1.0 + index_mapping.relative_accuracy());
+            assert!(index_mapping.max_indexable_value() < largest_value);
+            let index = index_mapping.log(largest_value) * index_mapping.multiplier();
+            assert!(index > i32::MAX as f64);
+        }
+    }
+
     #[test]
     fn test_cubically_interpolated_mapping_offset() {
         for gamma in TEST_GAMMAS {
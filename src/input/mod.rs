@@ -1,55 +1,190 @@
 use crate::error::Error;
-use std::io::ErrorKind;
+use std::borrow::Cow;
+use std::io::{self, BufRead, ErrorKind, Read};
 
 pub struct Input<'a> {
-    vec: &'a Vec<u8>,
+    source: Source<'a>,
     pos: usize,
-    end: usize,
+}
+
+enum Source<'a> {
+    Slice {
+        vec: &'a [u8],
+        end: usize,
+    },
+    /// The bytes read from a reader by `take`, the first of them being at the position `start`.
+    Buffer {
+        vec: Vec<u8>,
+        start: usize,
+    },
+    Reader(&'a mut dyn BufRead),
 }
 
 impl<'a> Input<'a> {
-    pub fn wrap(vec: &'a Vec<u8>) -> Input {
+    pub fn wrap(vec: &'a [u8]) -> Input<'a> {
         Input {
+            source: Source::Slice {
+                vec,
+                end: vec.len(),
+            },
             pos: 0,
-            end: vec.len(),
-            vec,
         }
     }
 
-    pub(crate) fn has_remaining(&self) -> bool {
-        self.pos < self.end
+    /// Reads from `reader` as the bytes are needed, through its buffer.
+    pub(crate) fn from_reader(reader: &'a mut dyn BufRead) -> Input<'a> {
+        Input {
+            source: Source::Reader(reader),
+            pos: 0,
+        }
     }
 
-    pub(crate) fn read_byte(&mut self) -> Result<u8, Error> {
-        if self.pos >= self.end {
-            return Err(Error::IoError(ErrorKind::UnexpectedEof));
+    /// Returns the number of bytes read so far.
+    pub(crate) fn position(&self) -> usize {
+        self.pos
+    }
+
+    /// Returns the bytes left to read, unless they come from a reader.
+    fn remaining(&self) -> Option<&[u8]> {
+        match &self.source {
+            Source::Slice { vec, end } => Some(&vec[self.pos..*end]),
+            Source::Buffer { vec, start } => Some(&vec[self.pos - start..]),
+            Source::Reader(_) => None,
         }
-        let value = self.vec[self.pos];
-        self.pos += 1;
-        Ok(value)
     }
 
-    fn read_long_le(&mut self) -> Result<u64, Error> {
-        if self.pos + 8 > self.end {
-            return Err(Error::IoError(ErrorKind::UnexpectedEof));
+    pub(crate) fn has_remaining(&mut self) -> bool {
+        if let Some(remaining) = self.remaining() {
+            return !remaining.is_empty();
+        }
+        match &mut self.source {
+            // An error is reported by the next read.
+            Source::Reader(reader) => has_data_left(*reader).unwrap_or(true),
+            _ => unreachable!(),
         }
+    }
 
-        let value = u64::from_le_bytes([
-            self.vec[self.pos],
-            self.vec[self.pos + 1],
-            self.vec[self.pos + 2],
-            self.vec[self.pos + 3],
-            self.vec[self.pos + 4],
-            self.vec[self.pos + 5],
-            self.vec[self.pos + 6],
-            self.vec[self.pos + 7],
-        ]);
-        self.pos += 8;
+    pub(crate) fn read_bytes(&mut self, len: usize) -> Result<Cow<'a, [u8]>, Error> {
+        let value = match &mut self.source {
+            Source::Slice { vec, end } => {
+                if len > *end - self.pos {
+                    return Err(Error::Truncated { offset: self.pos });
+                }
+                let vec: &'a [u8] = vec;
+                Cow::Borrowed(&vec[self.pos..self.pos + len])
+            }
+            Source::Buffer { vec, start } => {
+                let remaining = &vec[self.pos - *start..];
+                if len > remaining.len() {
+                    return Err(Error::Truncated { offset: self.pos });
+                }
+                Cow::Owned(remaining[..len].to_vec())
+            }
+            Source::Reader(reader) => {
+                // The buffer grows with the bytes actually read, whatever the length asked for.
+                let mut value = Vec::new();
+                (&mut **reader)
+                    .take(len as u64)
+                    .read_to_end(&mut value)
+                    .map_err(Error::Io)?;
+                if value.len() < len {
+                    return Err(Error::Truncated { offset: self.pos });
+                }
+                Cow::Owned(value)
+            }
+        };
+        self.pos += len;
         Ok(value)
     }
 
+    /// Returns an input over the next `len` bytes, and skips them.
+    pub(crate) fn take(&mut self, len: usize) -> Result<Input<'a>, Error> {
+        let pos = self.pos;
+        let source = match &mut self.source {
+            Source::Slice { vec, end } => {
+                if len > *end - pos {
+                    return Err(Error::Truncated { offset: pos });
+                }
+                self.pos += len;
+                Source::Slice {
+                    vec,
+                    end: pos + len,
+                }
+            }
+            _ => Source::Buffer {
+                vec: self.read_bytes(len)?.into_owned(),
+                start: pos,
+            },
+        };
+        Ok(Input { source, pos })
+    }
+
+    pub(crate) fn read_byte(&mut self) -> Result<u8, Error> {
+        let mut value = [0];
+        self.read_exact(&mut value)?;
+        Ok(value[0])
+    }
+
+    fn read_exact(&mut self, buf: &mut [u8]) -> Result<(), Error> {
+        if let Some(remaining) = self.remaining() {
+            if buf.len() > remaining.len() {
+                return Err(Error::Truncated { offset: self.pos });
+            }
+            buf.copy_from_slice(&remaining[..buf.len()]);
+        } else if let Source::Reader(reader) = &mut self.source {
+            reader.read_exact(buf).map_err(|error| match error.kind() {
+                ErrorKind::UnexpectedEof => Error::Truncated { offset: self.pos },
+                _ => Error::Io(error),
+            })?;
+        }
+        self.pos += buf.len();
+        Ok(())
+    }
+
+    fn read_long_le(&mut self) -> Result<u64, Error> {
+        let mut value = [0; 8];
+        self.read_exact(&mut value)?;
+        Ok(u64::from_le_bytes(value))
+    }
+
     pub(crate) fn read_double_le(&mut self) -> Result<f64, Error> {
         let value = f64::from_bits(self.read_long_le()?);
         Ok(value)
     }
 }
+
+fn has_data_left(reader: &mut dyn BufRead) -> io::Result<bool> {
+    loop {
+        match reader.fill_buf() {
+            Err(error) if error.kind() == ErrorKind::Interrupted => continue,
+            result => return result.map(|buf| !buf.is_empty()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::BufReader;
+
+    #[test]
+    fn test_reader_read_bytes_and_take() {
+        let bytes = [1, 2, 3, 4, 5, 6];
+        let mut reader = BufReader::with_capacity(2, &bytes[..]);
+        let mut input = Input::from_reader(&mut reader);
+        assert_eq!(&[1, 2, 3][..], &*input.read_bytes(3).unwrap());
+
+        let mut taken = input.take(2).unwrap();
+        assert_eq!(3, taken.position());
+        assert_eq!(4, taken.read_byte().unwrap());
+        assert_eq!(&[5][..], &*taken.read_bytes(1).unwrap());
+        assert!(!taken.has_remaining());
+        assert!(matches!(
+            taken.read_byte(),
+            Err(Error::Truncated { offset: 5 })
+        ));
+
+        assert!(input.has_remaining());
+        assert!(matches!(input.take(2), Err(Error::Truncated { offset: 5 })));
+    }
+}
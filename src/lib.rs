@@ -49,23 +49,55 @@ Encode to bytes:
 Decode from bytes:
 ```rust
     use self::sketches_rust::{DDSketch};
-    let mut input = vec![
+    let input = vec![
         2, 42, 120, 57, 5, 47, 167, 240, 63, 0, 0, 0, 0, 0, 0, 0, 0, 13, 50, 130, 1, 2, 136, 32, 0,
         3, 0, 0, 0, 3, 0, 2, 0, 0, 3, 3, 2, 2, 3, 3, 2, 0, 0, 0, 0, 2, 0, 2, 2, 2, 4, 4, 132, 64,
         0, 4, 2, 0, 2, 2, 3, 132, 64, 4, 132, 64, 4, 2, 2, 0, 6, 4, 6, 132, 64, 2, 6,
     ];
-    let mut d = DDSketch::decode(&input).unwrap();
+    let d = DDSketch::decode(&input).unwrap();
     assert_eq!(d.get_count(), 100.0);
 ```
  */
 
+mod builder;
+mod clock;
+mod concurrent;
+mod decayed;
+mod distance;
 mod error;
+#[cfg(feature = "fuzzing")]
+#[doc(hidden)]
+pub mod fuzzing;
 mod index_mapping;
 mod input;
+mod limits;
 mod output;
+mod proto;
 mod serde;
+#[cfg(feature = "serde")]
+mod serialization;
 mod sketch;
+mod sketch_map;
+mod snapshot;
 mod store;
+mod strict;
+mod summary;
+mod view;
+mod windowed;
 
+pub use self::builder::DDSketchBuilder;
+pub use self::clock::{Clock, ManualClock, SystemClock};
+pub use self::concurrent::ConcurrentDDSketch;
+pub use self::decayed::DecayedDDSketch;
+pub use self::distance::{
+    kolmogorov_smirnov_distance, quantile_relative_differences, wasserstein_distance,
+};
 pub use self::error::Error;
-pub use self::sketch::DDSketch;
+pub use self::index_mapping::{IndexMapping, IndexMappingLayout};
+pub use self::limits::DecodeLimits;
+pub use self::sketch::{DDSketch, OutOfRangePolicy};
+pub use self::sketch_map::{KeyOverflowPolicy, SketchMap};
+pub use self::snapshot::DDSketchSnapshot;
+pub use self::store::{AtomicDenseStore, StoreType};
+pub use self::view::EncodedSketchView;
+pub use self::windowed::WindowedDDSketch;
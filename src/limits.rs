@@ -0,0 +1,68 @@
+use crate::error::Error;
+
+/// Bounds on the work that decoding a sketch may take, which keep the decoding of arbitrary bytes
+/// bounded in time and memory.
+///
+/// The default limits accept the sketches of any relative accuracy down to 1e-3 over the whole
+/// range of the values, and the sketches of finer accuracies whose values span fewer bins. A
+/// store takes 8 bytes per index of its span. The index span also bounds the bins allocated by
+/// `AtomicDenseStore::with_value_range_and_limits`.
+///
+/// ```rust
+///     use sketches_rust::{DDSketch, DecodeLimits};
+///     let mut d = DDSketch::unbounded_dense(1e-4).unwrap();
+///     d.accept(1e-300);
+///     d.accept(1e300);
+///     let encoded = d.encode().unwrap();
+///     assert!(DDSketch::decode(&encoded).is_err());
+///     let limits = DecodeLimits::new().max_index_span(1 << 23);
+///     assert!(DDSketch::decode_with_limits(&encoded, limits).is_ok());
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DecodeLimits {
+    max_num_bins: usize,
+    max_index_span: usize,
+}
+
+impl DecodeLimits {
+    pub fn new() -> DecodeLimits {
+        DecodeLimits {
+            max_num_bins: 1 << 24,
+            max_index_span: 1 << 22,
+        }
+    }
+
+    /// Sets the maximum number of bins of an encoding, over all its blocks, the empty bins of
+    /// the contiguous counts included.
+    pub fn max_num_bins(mut self, max_num_bins: usize) -> DecodeLimits {
+        self.max_num_bins = max_num_bins;
+        self
+    }
+
+    /// Sets the maximum difference between the highest and the lowest index of the decoded bins
+    /// of a store. The bins of collapsing stores, which are bounded anyway, are not limited.
+    pub fn max_index_span(mut self, max_index_span: usize) -> DecodeLimits {
+        self.max_index_span = max_index_span;
+        self
+    }
+
+    pub(crate) fn check_num_bins(&self, num_bins: usize) -> Result<(), Error> {
+        if num_bins > self.max_num_bins {
+            return Err(Error::LimitExceeded("Too many bins."));
+        }
+        Ok(())
+    }
+
+    pub(crate) fn check_index_span(&self, min_index: i32, max_index: i32) -> Result<(), Error> {
+        if max_index as i64 - min_index as i64 > self.max_index_span as i64 {
+            return Err(Error::LimitExceeded("The index span is too wide."));
+        }
+        Ok(())
+    }
+}
+
+impl Default for DecodeLimits {
+    fn default() -> Self {
+        DecodeLimits::new()
+    }
+}
@@ -1,22 +1,49 @@
 use crate::error::Error;
+use std::io::Write;
 
-pub struct Output {
-    vec: Vec<u8>,
+pub struct Output<'a> {
+    sink: Sink<'a>,
 }
 
-impl Output {
-    pub fn with_capacity(capacity: usize) -> Output {
+enum Sink<'a> {
+    Vec(Vec<u8>),
+    Writer(&'a mut dyn Write),
+}
+
+impl Output<'static> {
+    pub fn with_capacity(capacity: usize) -> Output<'static> {
+        Output {
+            sink: Sink::Vec(Vec::with_capacity(capacity)),
+        }
+    }
+}
+
+impl<'a> Output<'a> {
+    /// Writes to `writer` as the bytes are produced, a few at a time, so the writer is better
+    /// buffered.
+    pub(crate) fn to_writer(writer: &'a mut dyn Write) -> Output<'a> {
         Output {
-            vec: Vec::with_capacity(capacity),
+            sink: Sink::Writer(writer),
         }
     }
 
+    /// Returns the bytes written, none of them being kept when writing to a writer.
     pub fn trim(self) -> Vec<u8> {
-        self.vec
+        match self.sink {
+            Sink::Vec(vec) => vec,
+            Sink::Writer(_) => Vec::new(),
+        }
     }
 
     pub(crate) fn write_byte(&mut self, value: u8) -> Result<(), Error> {
-        self.vec.push(value);
+        self.write_bytes(&[value])
+    }
+
+    pub(crate) fn write_bytes(&mut self, value: &[u8]) -> Result<(), Error> {
+        match &mut self.sink {
+            Sink::Vec(vec) => vec.extend_from_slice(value),
+            Sink::Writer(writer) => writer.write_all(value)?,
+        }
         Ok(())
     }
 
@@ -29,8 +56,6 @@ impl Output {
     // }
 
     pub(crate) fn write_double_le(&mut self, value: f64) -> Result<(), Error> {
-        let bytes = f64::to_le_bytes(value);
-        self.vec.extend(bytes);
-        Ok(())
+        self.write_bytes(&f64::to_le_bytes(value))
     }
 }
@@ -0,0 +1,320 @@
+//! Encoding of the `DDSketch` protobuf message of DataDog's `ddsketch.proto`, which the Go and
+//! Python implementations exchange:
+//!
+//! ```proto
+//! message DDSketch {
+//!   IndexMapping mapping = 1;
+//!   Store positiveValues = 2;
+//!   Store negativeValues = 3;
+//!   double zeroCount = 4;
+//! }
+//!
+//! message IndexMapping {
+//!   double gamma = 1;
+//!   double indexOffset = 2;
+//!   enum Interpolation { NONE = 0; LINEAR = 1; QUADRATIC = 2; CUBIC = 3; }
+//!   Interpolation interpolation = 3;
+//! }
+//!
+//! message Store {
+//!   map<sint32, double> binCounts = 1;
+//!   repeated double contiguousBinCounts = 2 [packed = true];
+//!   sint32 contiguousBinIndexOffset = 3;
+//! }
+//! ```
+
+use crate::error::Error;
+use crate::index_mapping::{IndexMapping, IndexMappingLayout};
+use crate::input::Input;
+use crate::limits::DecodeLimits;
+use crate::output::Output;
+use crate::sketch::DDSketch;
+use crate::store::Store;
+
+const WIRE_TYPE_VARINT: u8 = 0;
+const WIRE_TYPE_FIXED64: u8 = 1;
+const WIRE_TYPE_LENGTH_DELIMITED: u8 = 2;
+const WIRE_TYPE_FIXED32: u8 = 5;
+
+impl DDSketch {
+    /// Encodes the sketch as a `DDSketch` protobuf message. The mapping must be logarithmic or
+    /// cubically interpolated, and the exact summary statistics, the zero threshold and the
+    /// value counters are left out as the message has no room for them.
+    ///
+    /// ```rust
+    ///     use sketches_rust::DDSketch;
+    ///     let mut d = DDSketch::unbounded_dense(2e-2).unwrap();
+    ///     d.accept(1.0);
+    ///     let decoded = DDSketch::from_proto_bytes(&d.to_proto_bytes().unwrap()).unwrap();
+    ///     assert_eq!(decoded.get_count(), 1.0);
+    /// ```
+    pub fn to_proto_bytes(&self) -> Result<Vec<u8>, Error> {
+        let mut mapping = Output::with_capacity(32);
+        encode_index_mapping(&mut mapping, self.get_index_mapping())?;
+        let mut positive_values = Output::with_capacity(64);
+        encode_store(&mut positive_values, self.get_positive_value_store())?;
+        let mut negative_values = Output::with_capacity(64);
+        encode_store(&mut negative_values, self.get_negative_value_store())?;
+
+        let mut output = Output::with_capacity(64);
+        encode_length_delimited(&mut output, 1, &mapping.trim())?;
+        encode_length_delimited(&mut output, 2, &positive_values.trim())?;
+        encode_length_delimited(&mut output, 3, &negative_values.trim())?;
+        encode_double(&mut output, 4, self.get_zero_count())?;
+        Ok(output.trim())
+    }
+
+    /// Decodes a `DDSketch` protobuf message into a sketch with unbounded stores.
+    pub fn from_proto_bytes(bytes: &[u8]) -> Result<DDSketch, Error> {
+        DDSketch::from_proto_bytes_with_limits(bytes, DecodeLimits::default())
+    }
+
+    /// Decodes a `DDSketch` protobuf message like `from_proto_bytes`, failing with
+    /// `Error::LimitExceeded` if it exceeds `limits`.
+    pub fn from_proto_bytes_with_limits(
+        bytes: &[u8],
+        limits: DecodeLimits,
+    ) -> Result<DDSketch, Error> {
+        let mut input = Input::wrap(bytes);
+        let mut index_mapping = None;
+        let mut positive_bins = Vec::new();
+        let mut negative_bins = Vec::new();
+        let mut zero_count = 0.0;
+        while input.has_remaining() {
+            let (field, wire_type) = decode_tag(&mut input)?;
+            match (field, wire_type) {
+                (1, WIRE_TYPE_LENGTH_DELIMITED) => {
+                    let offset = input.position();
+                    index_mapping = Some(
+                        decode_index_mapping(&mut take_message(&mut input)?)
+                            .map_err(|error| error.at(offset, None))?,
+                    );
+                }
+                (2, WIRE_TYPE_LENGTH_DELIMITED) => {
+                    decode_store(&mut take_message(&mut input)?, &mut positive_bins)?;
+                }
+                (3, WIRE_TYPE_LENGTH_DELIMITED) => {
+                    decode_store(&mut take_message(&mut input)?, &mut negative_bins)?;
+                }
+                (4, WIRE_TYPE_FIXED64) => zero_count = read_count(&mut input)?,
+                _ => skip_field(&mut input, wire_type)?,
+            }
+        }
+
+        match index_mapping {
+            Some(index_mapping) => DDSketch::from_encoded_parts(
+                index_mapping,
+                0.0,
+                zero_count,
+                &negative_bins,
+                &positive_bins,
+                None,
+                &limits,
+            )
+            .map_err(|error| error.at(bytes.len(), None)),
+            None => Err(invalid_encoding(bytes.len(), "No IndexMapping decoded.")),
+        }
+    }
+}
+
+fn encode_index_mapping(output: &mut Output, index_mapping: &IndexMapping) -> Result<(), Error> {
+    let interpolation = match index_mapping.layout() {
+        IndexMappingLayout::LOG => 0,
+        IndexMappingLayout::LogLinear => 1,
+        IndexMappingLayout::LogQuadratic => 2,
+        IndexMappingLayout::LogCubic => 3,
+        IndexMappingLayout::LogQuartic => {
+            return Err(Error::InvalidArgument("Unsupported IndexLayout."));
+        }
+    };
+    encode_double(output, 1, index_mapping.gamma())?;
+    encode_double(output, 2, index_mapping.index_offset())?;
+    if interpolation != 0 {
+        encode_tag(output, 3, WIRE_TYPE_VARINT)?;
+        encode_varint(output, interpolation)?;
+    }
+    Ok(())
+}
+
+fn decode_index_mapping(input: &mut Input) -> Result<IndexMapping, Error> {
+    let mut gamma = 0.0;
+    let mut index_offset = 0.0;
+    let mut layout = IndexMappingLayout::LOG;
+    while input.has_remaining() {
+        let (field, wire_type) = decode_tag(input)?;
+        match (field, wire_type) {
+            (1, WIRE_TYPE_FIXED64) => gamma = input.read_double_le()?,
+            (2, WIRE_TYPE_FIXED64) => index_offset = input.read_double_le()?,
+            (3, WIRE_TYPE_VARINT) => {
+                let offset = input.position();
+                layout = match decode_varint(input)? {
+                    0 => IndexMappingLayout::LOG,
+                    1 => IndexMappingLayout::LogLinear,
+                    2 => IndexMappingLayout::LogQuadratic,
+                    3 => IndexMappingLayout::LogCubic,
+                    _ => return Err(invalid_encoding(offset, "Unknown interpolation.")),
+                }
+            }
+            _ => skip_field(input, wire_type)?,
+        }
+    }
+    IndexMapping::with_checked_gamma_offset(layout, gamma, index_offset)
+}
+
+/// Encodes the bins as contiguous counts, as the dense stores of the other implementations do.
+fn encode_store(output: &mut Output, store: &dyn Store) -> Result<(), Error> {
+    if store.is_empty() {
+        return Ok(());
+    }
+    let min_index = store.get_min_index();
+    let max_index = store.get_max_index();
+    let offset = store.get_offset();
+    let num_bins = (max_index as i64 - min_index as i64 + 1) as u64;
+    encode_tag(output, 2, WIRE_TYPE_LENGTH_DELIMITED)?;
+    encode_varint(output, 8 * num_bins)?;
+    for index in min_index..=max_index {
+        output.write_double_le(store.get_count(index - offset))?;
+    }
+    if min_index != 0 {
+        encode_tag(output, 3, WIRE_TYPE_VARINT)?;
+        encode_varint(output, zigzag(min_index))?;
+    }
+    Ok(())
+}
+
+/// Decodes the bins of a store, both sparse and contiguous, into `bins`.
+fn decode_store(input: &mut Input, bins: &mut Vec<(i32, f64)>) -> Result<(), Error> {
+    let mut contiguous_counts = Vec::new();
+    let mut contiguous_offset = 0;
+    while input.has_remaining() {
+        let (field, wire_type) = decode_tag(input)?;
+        match (field, wire_type) {
+            (1, WIRE_TYPE_LENGTH_DELIMITED) => {
+                let mut entry = take_message(input)?;
+                let mut index = 0;
+                let mut count = 0.0;
+                while entry.has_remaining() {
+                    let (field, wire_type) = decode_tag(&mut entry)?;
+                    match (field, wire_type) {
+                        (1, WIRE_TYPE_VARINT) => index = decode_sint32(&mut entry)?,
+                        (2, WIRE_TYPE_FIXED64) => count = read_count(&mut entry)?,
+                        _ => skip_field(&mut entry, wire_type)?,
+                    }
+                }
+                bins.push((index, count));
+            }
+            (2, WIRE_TYPE_LENGTH_DELIMITED) => {
+                let mut packed = take_message(input)?;
+                while packed.has_remaining() {
+                    contiguous_counts.push(read_count(&mut packed)?);
+                }
+            }
+            // A non-packed repeated field, which parsers must accept too.
+            (2, WIRE_TYPE_FIXED64) => contiguous_counts.push(read_count(input)?),
+            (3, WIRE_TYPE_VARINT) => contiguous_offset = decode_sint32(input)?,
+            _ => skip_field(input, wire_type)?,
+        }
+    }
+    for (i, count) in contiguous_counts.into_iter().enumerate() {
+        let index = i32::try_from(i)
+            .ok()
+            .and_then(|i| contiguous_offset.checked_add(i))
+            .ok_or(Error::IndexOutOfRange(contiguous_offset as i64 + i as i64))?;
+        bins.push((index, count));
+    }
+    Ok(())
+}
+
+fn encode_tag(output: &mut Output, field: u32, wire_type: u8) -> Result<(), Error> {
+    encode_varint(output, (field as u64) << 3 | wire_type as u64)
+}
+
+fn decode_tag(input: &mut Input) -> Result<(u64, u8), Error> {
+    let tag = decode_varint(input)?;
+    Ok((tag >> 3, (tag & 0x7) as u8))
+}
+
+/// Writes a double field, leaving it out if it has the default value, as proto3 does.
+fn encode_double(output: &mut Output, field: u32, value: f64) -> Result<(), Error> {
+    if value == 0.0 && value.is_sign_positive() {
+        return Ok(());
+    }
+    encode_tag(output, field, WIRE_TYPE_FIXED64)?;
+    output.write_double_le(value)
+}
+
+fn encode_length_delimited(output: &mut Output, field: u32, bytes: &[u8]) -> Result<(), Error> {
+    encode_tag(output, field, WIRE_TYPE_LENGTH_DELIMITED)?;
+    encode_varint(output, bytes.len() as u64)?;
+    output.write_bytes(bytes)
+}
+
+fn take_message<'a>(input: &mut Input<'a>) -> Result<Input<'a>, Error> {
+    let offset = input.position();
+    let len = usize::try_from(decode_varint(input)?)
+        .map_err(|_| invalid_encoding(offset, "Invalid length."))?;
+    input.take(len)
+}
+
+/// Reads a count, which must be finite and non-negative.
+fn read_count(input: &mut Input) -> Result<f64, Error> {
+    let offset = input.position();
+    let count = input.read_double_le()?;
+    if !count.is_finite() || count < 0.0 {
+        return Err(invalid_encoding(
+            offset,
+            "The counts must be finite and non-negative.",
+        ));
+    }
+    Ok(count)
+}
+
+fn invalid_encoding(offset: usize, reason: &'static str) -> Error {
+    Error::InvalidEncoding {
+        offset,
+        flag: None,
+        reason,
+    }
+}
+
+fn skip_field(input: &mut Input, wire_type: u8) -> Result<(), Error> {
+    match wire_type {
+        WIRE_TYPE_VARINT => decode_varint(input).map(|_| ()),
+        WIRE_TYPE_FIXED64 => input.read_bytes(8).map(|_| ()),
+        WIRE_TYPE_LENGTH_DELIMITED => take_message(input).map(|_| ()),
+        WIRE_TYPE_FIXED32 => input.read_bytes(4).map(|_| ()),
+        _ => Err(invalid_encoding(input.position(), "Unknown wire type.")),
+    }
+}
+
+/// Protobuf varints, unlike the ones of `serde`, use 7 bits of all their bytes, up to 10 of
+/// them.
+fn encode_varint(output: &mut Output, mut value: u64) -> Result<(), Error> {
+    while value >= 0x80 {
+        output.write_byte((value as u8 & 0x7F) | 0x80)?;
+        value >>= 7;
+    }
+    output.write_byte(value as u8)
+}
+
+fn decode_varint(input: &mut Input) -> Result<u64, Error> {
+    let offset = input.position();
+    let mut value = 0;
+    for shift in (0..64).step_by(7) {
+        let byte = input.read_byte()?;
+        value |= ((byte & 0x7F) as u64) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err(invalid_encoding(offset, "Varint is too long."))
+}
+
+fn zigzag(value: i32) -> u64 {
+    ((value << 1) ^ (value >> 31)) as u32 as u64
+}
+
+fn decode_sint32(input: &mut Input) -> Result<i32, Error> {
+    let value = decode_varint(input)? as u32;
+    Ok(((value >> 1) as i32) ^ -((value & 1) as i32))
+}
@@ -60,14 +60,14 @@ pub fn decode_var_double(input: &mut Input) -> Result<f64, Error> {
 pub fn i64_to_i32_exact(value: i64) -> Result<i32, Error> {
     let v = value as i32;
     if value != v as i64 {
-        return Err(Error::InvalidArgument("Value is not valid i32."));
+        return Err(Error::IndexOutOfRange(value));
     }
     Ok(v)
 }
 
 pub fn i32_to_usize_exact(value: i32) -> Result<usize, Error> {
     if value < 0 {
-        return Err(Error::InvalidArgument("Value should be grate than 0."));
+        return Err(Error::InvalidArgument("The value must be non-negative."));
     }
     Ok(value as usize)
 }
@@ -93,7 +93,7 @@ fn zig_zag_decode(value: i64) -> i64 {
 }
 
 fn var_bits_to_double(bits: i64) -> f64 {
-    f64::from_bits((i64::rotate_right(bits, 6) + f64::to_bits(1.0) as i64) as u64) - 1.0
+    f64::from_bits(i64::rotate_right(bits, 6).wrapping_add(f64::to_bits(1.0) as i64) as u64) - 1.0
 }
 
 pub fn ignore_exact_summary_statistic_flags(input: &mut Input, flag: Flag) -> Result<(), Error> {
@@ -104,7 +104,7 @@ pub fn ignore_exact_summary_statistic_flags(input: &mut Input, flag: Flag) -> Re
         input.read_double_le()?;
         Ok(())
     } else {
-        Err(Error::InvalidArgument("Unknown Flag."))
+        Err(Error::UnknownFlag(flag.get_marker()))
     }
 }
 
@@ -125,7 +125,7 @@ pub fn encode_var_double(output: &mut Output, value: f64) -> Result<(), Error> {
 
 fn double_to_var_bits(value: f64) -> u64 {
     i64::rotate_left(
-        f64::to_bits(value + 1.0) as i64 - f64::to_bits(1.0) as i64,
+        (f64::to_bits(value + 1.0) as i64).wrapping_sub(f64::to_bits(1.0) as i64),
         VAR_DOUBLE_ROTATE_DISTANCE,
     ) as u64
 }
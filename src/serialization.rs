@@ -0,0 +1,312 @@
+//! Implementations of the `Serialize` and `Deserialize` traits of serde, behind the `serde`
+//! feature.
+//!
+//! Compact formats get the binary encoding of `DDSketch::encode`, so that what they hold can
+//! also be read by the other DDSketch implementations. Human-readable formats get a structured
+//! form, made of the parameters of the mapping and of the non-empty bins of each store.
+//!
+//! Both forms only hold what the binary encoding holds. A deserialized sketch therefore has
+//! unbounded dense stores, whatever the stores of the serialized one, the default
+//! `OutOfRangePolicy`, and no exact summary statistics: its sum and extremes are estimated
+//! from the bins. Merging the deserialized sketch into a new sketch of the builder of the
+//! serialized one brings back its stores and policy, but the exact summary statistics are lost.
+//!
+//! `AtomicDenseStore`, which has no binary encoding, gets a structured form in both cases, made
+//! of its mapping, its index range, its non-empty bins and its counts.
+
+use crate::error::Error;
+use crate::index_mapping::{IndexMapping, IndexMappingLayout};
+use crate::input::Input;
+use crate::limits::DecodeLimits;
+use crate::output::Output;
+use crate::sketch::{DDSketch, Flag, FlagType};
+use crate::store::AtomicDenseStore;
+use ::serde::de::{self, Deserializer, Visitor};
+use ::serde::ser::Serializer;
+use ::serde::{Deserialize, Serialize};
+use std::fmt;
+
+#[derive(Serialize, Deserialize)]
+struct IndexMappingRepr {
+    layout: IndexMappingLayout,
+    gamma: f64,
+    index_offset: f64,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ValueCountersRepr {
+    dropped: f64,
+    nan: f64,
+    infinite: f64,
+}
+
+#[derive(Serialize, Deserialize)]
+struct DDSketchRepr {
+    mapping: IndexMappingRepr,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    zero_threshold: Option<f64>,
+    #[serde(default)]
+    zero_count: f64,
+    #[serde(default)]
+    negative_bins: Vec<(i32, f64)>,
+    #[serde(default)]
+    positive_bins: Vec<(i32, f64)>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    value_counters: Option<ValueCountersRepr>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct AtomicDenseStoreRepr {
+    mapping: IndexMapping,
+    min_index: i32,
+    max_index: i32,
+    #[serde(default)]
+    bins: Vec<(i32, u64)>,
+    #[serde(default)]
+    zero_count: u64,
+    #[serde(default)]
+    dropped_count: u64,
+    #[serde(default)]
+    nan_count: u64,
+    #[serde(default)]
+    infinite_count: u64,
+}
+
+impl From<&IndexMapping> for IndexMappingRepr {
+    fn from(index_mapping: &IndexMapping) -> Self {
+        IndexMappingRepr {
+            layout: index_mapping.layout(),
+            gamma: index_mapping.gamma(),
+            index_offset: index_mapping.index_offset(),
+        }
+    }
+}
+
+impl TryFrom<IndexMappingRepr> for IndexMapping {
+    type Error = Error;
+
+    fn try_from(repr: IndexMappingRepr) -> Result<Self, Error> {
+        IndexMapping::with_checked_gamma_offset(repr.layout, repr.gamma, repr.index_offset)
+    }
+}
+
+impl From<&DDSketch> for DDSketchRepr {
+    fn from(sketch: &DDSketch) -> Self {
+        DDSketchRepr {
+            mapping: sketch.get_index_mapping().into(),
+            zero_threshold: sketch.get_encoded_zero_threshold(),
+            zero_count: sketch.get_zero_count(),
+            negative_bins: sketch.get_negative_value_store().get_ascending_stream(),
+            positive_bins: sketch.get_positive_value_store().get_ascending_stream(),
+            value_counters: sketch
+                .get_encoded_value_counters()
+                .map(|(dropped, nan, infinite)| ValueCountersRepr {
+                    dropped,
+                    nan,
+                    infinite,
+                }),
+        }
+    }
+}
+
+impl TryFrom<DDSketchRepr> for DDSketch {
+    type Error = Error;
+
+    fn try_from(repr: DDSketchRepr) -> Result<Self, Error> {
+        DDSketch::from_encoded_parts(
+            repr.mapping.try_into()?,
+            repr.zero_threshold.unwrap_or(0.0),
+            repr.zero_count,
+            &repr.negative_bins,
+            &repr.positive_bins,
+            repr.value_counters
+                .map(|counters| (counters.dropped, counters.nan, counters.infinite)),
+            &DecodeLimits::default(),
+        )
+    }
+}
+
+impl From<&AtomicDenseStore> for AtomicDenseStoreRepr {
+    fn from(store: &AtomicDenseStore) -> Self {
+        let (dropped_count, nan_count, infinite_count) = store.get_dropped_counts();
+        AtomicDenseStoreRepr {
+            mapping: store.get_index_mapping().clone(),
+            min_index: store.get_min_index(),
+            max_index: store.get_max_index(),
+            bins: store.get_bins(),
+            zero_count: store.get_zero_count(),
+            dropped_count,
+            nan_count,
+            infinite_count,
+        }
+    }
+}
+
+impl TryFrom<AtomicDenseStoreRepr> for AtomicDenseStore {
+    type Error = Error;
+
+    fn try_from(repr: AtomicDenseStoreRepr) -> Result<Self, Error> {
+        AtomicDenseStore::with_bins(
+            repr.mapping,
+            repr.min_index,
+            repr.max_index,
+            &repr.bins,
+            repr.zero_count,
+            (repr.dropped_count, repr.nan_count, repr.infinite_count),
+        )
+    }
+}
+
+fn decode_index_mapping(bytes: &[u8]) -> Result<IndexMapping, Error> {
+    let mut input = Input::wrap(bytes);
+    let flag = Flag::decode(&mut input)?;
+    if !matches!(flag.get_type()?, FlagType::IndexMapping) {
+        return Err(Error::InvalidEncoding {
+            offset: 0,
+            flag: Some(flag.get_marker()),
+            reason: "No IndexMapping decoded.",
+        });
+    }
+    let layout = IndexMappingLayout::of_flag(&flag)?;
+    let gamma = input.read_double_le()?;
+    let index_offset = input.read_double_le()?;
+    if input.has_remaining() {
+        return Err(Error::InvalidEncoding {
+            offset: input.position(),
+            flag: None,
+            reason: "Trailing bytes after the IndexMapping.",
+        });
+    }
+    IndexMapping::with_checked_gamma_offset(layout, gamma, index_offset)
+        .map_err(|error| error.at(1, Some(flag.get_marker())))
+}
+
+impl Serialize for IndexMapping {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            IndexMappingRepr::from(self).serialize(serializer)
+        } else {
+            let mut output = Output::with_capacity(17);
+            self.encode(&mut output)
+                .map_err(::serde::ser::Error::custom)?;
+            serializer.serialize_bytes(&output.trim())
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for IndexMapping {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        if deserializer.is_human_readable() {
+            IndexMappingRepr::deserialize(deserializer)?
+                .try_into()
+                .map_err(de::Error::custom)
+        } else {
+            let bytes = deserializer.deserialize_bytes(BytesVisitor)?;
+            decode_index_mapping(&bytes).map_err(de::Error::custom)
+        }
+    }
+}
+
+impl Serialize for DDSketch {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            DDSketchRepr::from(self).serialize(serializer)
+        } else {
+            let bytes = self.encode().map_err(::serde::ser::Error::custom)?;
+            serializer.serialize_bytes(&bytes)
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for DDSketch {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        if deserializer.is_human_readable() {
+            DDSketchRepr::deserialize(deserializer)?
+                .try_into()
+                .map_err(de::Error::custom)
+        } else {
+            let bytes = deserializer.deserialize_bytes(BytesVisitor)?;
+            DDSketch::decode(&bytes).map_err(de::Error::custom)
+        }
+    }
+}
+
+impl Serialize for AtomicDenseStore {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        AtomicDenseStoreRepr::from(self).serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for AtomicDenseStore {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        AtomicDenseStoreRepr::deserialize(deserializer)?
+            .try_into()
+            .map_err(de::Error::custom)
+    }
+}
+
+const MAX_PREALLOCATED_BYTES: usize = 4096;
+
+/// Reads a byte buffer, whether the format hands it over as bytes or as a sequence.
+struct BytesVisitor;
+
+impl<'de> Visitor<'de> for BytesVisitor {
+    type Value = Vec<u8>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("an encoded sketch")
+    }
+
+    fn visit_bytes<E: de::Error>(self, bytes: &[u8]) -> Result<Vec<u8>, E> {
+        Ok(bytes.to_vec())
+    }
+
+    fn visit_byte_buf<E: de::Error>(self, bytes: Vec<u8>) -> Result<Vec<u8>, E> {
+        Ok(bytes)
+    }
+
+    fn visit_seq<A: de::SeqAccess<'de>>(self, mut seq: A) -> Result<Vec<u8>, A::Error> {
+        // The hint comes from the input, it must not decide how much memory is allocated.
+        let mut bytes =
+            Vec::with_capacity(seq.size_hint().unwrap_or(0).min(MAX_PREALLOCATED_BYTES));
+        while let Some(byte) = seq.next_element()? {
+            bytes.push(byte);
+        }
+        Ok(bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A sequence whose length hint is much larger than what it holds.
+    struct OverHintedSeq(u8);
+
+    impl<'de> de::SeqAccess<'de> for OverHintedSeq {
+        type Error = de::value::Error;
+
+        fn next_element_seed<T: de::DeserializeSeed<'de>>(
+            &mut self,
+            seed: T,
+        ) -> Result<Option<T::Value>, Self::Error> {
+            if self.0 == 0 {
+                return Ok(None);
+            }
+            self.0 -= 1;
+            seed.deserialize(de::value::U8Deserializer::new(self.0))
+                .map(Some)
+        }
+
+        fn size_hint(&self) -> Option<usize> {
+            Some(usize::MAX)
+        }
+    }
+
+    #[test]
+    fn test_bytes_visitor_bounds_the_preallocation() {
+        let bytes = BytesVisitor.visit_seq(OverHintedSeq(3)).unwrap();
+        assert_eq!(vec![2, 1, 0], bytes);
+        assert!(bytes.capacity() <= MAX_PREALLOCATED_BYTES);
+    }
+}
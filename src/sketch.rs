@@ -1,21 +1,59 @@
+use crate::builder::DDSketchBuilder;
 use crate::error::Error;
-use crate::index_mapping::IndexMappingLayout::{LogCubic, LOG};
+use crate::index_mapping::IndexMappingLayout::LOG;
 use crate::index_mapping::{IndexMapping, IndexMappingLayout};
 use crate::input::Input;
+use crate::limits::DecodeLimits;
 use crate::output::Output;
 use crate::serde;
-use crate::store::{
-    BinEncodingMode, CollapsingHighestDenseStore, CollapsingLowestDenseStore, Store,
-    UnboundedSizeDenseStore,
-};
+use crate::snapshot::DDSketchSnapshot;
+use crate::store::{self, BinEncodingMode, Store, StoreType, UnboundedSizeDenseStore};
+use crate::summary::SummaryStatistics;
+use std::io::{BufReader, Read, Write};
 
 pub struct DDSketch {
     index_mapping: IndexMapping,
+    zero_threshold: f64,
     min_indexed_value: f64,
     max_indexed_value: f64,
     negative_value_store: Box<dyn Store>,
     positive_value_store: Box<dyn Store>,
     zero_count: f64,
+    out_of_range_policy: OutOfRangePolicy,
+    summary_statistics: Option<SummaryStatistics>,
+    value_counters: ValueCounters,
+    extensions: Extensions,
+}
+
+/// What a sketch does with values whose magnitude exceeds the largest indexable value,
+/// infinities included.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum OutOfRangePolicy {
+    /// Ignore the value.
+    #[default]
+    Drop,
+    /// Record the value in the extreme bin of its sign.
+    Clamp,
+    /// Ignore the value, and have `try_accept` return an error.
+    Error,
+}
+
+/// The features that are written into the encoding although other implementations don't know
+/// about them, and fail to decode the sketches holding them, or may not decode them.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub(crate) struct Extensions {
+    pub(crate) zero_threshold: bool,
+    pub(crate) value_counters: bool,
+    /// Whether the bins of a store may be split into several blocks, see `store::encoding`.
+    pub(crate) compact_bins: bool,
+}
+
+/// The total weights of the values that could not be recorded as they are.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub(crate) struct ValueCounters {
+    dropped: f64,
+    nan: f64,
+    infinite: f64,
 }
 
 #[derive(PartialEq)]
@@ -23,6 +61,7 @@ pub struct Flag {
     marker: u8,
 }
 
+#[derive(Clone, Copy)]
 pub enum FlagType {
     SketchFeatures = 0b00,
     PositiveStore = 0b01,
@@ -35,26 +74,190 @@ impl DDSketch {
         self.accept_with_count(value, 1.0);
     }
 
+    /// Records `value` with the weight `count`. The values that can't be recorded are counted
+    /// instead, see `get_dropped_count`.
     pub fn accept_with_count(&mut self, value: f64, count: f64) {
-        if count < 0.0 {
-            return;
+        let _ = self.try_accept_with_count(value, count);
+    }
+
+    pub fn try_accept(&mut self, value: f64) -> Result<(), Error> {
+        self.try_accept_with_count(value, 1.0)
+    }
+
+    /// Records `value` with the weight `count`, and returns an error if the value is NaN, or if
+    /// it is out of the indexable range under the `OutOfRangePolicy::Error` policy. The value
+    /// is counted as dropped in both cases.
+    pub fn try_accept_with_count(&mut self, value: f64, count: f64) -> Result<(), Error> {
+        if count.is_nan() || count <= 0.0 {
+            return Ok(());
         }
 
-        if value < -self.max_indexed_value || value > self.max_indexed_value {
-            return;
+        if value.is_nan() {
+            self.value_counters.nan += count;
+            self.value_counters.dropped += count;
+            return Err(Error::InvalidArgument("The value is NaN."));
         }
+        if value.is_infinite() {
+            self.value_counters.infinite += count;
+        }
+
+        let value = if value < -self.max_indexed_value || value > self.max_indexed_value {
+            match self.out_of_range_policy {
+                OutOfRangePolicy::Drop => {
+                    self.value_counters.dropped += count;
+                    return Ok(());
+                }
+                OutOfRangePolicy::Clamp => {
+                    value.clamp(-self.max_indexed_value, self.max_indexed_value)
+                }
+                OutOfRangePolicy::Error => {
+                    self.value_counters.dropped += count;
+                    return Err(Error::InvalidArgument(
+                        "The value is out of the indexable range.",
+                    ));
+                }
+            }
+        } else {
+            value
+        };
+
+        self.add_to_bin(value, count);
+        if let Some(summary_statistics) = self.summary_statistics.as_mut() {
+            summary_statistics.add(value, count);
+        }
+        Ok(())
+    }
 
+    /// Adds `count` to the bin of `value`, which is clamped to the indexable range.
+    fn add_to_bin(&mut self, value: f64, count: f64) {
+        let value = value.clamp(-self.max_indexed_value, self.max_indexed_value);
         if value > self.min_indexed_value {
             self.positive_value_store
-                .add(self.index_mapping.index(value), 1.0);
+                .add(self.index_mapping.index(value), count);
         } else if value < -self.min_indexed_value {
             self.negative_value_store
-                .add(self.index_mapping.index(-value), 1.0);
+                .add(self.index_mapping.index(-value), count);
         } else {
-            self.zero_count += 1.0;
+            self.zero_count += count;
+        }
+    }
+
+    pub fn get_index_mapping(&self) -> &IndexMapping {
+        &self.index_mapping
+    }
+
+    /// Returns the total weight of the values that were not recorded: the NaN values, and the
+    /// values out of the indexable range unless they are clamped.
+    pub fn get_dropped_count(&self) -> f64 {
+        self.value_counters.dropped
+    }
+
+    /// Returns the total weight of the NaN values, all of which are dropped.
+    pub fn get_nan_count(&self) -> f64 {
+        self.value_counters.nan
+    }
+
+    /// Returns the total weight of the infinite values, whether they were clamped or dropped.
+    pub fn get_infinite_count(&self) -> f64 {
+        self.value_counters.infinite
+    }
+
+    pub(crate) fn add_dropped_counts(&mut self, dropped: f64, nan: f64, infinite: f64) {
+        self.value_counters.dropped += dropped;
+        self.value_counters.nan += nan;
+        self.value_counters.infinite += infinite;
+    }
+
+    /// Returns the absolute value up to which values are counted as zero.
+    pub fn get_zero_threshold(&self) -> f64 {
+        self.zero_threshold
+    }
+
+    /// Raises the zero threshold to `zero_threshold` if it is lower, and moves to the zero
+    /// bucket the bins that lie entirely below the threshold, as OpenTelemetry exponential
+    /// histograms do when merging histograms with different zero thresholds. The bin that
+    /// holds the threshold itself is kept, as values just above the threshold go to it. A raised
+    /// threshold is encoded, so that it is not lost on decoding.
+    fn reconcile_zero_threshold(&mut self, zero_threshold: f64) {
+        if zero_threshold > self.zero_threshold {
+            self.zero_threshold = zero_threshold;
+            self.extensions.zero_threshold = true;
+            self.min_indexed_value =
+                f64::max(zero_threshold, self.index_mapping.min_indexable_value());
+        }
+        if self.zero_threshold > 0.0 {
+            let index = self.index_mapping.index(self.min_indexed_value);
+            self.zero_count += self.negative_value_store.remove_below(index);
+            self.zero_count += self.positive_value_store.remove_below(index);
         }
     }
 
+    /// Returns a sketch of the accepted values multiplied by `factor`, along with the relative
+    /// accuracy it guarantees. Its mapping may be shifted, see `scale_keeping_mapping`.
+    pub fn scale(self, factor: f64) -> Result<(DDSketch, f64), Error> {
+        self.scale_with(factor, true)
+    }
+
+    /// Returns a sketch of the accepted values multiplied by `factor` like `scale`, but always
+    /// moves the bins under the mapping of this sketch, so that the result can be merged with
+    /// the other sketches sharing it.
+    pub fn scale_keeping_mapping(self, factor: f64) -> Result<(DDSketch, f64), Error> {
+        self.scale_with(factor, false)
+    }
+
+    fn scale_with(mut self, factor: f64, shift_mapping: bool) -> Result<(DDSketch, f64), Error> {
+        if !factor.is_finite() {
+            return Err(Error::InvalidArgument("The factor must be finite."));
+        }
+        let relative_accuracy = self.index_mapping.relative_accuracy();
+        if let Some(summary_statistics) = self.summary_statistics.as_mut() {
+            summary_statistics.scale(factor);
+        }
+
+        if factor == 0.0 {
+            self.zero_count = self.get_count();
+            self.negative_value_store.clear();
+            self.positive_value_store.clear();
+            return Ok((self, relative_accuracy));
+        }
+
+        self.zero_threshold *= factor.abs();
+        let scaled_index_mapping = if shift_mapping {
+            self.index_mapping.scaled(factor.abs())
+        } else {
+            None
+        };
+        if let Some(index_mapping) = scaled_index_mapping {
+            self.min_indexed_value =
+                f64::max(self.zero_threshold, index_mapping.min_indexable_value());
+            self.max_indexed_value = index_mapping.max_indexable_value();
+            self.index_mapping = index_mapping;
+            if factor < 0.0 {
+                std::mem::swap(
+                    &mut self.negative_value_store,
+                    &mut self.positive_value_store,
+                );
+            }
+            return Ok((self, relative_accuracy));
+        }
+
+        let bins: Vec<(f64, f64)> = self.get_bins().collect();
+        self.negative_value_store.clear();
+        self.positive_value_store.clear();
+        self.zero_count = 0.0;
+        self.min_indexed_value = f64::max(
+            self.zero_threshold,
+            self.index_mapping.min_indexable_value(),
+        );
+        for (value, count) in bins {
+            self.add_to_bin(value * factor, count);
+        }
+        Ok((
+            self,
+            2.0 * relative_accuracy + relative_accuracy * relative_accuracy,
+        ))
+    }
+
     pub fn is_empty(&self) -> bool {
         self.zero_count == 0.0
             && self.negative_value_store.is_empty()
@@ -65,20 +268,51 @@ impl DDSketch {
         self.negative_value_store.clear();
         self.positive_value_store.clear();
         self.zero_count = 0.0;
+        self.value_counters = ValueCounters::default();
+        if let Some(summary_statistics) = self.summary_statistics.as_mut() {
+            summary_statistics.clear();
+        }
     }
 
-    pub fn get_count(&mut self) -> f64 {
+    /// Multiplies the count of every bin by `factor`.
+    pub(crate) fn multiply_counts(&mut self, factor: f64) {
+        self.negative_value_store.multiply_counts(factor);
+        self.positive_value_store.multiply_counts(factor);
+        self.zero_count *= factor;
+        self.value_counters.multiply(factor);
+        if let Some(summary_statistics) = self.summary_statistics.as_mut() {
+            summary_statistics.multiply_counts(factor);
+        }
+    }
+
+    /// Enables the exact tracking of the count, sum, extremes and central moments of the
+    /// accepted values. It must be enabled before any value is accepted.
+    pub fn with_exact_summary_statistics(mut self) -> Result<DDSketch, Error> {
+        if !self.is_empty() {
+            return Err(Error::InvalidArgument(
+                "Exact summary statistics must be enabled on an empty sketch.",
+            ));
+        }
+        self.summary_statistics = Some(SummaryStatistics::new());
+        Ok(self)
+    }
+
+    pub fn get_count(&self) -> f64 {
         self.zero_count
             + self.negative_value_store.get_total_count()
             + self.positive_value_store.get_total_count()
     }
 
-    pub fn get_sum(&mut self) -> Option<f64> {
+    pub fn get_sum(&self) -> Option<f64> {
         let count = self.get_count();
         if count <= 0.0 {
             return None;
         }
 
+        if let Some(summary_statistics) = &self.summary_statistics {
+            return Some(summary_statistics.sum());
+        }
+
         let mut sum = 0.0;
         sum -= self.negative_value_store.get_sum(&self.index_mapping);
         sum += self.positive_value_store.get_sum(&self.index_mapping);
@@ -86,7 +320,13 @@ impl DDSketch {
         Some(sum)
     }
 
-    pub fn get_max(&mut self) -> Option<f64> {
+    pub fn get_max(&self) -> Option<f64> {
+        if let Some(summary_statistics) = &self.summary_statistics {
+            if summary_statistics.count() > 0.0 {
+                return Some(summary_statistics.max());
+            }
+        }
+
         if !self.positive_value_store.is_empty() {
             Some(
                 self.index_mapping
@@ -105,7 +345,13 @@ impl DDSketch {
         }
     }
 
-    pub fn get_min(&mut self) -> Option<f64> {
+    pub fn get_min(&self) -> Option<f64> {
+        if let Some(summary_statistics) = &self.summary_statistics {
+            if summary_statistics.count() > 0.0 {
+                return Some(summary_statistics.min());
+            }
+        }
+
         if !self.negative_value_store.is_empty() {
             Some(
                 -self
@@ -124,7 +370,7 @@ impl DDSketch {
         }
     }
 
-    pub fn get_average(&mut self) -> Option<f64> {
+    pub fn get_average(&self) -> Option<f64> {
         let count = self.get_count();
         if count <= 0.0 {
             return None;
@@ -132,7 +378,155 @@ impl DDSketch {
         Some(self.get_sum()? / count)
     }
 
-    pub fn get_value_at_quantile(self: &mut DDSketch, quantile: f64) -> Option<f64> {
+    /// Returns the population variance of the accepted values, computed from the exact summary
+    /// statistics when they are tracked and from the bin representatives otherwise.
+    pub fn get_variance(&self) -> Option<f64> {
+        Some(self.get_central_moments()?.0)
+    }
+
+    pub fn get_standard_deviation(&self) -> Option<f64> {
+        Some(self.get_variance()?.sqrt())
+    }
+
+    /// Returns the skewness of the accepted values, or `0.0` if all of them fall into the same
+    /// bin.
+    pub fn get_skewness(&self) -> Option<f64> {
+        let (m2, m3, _m4) = self.get_central_moments()?;
+        if m2 <= 0.0 {
+            return Some(0.0);
+        }
+        Some(m3 / m2.powf(1.5))
+    }
+
+    /// Returns the excess kurtosis of the accepted values, or `0.0` if all of them fall into the
+    /// same bin.
+    pub fn get_kurtosis(&self) -> Option<f64> {
+        let (m2, _m3, m4) = self.get_central_moments()?;
+        if m2 <= 0.0 {
+            return Some(0.0);
+        }
+        Some(m4 / (m2 * m2) - 3.0)
+    }
+
+    /// Returns the mean of the values between the `q_low` and `q_high` quantiles, the bins
+    /// straddling either cut point only contributing their overlapping fraction.
+    pub fn trimmed_mean(&self, q_low: f64, q_high: f64) -> Option<f64> {
+        let (lower_rank, upper_rank, sum, low_value, _high_value) =
+            self.get_clipped_sum(q_low, q_high)?;
+        if upper_rank > lower_rank {
+            Some(sum / (upper_rank - lower_rank))
+        } else {
+            Some(low_value)
+        }
+    }
+
+    /// Returns the mean of the values after clamping those below the `q_low` quantile and
+    /// above the `q_high` quantile to the values of these quantiles.
+    pub fn winsorized_mean(&self, q_low: f64, q_high: f64) -> Option<f64> {
+        let count = self.get_count();
+        let (lower_rank, upper_rank, sum, low_value, high_value) =
+            self.get_clipped_sum(q_low, q_high)?;
+        Some((lower_rank * low_value + sum + (count - upper_rank) * high_value) / count)
+    }
+
+    /// Walks the bins once and returns the ranks of both cut points, the sum of the values
+    /// between them, prorating the partial bins, and the values at both cut points.
+    fn get_clipped_sum(&self, q_low: f64, q_high: f64) -> Option<(f64, f64, f64, f64, f64)> {
+        if !(0.0..=1.0).contains(&q_low) || !(0.0..=1.0).contains(&q_high) || q_low > q_high {
+            return None;
+        }
+
+        let count = self.get_count();
+        if count <= 0.0 {
+            return None;
+        }
+
+        let lower_rank = q_low * count;
+        let upper_rank = q_high * count;
+
+        let mut n = 0.0;
+        let mut sum = 0.0;
+        let mut low_value = None;
+        let mut high_value = None;
+        let mut last_value = 0.0;
+        for (value, bin_count) in self.get_bins() {
+            let start = n;
+            n += bin_count;
+            last_value = value;
+            if low_value.is_none() && n > lower_rank {
+                low_value = Some(value);
+            }
+            let overlap = n.min(upper_rank) - start.max(lower_rank);
+            if overlap > 0.0 {
+                sum += overlap * value;
+            }
+            if n >= upper_rank {
+                high_value = Some(value);
+                break;
+            }
+        }
+
+        let high_value = high_value.unwrap_or(last_value);
+        let low_value = low_value.unwrap_or(high_value);
+        Some((lower_rank, upper_rank, sum, low_value, high_value))
+    }
+
+    fn get_central_moments(&self) -> Option<(f64, f64, f64)> {
+        if let Some(summary_statistics) = &self.summary_statistics {
+            if summary_statistics.count() > 0.0 {
+                return Some(summary_statistics.central_moments());
+            }
+        }
+
+        let mut count = 0.0;
+        let mut sum = 0.0;
+        for (value, bin_count) in self.get_bins() {
+            count += bin_count;
+            sum += value * bin_count;
+        }
+        if count <= 0.0 {
+            return None;
+        }
+
+        let mean = sum / count;
+        let mut moments = (0.0, 0.0, 0.0);
+        for (value, bin_count) in self.get_bins() {
+            let deviation = value - mean;
+            let square = deviation * deviation;
+            moments.0 += bin_count * square;
+            moments.1 += bin_count * square * deviation;
+            moments.2 += bin_count * square * square;
+        }
+        Some((moments.0 / count, moments.1 / count, moments.2 / count))
+    }
+
+    /// Freezes the current content of the sketch into a snapshot that answers quantile and rank
+    /// queries in logarithmic time and can be shared between threads.
+    pub fn snapshot(&self) -> DDSketchSnapshot {
+        DDSketchSnapshot::new(
+            self.get_bins(),
+            self.get_sum(),
+            self.get_min(),
+            self.get_max(),
+        )
+    }
+
+    /// Iterates over the non-empty bins in ascending order of value, yielding the representative
+    /// value of each bin along with its count. The zero bucket is yielded as `0.0`.
+    pub(crate) fn get_bins(&self) -> impl Iterator<Item = (f64, f64)> + '_ {
+        let negative_bins = self
+            .negative_value_store
+            .get_descending_iter()
+            .map(move |bin| (-self.index_mapping.value(bin.0), bin.1));
+        let zero_bin = Some((0.0, self.zero_count)).filter(|bin| bin.1 > 0.0);
+        let positive_bins = self
+            .positive_value_store
+            .get_ascending_iter()
+            .map(move |bin| (self.index_mapping.value(bin.0), bin.1));
+        negative_bins.chain(zero_bin).chain(positive_bins)
+    }
+
+    pub fn get_value_at_quantile(&self, quantile: f64) -> Option<f64> {
         if !(0.0..=1.0).contains(&quantile) {
             return None;
         }
@@ -142,8 +536,11 @@ impl DDSketch {
             return None;
         }
 
-        let rank = quantile * (count - 1.0);
+        self.get_value_at_rank(quantile * (count - 1.0))
+    }
 
+    /// Returns the value of the bin where the cumulative count first exceeds `rank`.
+    pub(crate) fn get_value_at_rank(&self, rank: f64) -> Option<f64> {
         let mut n: f64 = 0.0;
 
         let negative_bin_iterator = self.negative_value_store.get_descending_iter();
@@ -170,106 +567,524 @@ impl DDSketch {
         None
     }
 
-    pub fn decode_and_merge_with(&mut self, bytes: &Vec<u8>) -> Result<(), Error> {
-        let mut input = Input::wrap(bytes);
+    pub fn decode_and_merge_with(&mut self, bytes: &[u8]) -> Result<(), Error> {
+        self.decode_and_merge_with_limits(bytes, DecodeLimits::default())
+    }
+
+    /// Decodes a sketch and merges it into this one, failing with `Error::LimitExceeded` if it
+    /// exceeds `limits`.
+    pub fn decode_and_merge_with_limits(
+        &mut self,
+        bytes: &[u8],
+        limits: DecodeLimits,
+    ) -> Result<(), Error> {
+        self.decode_and_merge_with_input(&mut Input::wrap(bytes), &limits)
+    }
+
+    /// Decodes a sketch from `reader`, which is read to its end through a buffer, and merges it
+    /// into this one. See `decode_from` to read a sketch followed by other data.
+    pub fn decode_and_merge_with_reader<R: Read>(&mut self, reader: R) -> Result<(), Error> {
+        self.decode_and_merge_with_reader_with_limits(reader, DecodeLimits::default())
+    }
+
+    /// Decodes a sketch from `reader` and merges it into this one like
+    /// `decode_and_merge_with_reader`, within `limits`.
+    pub fn decode_and_merge_with_reader_with_limits<R: Read>(
+        &mut self,
+        reader: R,
+        limits: DecodeLimits,
+    ) -> Result<(), Error> {
+        self.decode_and_merge_with_input(
+            &mut Input::from_reader(&mut BufReader::new(reader)),
+            &limits,
+        )
+    }
+
+    pub(crate) fn decode_and_merge_with_input(
+        &mut self,
+        input: &mut Input,
+        limits: &DecodeLimits,
+    ) -> Result<(), Error> {
+        let count = self.get_count();
+        let result = self.decode_and_merge_bins_with_input(input, limits);
+        // The encoding carries no higher moments, the merged values can't be summarized exactly,
+        // even if only part of them was merged before an error.
+        if self.get_count() != count {
+            self.summary_statistics = None;
+        }
+        result
+    }
+
+    fn decode_and_merge_bins_with_input(
+        &mut self,
+        input: &mut Input,
+        limits: &DecodeLimits,
+    ) -> Result<(), Error> {
+        let mut zero_threshold = 0.0;
+        let mut num_bins = 0;
+        let mut negative_span = None;
+        let mut positive_span = None;
         while input.has_remaining() {
-            let flag = Flag::decode(&mut input)?;
+            let flag = Flag::decode(input)?;
             let flag_type = flag.get_type()?;
             match flag_type {
                 FlagType::PositiveStore => {
                     let mode = BinEncodingMode::of_flag(flag.get_marker())?;
-                    self.positive_value_store
-                        .decode_and_merge_with(&mut input, mode)?;
+                    self.positive_value_store.decode_and_merge_with(
+                        input,
+                        mode,
+                        limits,
+                        &mut num_bins,
+                        &mut positive_span,
+                    )?;
                 }
                 FlagType::NegativeStore => {
                     let mode = BinEncodingMode::of_flag(flag.get_marker())?;
-                    self.negative_value_store
-                        .decode_and_merge_with(&mut input, mode)?;
+                    self.negative_value_store.decode_and_merge_with(
+                        input,
+                        mode,
+                        limits,
+                        &mut num_bins,
+                        &mut negative_span,
+                    )?;
                 }
                 FlagType::IndexMapping => {
-                    let layout = IndexMappingLayout::of_flag(&flag)?;
-                    let gamma = input.read_double_le()?;
-                    let index_offset = input.read_double_le()?;
-                    let decoded_index_mapping =
-                        IndexMapping::with_gamma_offset(layout, gamma, index_offset)?;
+                    let decoded_index_mapping = decode_index_mapping(input, &flag)?;
                     if self.index_mapping != decoded_index_mapping {
-                        return Err(Error::InvalidArgument("Unmatched IndexMapping"));
+                        return Err(Error::UnmatchedMapping {
+                            expected: self.index_mapping.clone(),
+                            actual: decoded_index_mapping,
+                        });
                     }
                 }
                 FlagType::SketchFeatures => {
                     if Flag::ZERO_COUNT == flag {
-                        self.zero_count += serde::decode_var_double(&mut input)?;
-                    } else {
-                        serde::ignore_exact_summary_statistic_flags(&mut input, flag)?;
+                        self.zero_count += serde::decode_var_double(input)?;
+                    } else if Flag::ZERO_THRESHOLD == flag {
+                        zero_threshold = decode_zero_threshold(input)?;
+                    } else if !self.value_counters.decode_and_add(&flag, input)? {
+                        serde::ignore_exact_summary_statistic_flags(input, flag)?;
                     }
                 }
             }
         }
+        self.reconcile_zero_threshold(zero_threshold);
         Ok(())
     }
 
     pub fn merge_with(&mut self, other: &DDSketch) -> Result<(), Error> {
         if self.index_mapping != other.index_mapping {
-            return Err(Error::InvalidArgument("Unmatched indexMapping."));
+            return Err(Error::UnmatchedMapping {
+                expected: self.index_mapping.clone(),
+                actual: other.index_mapping.clone(),
+            });
         }
         self.negative_value_store
             .merge_with(other.negative_value_store.get_descending_stream());
         self.positive_value_store
             .merge_with(other.positive_value_store.get_descending_stream());
         self.zero_count += other.zero_count;
+        self.value_counters.merge_with(&other.value_counters);
+        self.reconcile_zero_threshold(other.zero_threshold);
+        match (self.summary_statistics.as_mut(), &other.summary_statistics) {
+            (Some(summary_statistics), Some(other_summary_statistics)) => {
+                summary_statistics.merge_with(other_summary_statistics)
+            }
+            (Some(_), None) if !other.is_empty() => self.summary_statistics = None,
+            _ => {}
+        }
         Ok(())
     }
 
+    /// Merges a sketch that shares the mapping of this one, as the sketches built from the same
+    /// builder do.
+    pub(crate) fn merge_with_same_mapping(&mut self, other: &DDSketch) {
+        self.merge_with(other)
+            .expect("The sketches share the same mapping.");
+    }
+
+    /// Merges many sketches at once into a new sketch with unbounded stores.
+    ///
+    /// The mappings are checked and the index ranges of all the sketches collected before any
+    /// count is read, so that each store of the result is allocated only once. The result takes
+    /// the largest zero threshold of the sketches and the out-of-range policy of the first one.
+    ///
+    /// The union of the index ranges of each sign must fit in the index span of the default
+    /// `DecodeLimits`, see `merge_all_with_limits`.
+    pub fn merge_all<'a, I>(sketches: I) -> Result<DDSketch, Error>
+    where
+        I: IntoIterator<Item = &'a DDSketch>,
+    {
+        DDSketch::merge_all_with_limits(sketches, DecodeLimits::default())
+    }
+
+    /// Merges many sketches at once like `merge_all`, the union of the index ranges of each
+    /// sign being bounded by the index span of `limits` rather than the default one.
+    pub fn merge_all_with_limits<'a, I>(
+        sketches: I,
+        limits: DecodeLimits,
+    ) -> Result<DDSketch, Error>
+    where
+        I: IntoIterator<Item = &'a DDSketch>,
+    {
+        let sketches: Vec<&DDSketch> = sketches.into_iter().collect();
+        let first = match sketches.first() {
+            Some(first) => *first,
+            None => return Err(Error::InvalidArgument("No sketch to merge.")),
+        };
+
+        let mut negative_counts = DenseCounts::new();
+        let mut positive_counts = DenseCounts::new();
+        for sketch in sketches.iter() {
+            if sketch.index_mapping != first.index_mapping {
+                return Err(Error::UnmatchedMapping {
+                    expected: first.index_mapping.clone(),
+                    actual: sketch.index_mapping.clone(),
+                });
+            }
+            negative_counts.extend_to_store(sketch.negative_value_store.as_ref());
+            positive_counts.extend_to_store(sketch.positive_value_store.as_ref());
+        }
+        // The values can be summarized exactly only if all the non-empty sketches are.
+        let mut summary_statistics = sketches
+            .iter()
+            .any(|sketch| sketch.summary_statistics.is_some())
+            .then(SummaryStatistics::new);
+        if sketches
+            .iter()
+            .any(|sketch| sketch.summary_statistics.is_none() && !sketch.is_empty())
+        {
+            summary_statistics = None;
+        }
+
+        negative_counts.allocate(&limits)?;
+        positive_counts.allocate(&limits)?;
+        let mut zero_count = 0.0;
+        let mut value_counters = ValueCounters::default();
+        for sketch in sketches.iter() {
+            for (index, count) in sketch.negative_value_store.get_ascending_iter() {
+                negative_counts.add(index, count);
+            }
+            for (index, count) in sketch.positive_value_store.get_ascending_iter() {
+                positive_counts.add(index, count);
+            }
+            zero_count += sketch.zero_count;
+            value_counters.merge_with(&sketch.value_counters);
+            if let (Some(merged), Some(other)) =
+                (summary_statistics.as_mut(), &sketch.summary_statistics)
+            {
+                merged.merge_with(other);
+            }
+        }
+
+        let mut merged = DDSketch::with_stores(
+            first.index_mapping.clone(),
+            negative_counts.into_store(),
+            positive_counts.into_store(),
+            first.zero_threshold,
+            first.out_of_range_policy,
+            false,
+            first.extensions,
+        );
+        merged.zero_count = zero_count;
+        merged.value_counters = value_counters;
+        merged.summary_statistics = summary_statistics;
+        merged.reconcile_zero_threshold(
+            sketches
+                .iter()
+                .map(|sketch| sketch.zero_threshold)
+                .fold(0.0, f64::max),
+        );
+        Ok(merged)
+    }
+
+    /// Merges many encoded sketches at once into a new sketch with unbounded stores, without
+    /// decoding each of them into its own sketch first.
+    ///
+    /// The buffers are read twice: once to check the mappings and collect the index ranges, and
+    /// once to sum the counts into stores allocated only once.
+    pub fn merge_all_encoded<'a, I>(encoded_sketches: I) -> Result<DDSketch, Error>
+    where
+        I: IntoIterator<Item = &'a [u8]>,
+    {
+        DDSketch::merge_all_encoded_with_limits(encoded_sketches, DecodeLimits::default())
+    }
+
+    /// Merges many encoded sketches at once like `merge_all_encoded`, each encoding and the
+    /// union of the index ranges of each sign being bounded by `limits`.
+    pub fn merge_all_encoded_with_limits<'a, I>(
+        encoded_sketches: I,
+        limits: DecodeLimits,
+    ) -> Result<DDSketch, Error>
+    where
+        I: IntoIterator<Item = &'a [u8]>,
+    {
+        let encoded_sketches: Vec<&[u8]> = encoded_sketches.into_iter().collect();
+        if encoded_sketches.is_empty() {
+            return Err(Error::InvalidArgument("No sketch to merge."));
+        }
+
+        let mut index_mapping: Option<IndexMapping> = None;
+        let mut zero_threshold: f64 = 0.0;
+        let mut negative_counts = DenseCounts::new();
+        let mut positive_counts = DenseCounts::new();
+        for bytes in encoded_sketches.iter() {
+            let mut has_index_mapping = false;
+            visit_encoded(bytes, &limits, |item| {
+                match item {
+                    EncodedItem::IndexMapping(decoded_index_mapping) => {
+                        match &index_mapping {
+                            Some(index_mapping) if *index_mapping != decoded_index_mapping => {
+                                return Err(Error::UnmatchedMapping {
+                                    expected: index_mapping.clone(),
+                                    actual: decoded_index_mapping,
+                                });
+                            }
+                            Some(_) => {}
+                            None => index_mapping = Some(decoded_index_mapping),
+                        }
+                        has_index_mapping = true;
+                    }
+                    EncodedItem::ZeroThreshold(threshold) => {
+                        zero_threshold = zero_threshold.max(threshold)
+                    }
+                    EncodedItem::ZeroCount(_) | EncodedItem::ValueCounters(_) => {}
+                    EncodedItem::NegativeBin(index, _) => negative_counts.extend_range(index),
+                    EncodedItem::PositiveBin(index, _) => positive_counts.extend_range(index),
+                }
+                Ok(())
+            })?;
+            if !has_index_mapping {
+                return Err(no_index_mapping(bytes.len()));
+            }
+        }
+
+        negative_counts.allocate(&limits)?;
+        positive_counts.allocate(&limits)?;
+        let mut zero_count = 0.0;
+        let mut value_counters: Option<ValueCounters> = None;
+        for bytes in encoded_sketches.iter() {
+            visit_encoded(bytes, &limits, |item| {
+                match item {
+                    EncodedItem::IndexMapping(_) | EncodedItem::ZeroThreshold(_) => {}
+                    EncodedItem::ZeroCount(count) => zero_count += count,
+                    EncodedItem::ValueCounters(counters) => value_counters
+                        .get_or_insert_with(ValueCounters::default)
+                        .merge_with(&counters),
+                    EncodedItem::NegativeBin(index, count) => negative_counts.add(index, count),
+                    EncodedItem::PositiveBin(index, count) => positive_counts.add(index, count),
+                }
+                Ok(())
+            })?;
+        }
+
+        let index_mapping =
+            index_mapping.ok_or_else(|| no_index_mapping(encoded_sketches[0].len()))?;
+        let mut merged = DDSketch::with_stores(
+            index_mapping,
+            negative_counts.into_store(),
+            positive_counts.into_store(),
+            0.0,
+            OutOfRangePolicy::default(),
+            false,
+            Extensions {
+                zero_threshold: zero_threshold > 0.0,
+                value_counters: value_counters.is_some(),
+                ..Extensions::default()
+            },
+        );
+        merged.zero_count = zero_count;
+        merged.value_counters = value_counters.unwrap_or_default();
+        merged.reconcile_zero_threshold(zero_threshold);
+        Ok(merged)
+    }
+
     pub fn encode(&self) -> Result<Vec<u8>, Error> {
         let mut output = Output::with_capacity(64);
-        self.index_mapping.encode(&mut output)?;
+        self.encode_output(&mut output)?;
+        Ok(output.trim())
+    }
 
+    /// Returns the number of bytes that `encode` produces, without encoding the sketch.
+    pub fn encoded_len(&self) -> usize {
+        // The flag of the mapping, then its gamma and index offset.
+        let mut len = 17;
+        if self.extensions.zero_threshold && self.zero_threshold > 0.0 {
+            len += 9;
+        }
         if self.zero_count != 0.0 {
-            Flag::ZERO_COUNT.encode(&mut output)?;
-            serde::encode_var_double(&mut output, self.zero_count)?;
+            len += 1 + serde::var_double_encoded_length(self.zero_count) as usize;
+        }
+        if self.extensions.value_counters {
+            len += self.value_counters.encoded_len();
         }
+        len + self
+            .positive_value_store
+            .encoded_len(self.extensions.compact_bins)
+            + self
+                .negative_value_store
+                .encoded_len(self.extensions.compact_bins)
+    }
 
-        self.positive_value_store
-            .encode(&mut output, FlagType::PositiveStore)?;
-        self.negative_value_store
-            .encode(&mut output, FlagType::NegativeStore)?;
+    /// Writes the encoding of the sketch to `writer`, without buffering it first.
+    pub fn encode_to<W: Write>(&self, mut writer: W) -> Result<(), Error> {
+        self.encode_output(&mut Output::to_writer(&mut writer))
+    }
 
-        Ok(output.trim())
+    /// Writes the encoding of the sketch to `writer`, prefixed with its length as a variable
+    /// length integer, so that `decode_length_prefixed_from` can read it back from a stream
+    /// holding other data after it.
+    pub fn encode_length_prefixed_to<W: Write>(&self, mut writer: W) -> Result<(), Error> {
+        let mut output = Output::to_writer(&mut writer);
+        serde::encode_unsigned_var_long(&mut output, self.encoded_len() as i64)?;
+        self.encode_output(&mut output)
     }
 
-    pub fn decode(bytes: &Vec<u8>) -> Result<DDSketch, Error> {
-        let mut input = Input::wrap(bytes);
+    fn encode_output(&self, output: &mut Output) -> Result<(), Error> {
+        self.index_mapping.encode(output)?;
+
+        if self.extensions.zero_threshold && self.zero_threshold > 0.0 {
+            Flag::ZERO_THRESHOLD.encode(output)?;
+            output.write_double_le(self.zero_threshold)?;
+        }
+
+        if self.zero_count != 0.0 {
+            Flag::ZERO_COUNT.encode(output)?;
+            serde::encode_var_double(output, self.zero_count)?;
+        }
+
+        if self.extensions.value_counters {
+            self.value_counters.encode(output)?;
+        }
+
+        self.positive_value_store.encode(
+            output,
+            FlagType::PositiveStore,
+            self.extensions.compact_bins,
+        )?;
+        self.negative_value_store.encode(
+            output,
+            FlagType::NegativeStore,
+            self.extensions.compact_bins,
+        )?;
+
+        Ok(())
+    }
+
+    /// Decodes a sketch within the default `DecodeLimits`.
+    pub fn decode(bytes: &[u8]) -> Result<DDSketch, Error> {
+        DDSketch::decode_with_limits(bytes, DecodeLimits::default())
+    }
+
+    /// Decodes a sketch, failing with `Error::LimitExceeded` if it exceeds `limits`.
+    pub fn decode_with_limits(bytes: &[u8], limits: DecodeLimits) -> Result<DDSketch, Error> {
+        DDSketch::decode_input(&mut Input::wrap(bytes), &limits)
+    }
+
+    /// Decodes a sketch from `reader`, which is read to its end through a buffer, without
+    /// holding the whole encoding in memory.
+    ///
+    /// The encoding doesn't record its own length, so a sketch followed by other data must be
+    /// read through a reader limited to its length, such as `Read::take`, or be written by
+    /// `encode_length_prefixed_to` and read by `decode_length_prefixed_from`.
+    pub fn decode_from<R: Read>(reader: R) -> Result<DDSketch, Error> {
+        DDSketch::decode_from_with_limits(reader, DecodeLimits::default())
+    }
+
+    /// Decodes a sketch from `reader` like `decode_from`, within `limits`.
+    pub fn decode_from_with_limits<R: Read>(
+        reader: R,
+        limits: DecodeLimits,
+    ) -> Result<DDSketch, Error> {
+        DDSketch::decode_input(
+            &mut Input::from_reader(&mut BufReader::new(reader)),
+            &limits,
+        )
+    }
+
+    /// Decodes a sketch written by `encode_length_prefixed_to` from `reader`, which is read up to
+    /// the end of the sketch only, so that what follows it can be read next.
+    pub fn decode_length_prefixed_from<R: Read>(reader: R) -> Result<DDSketch, Error> {
+        DDSketch::decode_length_prefixed_from_with_limits(reader, DecodeLimits::default())
+    }
+
+    /// Decodes a sketch written by `encode_length_prefixed_to` from `reader` like
+    /// `decode_length_prefixed_from`, within `limits`.
+    pub fn decode_length_prefixed_from_with_limits<R: Read>(
+        reader: R,
+        limits: DecodeLimits,
+    ) -> Result<DDSketch, Error> {
+        read_length_prefixed(reader, |input| DDSketch::decode_input(input, &limits))
+    }
+
+    /// Decodes a sketch written by `encode_length_prefixed_to` from `reader` and merges it into
+    /// this one, reading up to the end of the sketch only, as `decode_length_prefixed_from` does.
+    pub fn decode_and_merge_length_prefixed_from<R: Read>(
+        &mut self,
+        reader: R,
+    ) -> Result<(), Error> {
+        self.decode_and_merge_length_prefixed_from_with_limits(reader, DecodeLimits::default())
+    }
+
+    /// Decodes a sketch written by `encode_length_prefixed_to` from `reader` and merges it into
+    /// this one like `decode_and_merge_length_prefixed_from`, within `limits`.
+    pub fn decode_and_merge_length_prefixed_from_with_limits<R: Read>(
+        &mut self,
+        reader: R,
+        limits: DecodeLimits,
+    ) -> Result<(), Error> {
+        read_length_prefixed(reader, |input| {
+            self.decode_and_merge_with_input(input, &limits)
+        })
+    }
+
+    fn decode_input(input: &mut Input, limits: &DecodeLimits) -> Result<DDSketch, Error> {
         let mut positive_value_store = UnboundedSizeDenseStore::new();
         let mut negative_value_store = UnboundedSizeDenseStore::new();
         let mut index_mapping = None;
+        let mut zero_threshold = 0.0;
         let mut zero_count = 0.0;
+        let mut value_counters = ValueCounters::default();
+        let mut has_value_counters = false;
+        let mut num_bins = 0;
+        let mut negative_span = None;
+        let mut positive_span = None;
         while input.has_remaining() {
-            let flag = Flag::decode(&mut input)?;
+            let flag = Flag::decode(input)?;
             let flag_type = flag.get_type()?;
             match flag_type {
                 FlagType::PositiveStore => {
                     let mode = BinEncodingMode::of_flag(flag.get_marker())?;
-                    positive_value_store.decode_and_merge_with(&mut input, mode)?;
+                    positive_value_store.decode_and_merge_with(
+                        input,
+                        mode,
+                        limits,
+                        &mut num_bins,
+                        &mut positive_span,
+                    )?;
                 }
                 FlagType::NegativeStore => {
                     let mode = BinEncodingMode::of_flag(flag.get_marker())?;
-                    negative_value_store.decode_and_merge_with(&mut input, mode)?;
+                    negative_value_store.decode_and_merge_with(
+                        input,
+                        mode,
+                        limits,
+                        &mut num_bins,
+                        &mut negative_span,
+                    )?;
                 }
                 FlagType::IndexMapping => {
-                    let layout = IndexMappingLayout::of_flag(&flag)?;
-                    let gamma = input.read_double_le()?;
-                    let index_offset = input.read_double_le()?;
-                    index_mapping = Some(IndexMapping::with_gamma_offset(
-                        layout,
-                        gamma,
-                        index_offset,
-                    )?);
+                    index_mapping = Some(decode_index_mapping(input, &flag)?);
                 }
                 FlagType::SketchFeatures => {
                     if Flag::ZERO_COUNT == flag {
-                        zero_count += serde::decode_var_double(&mut input)?;
+                        zero_count += serde::decode_var_double(input)?;
+                    } else if Flag::ZERO_THRESHOLD == flag {
+                        zero_threshold = decode_zero_threshold(input)?;
+                    } else if value_counters.decode_and_add(&flag, input)? {
+                        has_value_counters = true;
                     } else {
-                        serde::ignore_exact_summary_statistic_flags(&mut input, flag)?;
+                        serde::ignore_exact_summary_statistic_flags(input, flag)?;
                     }
                 }
             }
@@ -277,139 +1092,647 @@ impl DDSketch {
 
         match index_mapping {
             Some(mapping) => {
-                let min_indexed_value = f64::max(0.0, mapping.min_indexable_value());
-                let max_indexed_value = mapping.max_indexable_value();
-                Ok(DDSketch {
-                    index_mapping: mapping,
-                    negative_value_store: Box::new(negative_value_store),
-                    positive_value_store: Box::new(positive_value_store),
-                    min_indexed_value,
-                    max_indexed_value,
-                    zero_count,
-                })
+                let mut sketch = DDSketch::with_stores(
+                    mapping,
+                    Box::new(negative_value_store),
+                    Box::new(positive_value_store),
+                    0.0,
+                    OutOfRangePolicy::default(),
+                    false,
+                    Extensions {
+                        zero_threshold: zero_threshold > 0.0,
+                        value_counters: has_value_counters,
+                        ..Extensions::default()
+                    },
+                );
+                sketch.zero_count = zero_count;
+                sketch.value_counters = value_counters;
+                sketch.reconcile_zero_threshold(zero_threshold);
+                Ok(sketch)
             }
-            None => Err(Error::InvalidArgument("No IndexMapping decoded")),
+            None => Err(no_index_mapping(input.position())),
         }
     }
 }
 
+impl DDSketch {
+    /// Creates a sketch from a histogram with fixed buckets, see `accept_histogram`.
+    pub fn from_histogram(
+        bounds: &[f64],
+        counts: &[f64],
+        min: Option<f64>,
+        index_mapping: IndexMapping,
+        out_of_range_policy: OutOfRangePolicy,
+    ) -> Result<(DDSketch, f64), Error> {
+        let mut sketch = DDSketch::with_stores(
+            index_mapping,
+            Box::new(UnboundedSizeDenseStore::new()),
+            Box::new(UnboundedSizeDenseStore::new()),
+            0.0,
+            out_of_range_policy,
+            false,
+            Extensions::default(),
+        );
+        let accuracy = sketch.accept_histogram(bounds, counts, min)?;
+        Ok((sketch, accuracy))
+    }
+
+    /// Adds the values of a histogram with fixed upper `bounds` and non-cumulated `counts`, the
+    /// first bucket starting at `min` if known, and returns the relative accuracy guaranteed.
+    pub fn accept_histogram(
+        &mut self,
+        bounds: &[f64],
+        counts: &[f64],
+        min: Option<f64>,
+    ) -> Result<f64, Error> {
+        if bounds.len() != counts.len() {
+            return Err(Error::InvalidArgument(
+                "There must be as many bounds as counts.",
+            ));
+        }
+        if bounds.iter().any(|bound| bound.is_nan())
+            || bounds.windows(2).any(|pair| pair[0] >= pair[1])
+        {
+            return Err(Error::InvalidArgument(
+                "The bounds must be strictly increasing.",
+            ));
+        }
+        if counts
+            .iter()
+            .any(|count| !count.is_finite() || *count < 0.0)
+        {
+            return Err(Error::InvalidArgument(
+                "The counts must be finite and non-negative.",
+            ));
+        }
+        let min = min.unwrap_or(f64::NEG_INFINITY);
+        if min.is_nan()
+            || bounds
+                .iter()
+                .zip(counts.iter())
+                .any(|(upper, count)| *upper < min && *count > 0.0)
+        {
+            return Err(Error::InvalidArgument(
+                "The buckets below the minimum must be empty.",
+            ));
+        }
+
+        let relative_accuracy = self.index_mapping.relative_accuracy();
+        let mut accuracy = relative_accuracy;
+        let mut out_of_range = false;
+        for (i, (upper, count)) in bounds.iter().zip(counts.iter()).enumerate() {
+            let (upper, count) = (*upper, *count);
+            if count == 0.0 {
+                continue;
+            }
+            let lower = match i {
+                0 => min,
+                _ => f64::max(bounds[i - 1], min),
+            };
+
+            if lower < -self.max_indexed_value || upper > self.max_indexed_value {
+                match self.out_of_range_policy {
+                    OutOfRangePolicy::Drop => self.value_counters.dropped += count,
+                    OutOfRangePolicy::Clamp => {
+                        let value = if upper > self.max_indexed_value {
+                            lower
+                        } else {
+                            upper
+                        };
+                        self.summary_statistics = None;
+                        self.accept_with_count(
+                            value.clamp(-self.max_indexed_value, self.max_indexed_value),
+                            count,
+                        );
+                        accuracy = 1.0;
+                    }
+                    OutOfRangePolicy::Error => {
+                        self.value_counters.dropped += count;
+                        out_of_range = true;
+                    }
+                }
+                continue;
+            }
+
+            if lower == upper {
+                self.accept_with_count(upper, count);
+            } else {
+                self.summary_statistics = None;
+                self.spread_uniformly(lower, upper, count);
+            }
+            // A value of the bucket may be reported anywhere between its bounds widened by the
+            // accuracy of the mapping.
+            let (inner, outer) = if lower < 0.0 && upper > 0.0 {
+                (0.0, f64::max(-lower, upper))
+            } else {
+                (
+                    f64::min(lower.abs(), upper.abs()),
+                    f64::max(lower.abs(), upper.abs()),
+                )
+            };
+            if outer > 0.0 {
+                accuracy = accuracy.max(
+                    1.0 - inner * (1.0 - relative_accuracy) / (outer * (1.0 + relative_accuracy)),
+                );
+            }
+        }
+        if out_of_range {
+            return Err(Error::InvalidArgument(
+                "A bucket is out of the indexable range.",
+            ));
+        }
+        Ok(accuracy.min(1.0))
+    }
+
+    /// Adds `count` values spread uniformly between `lower` and `upper`.
+    fn spread_uniformly(&mut self, lower: f64, upper: f64, count: f64) {
+        let density = count / (upper - lower);
+        let threshold = self.min_indexed_value;
+        let zero_width = f64::min(upper, threshold) - f64::max(lower, -threshold);
+        if zero_width > 0.0 {
+            self.zero_count += zero_width * density;
+        }
+        if upper > threshold {
+            spread_over_bins(
+                self.positive_value_store.as_mut(),
+                &self.index_mapping,
+                f64::max(lower, threshold),
+                upper,
+                density,
+            );
+        }
+        if lower < -threshold {
+            spread_over_bins(
+                self.negative_value_store.as_mut(),
+                &self.index_mapping,
+                f64::max(-upper, threshold),
+                -lower,
+                density,
+            );
+        }
+    }
+}
+
+/// Adds the values spread uniformly with `density` between the positive `lower` and `upper` to
+/// the bins they overlap, from the highest one down. Once the values left below a bin are
+/// negligible, they are added to that bin, so that a range starting close to zero doesn't go
+/// through all the bins down to the smallest indexable value.
+fn spread_over_bins(
+    store: &mut dyn Store,
+    index_mapping: &IndexMapping,
+    lower: f64,
+    upper: f64,
+    density: f64,
+) {
+    let total = (upper - lower) * density;
+    let mut remaining = total;
+    let mut index = index_mapping.index(upper);
+    loop {
+        let bin_lower = index_mapping.lower_bound(index);
+        let bin_upper = f64::min(upper, index_mapping.upper_bound(index));
+        let count = ((bin_upper - f64::max(lower, bin_lower)) * density).clamp(0.0, remaining);
+        remaining -= count;
+        if bin_lower <= lower || remaining <= total * f64::EPSILON || index == i32::MIN {
+            store.add(index, count + remaining);
+            return;
+        }
+        store.add(index, count);
+        index -= 1;
+    }
+}
+
 // factory methods
 impl DDSketch {
+    pub fn builder(relative_accuracy: f64) -> DDSketchBuilder {
+        DDSketchBuilder::new(relative_accuracy)
+    }
+
     pub fn collapsing_lowest_dense(
         relative_accuracy: f64,
         max_num_bins: usize,
     ) -> Result<DDSketch, Error> {
-        let index_mapping = IndexMapping::with_relative_accuracy(LogCubic, relative_accuracy)?;
-        let negative_value_store = CollapsingLowestDenseStore::with_capacity(max_num_bins)?;
-        let positive_value_store = CollapsingLowestDenseStore::with_capacity(max_num_bins)?;
-        let min_indexed_value = f64::max(0.0, index_mapping.min_indexable_value());
-        let max_indexed_value = index_mapping.max_indexable_value();
-        let zero_count = 0.0;
-
-        Ok(DDSketch {
-            index_mapping,
-            negative_value_store: Box::new(negative_value_store),
-            positive_value_store: Box::new(positive_value_store),
-            min_indexed_value,
-            max_indexed_value,
-            zero_count,
-        })
+        DDSketchBuilder::new(relative_accuracy)
+            .store(StoreType::CollapsingLowestDense(max_num_bins))
+            .build()
     }
 
     pub fn collapsing_highest_dense(
         relative_accuracy: f64,
         max_num_bins: usize,
     ) -> Result<DDSketch, Error> {
-        let index_mapping = IndexMapping::with_relative_accuracy(LogCubic, relative_accuracy)?;
-        let negative_value_store = CollapsingHighestDenseStore::with_capacity(max_num_bins)?;
-        let positive_value_store = CollapsingHighestDenseStore::with_capacity(max_num_bins)?;
-        let min_indexed_value = f64::max(0.0, index_mapping.min_indexable_value());
-        let max_indexed_value = index_mapping.max_indexable_value();
-        let zero_count = 0.0;
-        Ok(DDSketch {
-            index_mapping,
-            negative_value_store: Box::new(negative_value_store),
-            positive_value_store: Box::new(positive_value_store),
-            min_indexed_value,
-            max_indexed_value,
-            zero_count,
-        })
+        DDSketchBuilder::new(relative_accuracy)
+            .store(StoreType::CollapsingHighestDense(max_num_bins))
+            .build()
     }
 
     pub fn unbounded_dense(relative_accuracy: f64) -> Result<DDSketch, Error> {
-        let index_mapping = IndexMapping::with_relative_accuracy(LogCubic, relative_accuracy)?;
-        let negative_value_store = UnboundedSizeDenseStore::new();
-        let positive_value_store = UnboundedSizeDenseStore::new();
-        let min_indexed_value = f64::max(0.0, index_mapping.min_indexable_value());
-        let max_indexed_value = index_mapping.max_indexable_value();
-        let zero_count = 0.0;
-        Ok(DDSketch {
-            index_mapping,
-            negative_value_store: Box::new(negative_value_store),
-            positive_value_store: Box::new(positive_value_store),
-            min_indexed_value,
-            max_indexed_value,
-            zero_count,
-        })
+        DDSketchBuilder::new(relative_accuracy)
+            .store(StoreType::UnboundedDense)
+            .build()
     }
 
     pub fn logarithmic_collapsing_lowest_dense(
         relative_accuracy: f64,
         max_num_bins: usize,
     ) -> Result<DDSketch, Error> {
-        let index_mapping = IndexMapping::with_relative_accuracy(LOG, relative_accuracy)?;
-        let negative_value_store = CollapsingLowestDenseStore::with_capacity(max_num_bins)?;
-        let positive_value_store = CollapsingLowestDenseStore::with_capacity(max_num_bins)?;
-        let min_indexed_value = f64::max(0.0, index_mapping.min_indexable_value());
-        let max_indexed_value = index_mapping.max_indexable_value();
-        let zero_count = 0.0;
-        Ok(DDSketch {
-            index_mapping,
-            negative_value_store: Box::new(negative_value_store),
-            positive_value_store: Box::new(positive_value_store),
-            min_indexed_value,
-            max_indexed_value,
-            zero_count,
-        })
+        DDSketchBuilder::new(relative_accuracy)
+            .layout(LOG)
+            .store(StoreType::CollapsingLowestDense(max_num_bins))
+            .build()
     }
 
     pub fn logarithmic_collapsing_highest_dense(
         relative_accuracy: f64,
         max_num_bins: usize,
     ) -> Result<DDSketch, Error> {
-        let index_mapping = IndexMapping::with_relative_accuracy(LOG, relative_accuracy)?;
-        let negative_value_store = CollapsingHighestDenseStore::with_capacity(max_num_bins)?;
-        let positive_value_store = CollapsingHighestDenseStore::with_capacity(max_num_bins)?;
-        let min_indexed_value = f64::max(0.0, index_mapping.min_indexable_value());
-        let max_indexed_value = index_mapping.max_indexable_value();
-        let zero_count = 0.0;
-        Ok(DDSketch {
-            index_mapping,
-            negative_value_store: Box::new(negative_value_store),
-            positive_value_store: Box::new(positive_value_store),
-            min_indexed_value,
-            max_indexed_value,
-            zero_count,
-        })
+        DDSketchBuilder::new(relative_accuracy)
+            .layout(LOG)
+            .store(StoreType::CollapsingHighestDense(max_num_bins))
+            .build()
     }
 
     pub fn logarithmic_unbounded_size_dense_store(
         relative_accuracy: f64,
     ) -> Result<DDSketch, Error> {
-        let index_mapping = IndexMapping::with_relative_accuracy(LOG, relative_accuracy)?;
-        let negative_value_store = UnboundedSizeDenseStore::new();
-        let positive_value_store = UnboundedSizeDenseStore::new();
-        let min_indexed_value = f64::max(0.0, index_mapping.min_indexable_value());
+        DDSketchBuilder::new(relative_accuracy)
+            .layout(LOG)
+            .store(StoreType::UnboundedDense)
+            .build()
+    }
+
+    pub(crate) fn with_stores(
+        index_mapping: IndexMapping,
+        negative_value_store: Box<dyn Store>,
+        positive_value_store: Box<dyn Store>,
+        zero_threshold: f64,
+        out_of_range_policy: OutOfRangePolicy,
+        exact_summary_statistics: bool,
+        extensions: Extensions,
+    ) -> DDSketch {
+        let min_indexed_value = f64::max(zero_threshold, index_mapping.min_indexable_value());
         let max_indexed_value = index_mapping.max_indexable_value();
-        let zero_count = 0.0;
-        Ok(DDSketch {
+        DDSketch {
             index_mapping,
-            negative_value_store: Box::new(negative_value_store),
-            positive_value_store: Box::new(positive_value_store),
+            negative_value_store,
+            positive_value_store,
+            zero_threshold,
             min_indexed_value,
             max_indexed_value,
+            zero_count: 0.0,
+            out_of_range_policy,
+            summary_statistics: exact_summary_statistics.then(SummaryStatistics::new),
+            value_counters: ValueCounters::default(),
+            extensions,
+        }
+    }
+}
+
+// accessors for the other encodings
+impl DDSketch {
+    pub(crate) fn get_negative_value_store(&self) -> &dyn Store {
+        self.negative_value_store.as_ref()
+    }
+
+    pub(crate) fn get_positive_value_store(&self) -> &dyn Store {
+        self.positive_value_store.as_ref()
+    }
+
+    pub(crate) fn get_zero_count(&self) -> f64 {
+        self.zero_count
+    }
+
+    /// Creates a sketch holding what an encoding holds, with unbounded stores as `decode` does.
+    pub(crate) fn from_encoded_parts(
+        index_mapping: IndexMapping,
+        zero_threshold: f64,
+        zero_count: f64,
+        negative_bins: &[(i32, f64)],
+        positive_bins: &[(i32, f64)],
+        value_counters: Option<(f64, f64, f64)>,
+        limits: &DecodeLimits,
+    ) -> Result<DDSketch, Error> {
+        if !zero_threshold.is_finite() || zero_threshold < 0.0 {
+            return Err(Error::InvalidArgument("Invalid zero threshold."));
+        }
+        let is_valid_count = |count: f64| count.is_finite() && count >= 0.0;
+        let mut counts = negative_bins
+            .iter()
+            .chain(positive_bins.iter())
+            .map(|bin| bin.1)
+            .chain(Some(zero_count));
+        if !counts.all(is_valid_count) {
+            return Err(Error::InvalidArgument(
+                "The counts must be finite and non-negative.",
+            ));
+        }
+        limits.check_num_bins(negative_bins.len() + positive_bins.len())?;
+        for bins in [negative_bins, positive_bins] {
+            for bin in bins {
+                store::decoded_index(bin.0 as i64)?;
+            }
+            let min_index = bins.iter().map(|bin| bin.0).min();
+            let max_index = bins.iter().map(|bin| bin.0).max();
+            if let (Some(min_index), Some(max_index)) = (min_index, max_index) {
+                limits.check_index_span(min_index, max_index)?;
+            }
+        }
+        let mut negative_value_store = UnboundedSizeDenseStore::new();
+        let mut positive_value_store = UnboundedSizeDenseStore::new();
+        for bin in negative_bins {
+            negative_value_store.add_bin(*bin);
+        }
+        for bin in positive_bins {
+            positive_value_store.add_bin(*bin);
+        }
+        Ok(DDSketch::from_decoded_stores(
+            index_mapping,
+            zero_threshold,
             zero_count,
-        })
+            negative_value_store,
+            positive_value_store,
+            value_counters,
+        ))
+    }
+
+    /// Creates a sketch from decoded stores and counts that have already been checked.
+    pub(crate) fn from_decoded_stores(
+        index_mapping: IndexMapping,
+        zero_threshold: f64,
+        zero_count: f64,
+        negative_value_store: UnboundedSizeDenseStore,
+        positive_value_store: UnboundedSizeDenseStore,
+        value_counters: Option<(f64, f64, f64)>,
+    ) -> DDSketch {
+        let mut sketch = DDSketch::with_stores(
+            index_mapping,
+            Box::new(negative_value_store),
+            Box::new(positive_value_store),
+            0.0,
+            OutOfRangePolicy::default(),
+            false,
+            Extensions {
+                zero_threshold: zero_threshold > 0.0,
+                value_counters: value_counters.is_some(),
+                ..Extensions::default()
+            },
+        );
+        sketch.zero_count = zero_count;
+        if let Some((dropped, nan, infinite)) = value_counters {
+            sketch.value_counters = ValueCounters {
+                dropped,
+                nan,
+                infinite,
+            };
+        }
+        sketch.reconcile_zero_threshold(zero_threshold);
+        sketch
+    }
+}
+
+#[cfg(feature = "serde")]
+impl DDSketch {
+    /// Returns the zero threshold if it is part of the encoding.
+    pub(crate) fn get_encoded_zero_threshold(&self) -> Option<f64> {
+        (self.extensions.zero_threshold && self.zero_threshold > 0.0).then_some(self.zero_threshold)
+    }
+
+    /// Returns the dropped, NaN and infinite counts if they are part of the encoding.
+    pub(crate) fn get_encoded_value_counters(&self) -> Option<(f64, f64, f64)> {
+        self.extensions.value_counters.then_some((
+            self.value_counters.dropped,
+            self.value_counters.nan,
+            self.value_counters.infinite,
+        ))
+    }
+}
+
+impl ValueCounters {
+    fn merge_with(&mut self, other: &ValueCounters) {
+        self.dropped += other.dropped;
+        self.nan += other.nan;
+        self.infinite += other.infinite;
+    }
+
+    fn multiply(&mut self, factor: f64) {
+        self.dropped *= factor;
+        self.nan *= factor;
+        self.infinite *= factor;
+    }
+
+    fn encoded_len(&self) -> usize {
+        [self.dropped, self.nan, self.infinite]
+            .iter()
+            .map(|count| 1 + serde::var_double_encoded_length(*count) as usize)
+            .sum()
+    }
+
+    fn encode(&self, output: &mut Output) -> Result<(), Error> {
+        Flag::DROPPED_COUNT.encode(output)?;
+        serde::encode_var_double(output, self.dropped)?;
+        Flag::NAN_COUNT.encode(output)?;
+        serde::encode_var_double(output, self.nan)?;
+        Flag::INFINITE_COUNT.encode(output)?;
+        serde::encode_var_double(output, self.infinite)
+    }
+
+    /// Decodes the counter that `flag` introduces and adds it, or returns `false` if `flag`
+    /// does not introduce a counter.
+    fn decode_and_add(&mut self, flag: &Flag, input: &mut Input) -> Result<bool, Error> {
+        let counter = if Flag::DROPPED_COUNT == *flag {
+            &mut self.dropped
+        } else if Flag::NAN_COUNT == *flag {
+            &mut self.nan
+        } else if Flag::INFINITE_COUNT == *flag {
+            &mut self.infinite
+        } else {
+            return Ok(false);
+        };
+        *counter += serde::decode_var_double(input)?;
+        Ok(true)
+    }
+}
+
+fn decode_zero_threshold(input: &mut Input) -> Result<f64, Error> {
+    let offset = input.position();
+    let zero_threshold = input.read_double_le()?;
+    if !zero_threshold.is_finite() || zero_threshold < 0.0 {
+        return Err(Error::InvalidEncoding {
+            offset,
+            flag: Some(Flag::ZERO_THRESHOLD.get_marker()),
+            reason: "Invalid zero threshold.",
+        });
+    }
+    Ok(zero_threshold)
+}
+
+/// Decodes the parameters of an index mapping, which follow its flag.
+fn decode_index_mapping(input: &mut Input, flag: &Flag) -> Result<IndexMapping, Error> {
+    let layout = IndexMappingLayout::of_flag(flag)?;
+    let offset = input.position();
+    let gamma = input.read_double_le()?;
+    let index_offset = input.read_double_le()?;
+    IndexMapping::with_checked_gamma_offset(layout, gamma, index_offset)
+        .map_err(|error| error.at(offset, Some(flag.get_marker())))
+}
+
+/// Reads the length written by `DDSketch::encode_length_prefixed_to` from `reader`, then has
+/// `decode` read the sketch that follows, through a buffer that doesn't read past its end.
+fn read_length_prefixed<R, T, F>(mut reader: R, decode: F) -> Result<T, Error>
+where
+    R: Read,
+    F: FnOnce(&mut Input) -> Result<T, Error>,
+{
+    // The length is read one byte at a time, not to read past it.
+    let mut length_reader = BufReader::with_capacity(1, &mut reader);
+    let mut length_input = Input::from_reader(&mut length_reader);
+    let len = serde::decode_unsigned_var_long(&mut length_input)?;
+    let offset = length_input.position();
+    let len = u64::try_from(len).map_err(|_| Error::InvalidEncoding {
+        offset: 0,
+        flag: None,
+        reason: "The length of the sketch is negative.",
+    })?;
+
+    let mut sketch_reader = BufReader::new(reader.take(len));
+    let mut input = Input::from_reader(&mut sketch_reader);
+    let decoded = decode(&mut input)?;
+    let read = input.position();
+    if (read as u64) < len {
+        return Err(Error::Truncated {
+            offset: offset + read,
+        });
+    }
+    Ok(decoded)
+}
+
+/// The error of an encoding without index mapping, `offset` being its end.
+fn no_index_mapping(offset: usize) -> Error {
+    Error::InvalidEncoding {
+        offset,
+        flag: None,
+        reason: "No IndexMapping decoded.",
+    }
+}
+
+/// What an encoded sketch is made of, as reported by `visit_encoded`.
+pub(crate) enum EncodedItem {
+    IndexMapping(IndexMapping),
+    ZeroThreshold(f64),
+    ZeroCount(f64),
+    ValueCounters(ValueCounters),
+    NegativeBin(i32, f64),
+    PositiveBin(i32, f64),
+}
+
+/// Reads an encoded sketch through within `limits`, passing its mapping, zero count and
+/// non-empty bins to `visit`.
+pub(crate) fn visit_encoded<F>(
+    bytes: &[u8],
+    limits: &DecodeLimits,
+    mut visit: F,
+) -> Result<(), Error>
+where
+    F: FnMut(EncodedItem) -> Result<(), Error>,
+{
+    let mut input = Input::wrap(bytes);
+    let mut num_bins = 0;
+    while input.has_remaining() {
+        let flag = Flag::decode(&mut input)?;
+        match flag.get_type()? {
+            FlagType::PositiveStore => {
+                let mode = BinEncodingMode::of_flag(flag.get_marker())?;
+                store::decode_bins(&mut input, mode, |index, count| {
+                    num_bins += 1;
+                    limits.check_num_bins(num_bins)?;
+                    if count > 0.0 {
+                        visit(EncodedItem::PositiveBin(index, count))?;
+                    }
+                    Ok(())
+                })?;
+            }
+            FlagType::NegativeStore => {
+                let mode = BinEncodingMode::of_flag(flag.get_marker())?;
+                store::decode_bins(&mut input, mode, |index, count| {
+                    num_bins += 1;
+                    limits.check_num_bins(num_bins)?;
+                    if count > 0.0 {
+                        visit(EncodedItem::NegativeBin(index, count))?;
+                    }
+                    Ok(())
+                })?;
+            }
+            FlagType::IndexMapping => {
+                let index_mapping = decode_index_mapping(&mut input, &flag)?;
+                visit(EncodedItem::IndexMapping(index_mapping))?;
+            }
+            FlagType::SketchFeatures => {
+                if Flag::ZERO_COUNT == flag {
+                    let zero_count = serde::decode_var_double(&mut input)?;
+                    visit(EncodedItem::ZeroCount(zero_count))?;
+                } else if Flag::ZERO_THRESHOLD == flag {
+                    let zero_threshold = decode_zero_threshold(&mut input)?;
+                    visit(EncodedItem::ZeroThreshold(zero_threshold))?;
+                } else {
+                    let mut value_counters = ValueCounters::default();
+                    if value_counters.decode_and_add(&flag, &mut input)? {
+                        visit(EncodedItem::ValueCounters(value_counters))?;
+                    } else {
+                        serde::ignore_exact_summary_statistic_flags(&mut input, flag)?;
+                    }
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The counts of one sign of a k-way merge, laid out densely over the union of the index
+/// ranges of the merged sketches.
+struct DenseCounts {
+    min_index: i32,
+    max_index: i32,
+    counts: Vec<f64>,
+}
+
+impl DenseCounts {
+    fn new() -> DenseCounts {
+        DenseCounts {
+            min_index: i32::MAX,
+            max_index: i32::MIN,
+            counts: Vec::new(),
+        }
+    }
+
+    fn extend_range(&mut self, index: i32) {
+        self.min_index = self.min_index.min(index);
+        self.max_index = self.max_index.max(index);
+    }
+
+    fn extend_to_store(&mut self, store: &dyn Store) {
+        if !store.is_empty() {
+            self.extend_range(store.get_min_index());
+            self.extend_range(store.get_max_index());
+        }
+    }
+
+    fn allocate(&mut self, limits: &DecodeLimits) -> Result<(), Error> {
+        if self.min_index <= self.max_index {
+            limits.check_index_span(self.min_index, self.max_index)?;
+            let length = (self.max_index as i64 - self.min_index as i64 + 1) as usize;
+            self.counts = vec![0.0; length];
+        }
+        Ok(())
+    }
+
+    fn add(&mut self, index: i32, count: f64) {
+        self.counts[(index as i64 - self.min_index as i64) as usize] += count;
+    }
+
+    fn into_store(self) -> Box<dyn Store> {
+        Box::new(UnboundedSizeDenseStore::with_counts(
+            self.min_index,
+            self.counts,
+        ))
     }
 }
 
@@ -419,6 +1742,10 @@ impl Flag {
     pub const SUM: Flag = Flag::with_type(FlagType::SketchFeatures, 0x21);
     pub const MIN: Flag = Flag::with_type(FlagType::SketchFeatures, 0x22);
     pub const MAX: Flag = Flag::with_type(FlagType::SketchFeatures, 0x23);
+    pub const ZERO_THRESHOLD: Flag = Flag::with_type(FlagType::SketchFeatures, 0x24);
+    pub const DROPPED_COUNT: Flag = Flag::with_type(FlagType::SketchFeatures, 0x25);
+    pub const NAN_COUNT: Flag = Flag::with_type(FlagType::SketchFeatures, 0x26);
+    pub const INFINITE_COUNT: Flag = Flag::with_type(FlagType::SketchFeatures, 0x27);
 
     pub const fn new(marker: u8) -> Flag {
         Flag { marker }
@@ -454,7 +1781,7 @@ impl FlagType {
             0b01 => Ok(FlagType::PositiveStore),
             0b10 => Ok(FlagType::IndexMapping),
             0b11 => Ok(FlagType::NegativeStore),
-            _ => Err(Error::InvalidArgument("Unknown FlagType.")),
+            _ => Err(Error::UnknownFlag(t)),
         }
     }
 }
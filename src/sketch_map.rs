@@ -0,0 +1,317 @@
+use crate::builder::DDSketchBuilder;
+use crate::error::Error;
+use crate::index_mapping::IndexMapping;
+use crate::input::Input;
+use crate::limits::DecodeLimits;
+use crate::output::Output;
+use crate::serde;
+use crate::sketch::DDSketch;
+use std::collections::hash_map::Entry;
+use std::collections::{BTreeMap, HashMap};
+use std::hash::Hash;
+
+/// What a `SketchMap` does with the values of a new key once it holds the maximum number of
+/// keys.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum KeyOverflowPolicy {
+    /// Evict the key whose sketch has the lowest count to make room for the new one, the
+    /// oldest key among those of equal counts. The counts of the evicted sketches are summed,
+    /// see `SketchMap::get_evicted_count`.
+    #[default]
+    EvictSmallest,
+    /// Record the values into a single overflow sketch shared by all the keys left out.
+    OverflowBucket,
+}
+
+/// A collection of sketches indexed by key, all of them created from the same builder and
+/// therefore sharing one mapping and store configuration.
+///
+/// The number of keys can be capped to bound the memory used by the collection. Once the cap
+/// is reached, the values of a new key either evict the smallest sketch or go to an overflow
+/// sketch, according to the `KeyOverflowPolicy`.
+///
+/// ```rust
+///     use sketches_rust::{DDSketch, KeyOverflowPolicy, SketchMap};
+///     let mut m = SketchMap::with_max_keys(
+///         DDSketch::builder(0.02),
+///         2,
+///         KeyOverflowPolicy::OverflowBucket,
+///     )
+///     .unwrap();
+///     m.accept("a", 1.0);
+///     m.accept("b", 2.0);
+///     m.accept("c", 3.0);
+///     assert_eq!(m.len(), 2);
+///     assert_eq!(m.get_overflow().unwrap().get_count(), 1.0);
+/// ```
+pub struct SketchMap<K> {
+    builder: DDSketchBuilder,
+    index_mapping: IndexMapping,
+    sketches: HashMap<K, KeyedSketch>,
+    /// The keys ordered by the count of their sketch, then by insertion, kept only when the
+    /// smallest sketches are evicted.
+    keys_by_count: BTreeMap<(u64, u64), K>,
+    next_id: u64,
+    max_keys: Option<usize>,
+    key_overflow_policy: KeyOverflowPolicy,
+    overflow: Option<DDSketch>,
+    evicted_count: f64,
+}
+
+struct KeyedSketch {
+    id: u64,
+    sketch: DDSketch,
+}
+
+impl KeyedSketch {
+    /// The position of the sketch in `SketchMap::keys_by_count`. The bits of the count are
+    /// mapped so that they are ordered like the counts, negative ones included.
+    fn rank(&self) -> (u64, u64) {
+        let bits = self.sketch.get_count().to_bits();
+        let ordered_bits = if bits >> 63 == 1 {
+            !bits
+        } else {
+            bits | 1 << 63
+        };
+        (ordered_bits, self.id)
+    }
+}
+
+impl<K: Eq + Hash + Clone> SketchMap<K> {
+    pub fn new(builder: DDSketchBuilder) -> Result<SketchMap<K>, Error> {
+        SketchMap::with_limit(builder, None, KeyOverflowPolicy::default())
+    }
+
+    pub fn with_max_keys(
+        builder: DDSketchBuilder,
+        max_keys: usize,
+        key_overflow_policy: KeyOverflowPolicy,
+    ) -> Result<SketchMap<K>, Error> {
+        if max_keys == 0 {
+            return Err(Error::InvalidArgument(
+                "The maximum number of keys must be positive.",
+            ));
+        }
+        SketchMap::with_limit(builder, Some(max_keys), key_overflow_policy)
+    }
+
+    fn with_limit(
+        builder: DDSketchBuilder,
+        max_keys: Option<usize>,
+        key_overflow_policy: KeyOverflowPolicy,
+    ) -> Result<SketchMap<K>, Error> {
+        let index_mapping = builder.build()?.get_index_mapping().clone();
+        Ok(SketchMap {
+            builder,
+            index_mapping,
+            sketches: HashMap::new(),
+            keys_by_count: BTreeMap::new(),
+            next_id: 0,
+            max_keys,
+            key_overflow_policy,
+            overflow: None,
+            evicted_count: 0.0,
+        })
+    }
+
+    pub fn accept(&mut self, key: K, value: f64) {
+        self.accept_with_count(key, value, 1.0);
+    }
+
+    pub fn accept_with_count(&mut self, key: K, value: f64, count: f64) {
+        self.update(key, |sketch| sketch.accept_with_count(value, count));
+    }
+
+    /// Merges the sketches of `other` into the sketches of the same keys, the keys missing from
+    /// this map being added subject to its own cap.
+    pub fn merge_with(&mut self, other: &SketchMap<K>) -> Result<(), Error> {
+        if self.index_mapping != other.index_mapping {
+            return Err(Error::UnmatchedMapping {
+                expected: self.index_mapping.clone(),
+                actual: other.index_mapping.clone(),
+            });
+        }
+        for (key, sketch) in other.iter() {
+            self.update(key.clone(), |merged| merged.merge_with_same_mapping(sketch));
+        }
+        if let Some(overflow) = &other.overflow {
+            self.get_or_insert_overflow()
+                .merge_with_same_mapping(overflow);
+        }
+        self.evicted_count += other.evicted_count;
+        Ok(())
+    }
+
+    pub fn get(&self, key: &K) -> Option<&DDSketch> {
+        self.sketches.get(key).map(|keyed| &keyed.sketch)
+    }
+
+    /// Returns the sketch of the values whose keys did not fit in the map, if any.
+    pub fn get_overflow(&self) -> Option<&DDSketch> {
+        self.overflow.as_ref()
+    }
+
+    /// Returns the total count of the values of the sketches evicted to make room for new
+    /// keys.
+    pub fn get_evicted_count(&self) -> f64 {
+        self.evicted_count
+    }
+
+    pub fn remove(&mut self, key: &K) -> Option<DDSketch> {
+        let keyed = self.sketches.remove(key)?;
+        self.keys_by_count.remove(&keyed.rank());
+        Some(keyed.sketch)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &DDSketch)> + '_ {
+        self.sketches
+            .iter()
+            .map(|(key, keyed)| (key, &keyed.sketch))
+    }
+
+    pub fn len(&self) -> usize {
+        self.sketches.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sketches.is_empty() && self.overflow.is_none()
+    }
+
+    pub fn clear(&mut self) {
+        self.sketches.clear();
+        self.keys_by_count.clear();
+        self.overflow = None;
+        self.evicted_count = 0.0;
+    }
+
+    /// Encodes all the sketches into one buffer, the keys being encoded with `encode_key`.
+    ///
+    /// The buffer holds the number of keys, then for each of them the length-prefixed key and
+    /// sketch, and finally the overflow sketch, if any.
+    pub fn encode<F>(&self, mut encode_key: F) -> Result<Vec<u8>, Error>
+    where
+        F: FnMut(&K) -> Vec<u8>,
+    {
+        let mut output = Output::with_capacity(64 * (self.sketches.len() + 1));
+        serde::encode_unsigned_var_long(&mut output, self.sketches.len() as i64)?;
+        for (key, sketch) in self.iter() {
+            write_length_prefixed(&mut output, &encode_key(key))?;
+            write_length_prefixed(&mut output, &sketch.encode()?)?;
+        }
+        match &self.overflow {
+            Some(overflow) => {
+                output.write_byte(1)?;
+                write_length_prefixed(&mut output, &overflow.encode()?)?;
+            }
+            None => output.write_byte(0)?,
+        }
+        Ok(output.trim())
+    }
+
+    /// Decodes a buffer produced by `encode` and merges its sketches into this map, the keys
+    /// being decoded with `decode_key`. The whole buffer is decoded before any sketch is
+    /// merged, so the map is left as it was if the buffer is invalid.
+    pub fn decode_and_merge_with<F>(&mut self, bytes: &[u8], mut decode_key: F) -> Result<(), Error>
+    where
+        F: FnMut(&[u8]) -> Result<K, Error>,
+    {
+        let mut input = Input::wrap(bytes);
+        let num_keys = serde::decode_unsigned_var_long(&mut input)?;
+        let mut sketches = Vec::new();
+        for _ in 0..num_keys {
+            let key_len = read_length(&mut input)?;
+            let key = decode_key(&input.read_bytes(key_len)?)?;
+            sketches.push((key, self.decode_length_prefixed(&mut input)?));
+        }
+        let overflow = match input.read_byte()? {
+            0 => None,
+            _ => Some(self.decode_length_prefixed(&mut input)?),
+        };
+
+        for (key, sketch) in sketches {
+            self.update(key, |merged| merged.merge_with_same_mapping(&sketch));
+        }
+        if let Some(overflow) = overflow {
+            self.get_or_insert_overflow()
+                .merge_with_same_mapping(&overflow);
+        }
+        Ok(())
+    }
+
+    fn decode_length_prefixed(&self, input: &mut Input) -> Result<DDSketch, Error> {
+        let sketch_len = read_length(input)?;
+        let mut sketch = self.builder.build_validated();
+        sketch
+            .decode_and_merge_with_input(&mut input.take(sketch_len)?, &DecodeLimits::default())?;
+        Ok(sketch)
+    }
+
+    /// Applies `update` to the sketch of `key`, inserting it if needed, and keeps the keys
+    /// ordered by count.
+    fn update<F>(&mut self, key: K, update: F)
+    where
+        F: FnOnce(&mut DDSketch),
+    {
+        let is_full = self
+            .max_keys
+            .is_some_and(|max_keys| self.sketches.len() >= max_keys);
+        if is_full && !self.sketches.contains_key(&key) {
+            match self.key_overflow_policy {
+                KeyOverflowPolicy::EvictSmallest => self.evict_smallest(),
+                KeyOverflowPolicy::OverflowBucket => return update(self.get_or_insert_overflow()),
+            }
+        }
+        let is_evicting =
+            self.max_keys.is_some() && self.key_overflow_policy == KeyOverflowPolicy::EvictSmallest;
+        let keyed = match self.sketches.entry(key) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => {
+                self.next_id += 1;
+                let keyed = KeyedSketch {
+                    id: self.next_id,
+                    sketch: self.builder.build_validated(),
+                };
+                if is_evicting {
+                    self.keys_by_count.insert(keyed.rank(), entry.key().clone());
+                }
+                entry.insert(keyed)
+            }
+        };
+        let rank = keyed.rank();
+        update(&mut keyed.sketch);
+        if is_evicting && keyed.rank() != rank {
+            if let Some(key) = self.keys_by_count.remove(&rank) {
+                self.keys_by_count.insert(keyed.rank(), key);
+            }
+        }
+    }
+
+    fn get_or_insert_overflow(&mut self) -> &mut DDSketch {
+        let builder = &self.builder;
+        self.overflow
+            .get_or_insert_with(|| builder.build_validated())
+    }
+
+    fn evict_smallest(&mut self) {
+        if let Some((_, key)) = self.keys_by_count.pop_first() {
+            if let Some(keyed) = self.sketches.remove(&key) {
+                self.evicted_count += keyed.sketch.get_count();
+            }
+        }
+    }
+}
+
+fn write_length_prefixed(output: &mut Output, bytes: &[u8]) -> Result<(), Error> {
+    serde::encode_unsigned_var_long(output, bytes.len() as i64)?;
+    output.write_bytes(bytes)
+}
+
+fn read_length(input: &mut Input) -> Result<usize, Error> {
+    let offset = input.position();
+    let len = serde::decode_unsigned_var_long(input)?;
+    usize::try_from(len).map_err(|_| Error::InvalidEncoding {
+        offset,
+        flag: None,
+        reason: "Invalid length.",
+    })
+}
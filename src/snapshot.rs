@@ -0,0 +1,95 @@
+use std::sync::Arc;
+
+/// A frozen, query-optimized view of a `DDSketch`.
+///
+/// The representative values of the non-empty bins are laid out in ascending order, negative
+/// bins first, then the zero bucket and the positive bins, along with their cumulative counts,
+/// so that quantiles and ranks are answered by binary search. Cloning a snapshot is cheap: the
+/// bins are shared between the clones.
+#[derive(Clone, Debug)]
+pub struct DDSketchSnapshot {
+    values: Arc<[f64]>,
+    cumulative_counts: Arc<[f64]>,
+    sum: Option<f64>,
+    min: Option<f64>,
+    max: Option<f64>,
+}
+
+impl DDSketchSnapshot {
+    pub(crate) fn new(
+        bins: impl Iterator<Item = (f64, f64)>,
+        sum: Option<f64>,
+        min: Option<f64>,
+        max: Option<f64>,
+    ) -> DDSketchSnapshot {
+        let mut values = Vec::new();
+        let mut cumulative_counts = Vec::new();
+        let mut n = 0.0;
+        for (value, count) in bins {
+            n += count;
+            values.push(value);
+            cumulative_counts.push(n);
+        }
+        DDSketchSnapshot {
+            values: values.into(),
+            cumulative_counts: cumulative_counts.into(),
+            sum,
+            min,
+            max,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    pub fn get_count(&self) -> f64 {
+        self.cumulative_counts.last().copied().unwrap_or(0.0)
+    }
+
+    pub fn get_sum(&self) -> Option<f64> {
+        self.sum
+    }
+
+    pub fn get_min(&self) -> Option<f64> {
+        self.min
+    }
+
+    pub fn get_max(&self) -> Option<f64> {
+        self.max
+    }
+
+    pub fn get_average(&self) -> Option<f64> {
+        Some(self.sum? / self.get_count())
+    }
+
+    pub fn get_value_at_quantile(&self, quantile: f64) -> Option<f64> {
+        if !(0.0..=1.0).contains(&quantile) {
+            return None;
+        }
+
+        let count = self.get_count();
+        if count <= 0.0 {
+            return None;
+        }
+
+        let rank = quantile * (count - 1.0);
+        let index = self.cumulative_counts.partition_point(|n| *n <= rank);
+        self.values.get(index).copied()
+    }
+
+    /// Returns the fraction of the values whose bin representative is lower than or equal to
+    /// `value`.
+    pub fn get_rank(&self, value: f64) -> Option<f64> {
+        let count = self.get_count();
+        if count <= 0.0 || value.is_nan() {
+            return None;
+        }
+
+        let index = self.values.partition_point(|v| *v <= value);
+        if index == 0 {
+            return Some(0.0);
+        }
+        Some(self.cumulative_counts[index - 1] / count)
+    }
+}
@@ -0,0 +1,241 @@
+use super::*;
+use crate::limits::DecodeLimits;
+use crate::sketch::{DDSketch, Extensions, OutOfRangePolicy};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// A fixed-range store whose bins are atomic counters, so that values can be recorded from many
+/// threads through a shared reference without any lock.
+///
+/// The store is pre-sized to the bins of the mapping covering a configured value range. Positive
+/// values below the range are collapsed into the lowest bin, and values above the range into the
+/// highest bin. Zeros are counted apart and exported as the zero count of the sketch, while
+/// negative, infinite and NaN values are dropped and exported as dropped values, as a sketch
+/// with the default out-of-range policy does.
+pub struct AtomicDenseStore {
+    index_mapping: IndexMapping,
+    counts: Box<[AtomicU64]>,
+    min_index: i32,
+    max_index: i32,
+    zero_count: AtomicU64,
+    dropped_count: AtomicU64,
+    nan_count: AtomicU64,
+    infinite_count: AtomicU64,
+}
+
+impl AtomicDenseStore {
+    /// Creates a store covering the range of values from `min_value` to `max_value`, which may
+    /// span as many bins as the default `DecodeLimits` allow in a store.
+    pub fn with_value_range(
+        index_mapping: IndexMapping,
+        min_value: f64,
+        max_value: f64,
+    ) -> Result<Self, Error> {
+        AtomicDenseStore::with_value_range_and_limits(
+            index_mapping,
+            min_value,
+            max_value,
+            &DecodeLimits::default(),
+        )
+    }
+
+    /// Creates a store covering the range of values from `min_value` to `max_value`, whose bins
+    /// span at most the index span of `limits`.
+    pub fn with_value_range_and_limits(
+        index_mapping: IndexMapping,
+        min_value: f64,
+        max_value: f64,
+        limits: &DecodeLimits,
+    ) -> Result<Self, Error> {
+        if !(min_value > 0.0 && min_value <= max_value && max_value.is_finite()) {
+            return Err(Error::InvalidArgument(
+                "The value range must be positive, finite and not empty.",
+            ));
+        }
+        let min_index = index_mapping.index(min_value);
+        let max_index = index_mapping.index(max_value);
+        AtomicDenseStore::with_index_range(index_mapping, min_index, max_index, limits)
+    }
+
+    fn with_index_range(
+        index_mapping: IndexMapping,
+        min_index: i32,
+        max_index: i32,
+        limits: &DecodeLimits,
+    ) -> Result<Self, Error> {
+        limits.check_index_span(min_index, max_index)?;
+        let num_bins = max_index as i64 - min_index as i64 + 1;
+        let counts = (0..num_bins).map(|_| AtomicU64::new(0)).collect();
+        Ok(AtomicDenseStore {
+            index_mapping,
+            counts,
+            min_index,
+            max_index,
+            zero_count: AtomicU64::new(0),
+            dropped_count: AtomicU64::new(0),
+            nan_count: AtomicU64::new(0),
+            infinite_count: AtomicU64::new(0),
+        })
+    }
+
+    pub fn accept(&self, value: f64) {
+        self.accept_with_count(value, 1);
+    }
+
+    pub fn accept_with_count(&self, value: f64, count: u64) {
+        let _ = self.try_accept_with_count(value, count);
+    }
+
+    pub fn try_accept(&self, value: f64) -> Result<(), Error> {
+        self.try_accept_with_count(value, 1)
+    }
+
+    /// Records `value` with the weight `count`, and returns an error if the value is negative,
+    /// infinite or NaN, in which case it is counted as dropped.
+    pub fn try_accept_with_count(&self, value: f64, count: u64) -> Result<(), Error> {
+        if value > 0.0 && value.is_finite() {
+            self.add(self.index_mapping.index(value), count);
+            return Ok(());
+        }
+        if value == 0.0 {
+            self.zero_count.fetch_add(count, Ordering::Relaxed);
+            return Ok(());
+        }
+        self.dropped_count.fetch_add(count, Ordering::Relaxed);
+        if value.is_nan() {
+            self.nan_count.fetch_add(count, Ordering::Relaxed);
+            return Err(Error::InvalidArgument("The value is NaN."));
+        }
+        if value.is_infinite() {
+            self.infinite_count.fetch_add(count, Ordering::Relaxed);
+            return Err(Error::InvalidArgument(
+                "The value is out of the indexable range.",
+            ));
+        }
+        Err(Error::InvalidArgument(
+            "The value is negative, the store only covers positive values.",
+        ))
+    }
+
+    pub fn add(&self, index: i32, count: u64) {
+        let index = index.clamp(self.min_index, self.max_index);
+        self.counts[(index - self.min_index) as usize].fetch_add(count, Ordering::Relaxed);
+    }
+
+    pub fn get_index_mapping(&self) -> &IndexMapping {
+        &self.index_mapping
+    }
+
+    pub fn get_min_index(&self) -> i32 {
+        self.min_index
+    }
+
+    pub fn get_max_index(&self) -> i32 {
+        self.max_index
+    }
+
+    /// Returns the total count of the recorded values, zeros included.
+    pub fn get_total_count(&self) -> u64 {
+        self.zero_count.load(Ordering::Relaxed)
+            + self
+                .counts
+                .iter()
+                .map(|count| count.load(Ordering::Relaxed))
+                .sum::<u64>()
+    }
+
+    pub fn get_zero_count(&self) -> u64 {
+        self.zero_count.load(Ordering::Relaxed)
+    }
+
+    /// Returns the total count of the negative, infinite and NaN values, which are not recorded.
+    pub fn get_dropped_count(&self) -> u64 {
+        self.dropped_count.load(Ordering::Relaxed)
+    }
+
+    /// Moves the counts recorded so far into the positive store and the zero count of a new
+    /// sketch sharing the mapping of this store, along with the counts of the dropped values,
+    /// and resets them. Each counter is swapped atomically, so every value is exported exactly
+    /// once even if recording goes on concurrently.
+    pub fn snapshot_and_reset(&self) -> DDSketch {
+        let mut positive_value_store = UnboundedSizeDenseStore::new();
+        for (i, count) in self.counts.iter().enumerate() {
+            let count = count.swap(0, Ordering::Relaxed);
+            if count != 0 {
+                positive_value_store.add(self.min_index + i as i32, count as f64);
+            }
+        }
+        let mut sketch = DDSketch::with_stores(
+            self.index_mapping.clone(),
+            Box::new(UnboundedSizeDenseStore::new()),
+            Box::new(positive_value_store),
+            0.0,
+            OutOfRangePolicy::default(),
+            false,
+            Extensions::default(),
+        );
+        sketch.accept_with_count(0.0, self.zero_count.swap(0, Ordering::Relaxed) as f64);
+        sketch.add_dropped_counts(
+            self.dropped_count.swap(0, Ordering::Relaxed) as f64,
+            self.nan_count.swap(0, Ordering::Relaxed) as f64,
+            self.infinite_count.swap(0, Ordering::Relaxed) as f64,
+        );
+        sketch
+    }
+}
+
+#[cfg(feature = "serde")]
+impl AtomicDenseStore {
+    /// Returns the non-empty bins, each counter being read in turn.
+    pub(crate) fn get_bins(&self) -> Vec<(i32, u64)> {
+        self.counts
+            .iter()
+            .enumerate()
+            .map(|(i, count)| (self.min_index + i as i32, count.load(Ordering::Relaxed)))
+            .filter(|bin| bin.1 != 0)
+            .collect()
+    }
+
+    /// Returns the dropped, NaN and infinite counts.
+    pub(crate) fn get_dropped_counts(&self) -> (u64, u64, u64) {
+        (
+            self.dropped_count.load(Ordering::Relaxed),
+            self.nan_count.load(Ordering::Relaxed),
+            self.infinite_count.load(Ordering::Relaxed),
+        )
+    }
+
+    /// Creates a store covering the bins from `min_index` to `max_index` and holding `bins`,
+    /// which must lie in that range, and the given counts.
+    pub(crate) fn with_bins(
+        index_mapping: IndexMapping,
+        min_index: i32,
+        max_index: i32,
+        bins: &[(i32, u64)],
+        zero_count: u64,
+        (dropped, nan, infinite): (u64, u64, u64),
+    ) -> Result<Self, Error> {
+        if min_index > max_index
+            || bins
+                .iter()
+                .any(|bin| bin.0 < min_index || bin.0 > max_index)
+        {
+            return Err(Error::InvalidArgument(
+                "The bins must lie in the index range of the store.",
+            ));
+        }
+        let store = AtomicDenseStore::with_index_range(
+            index_mapping,
+            min_index,
+            max_index,
+            &DecodeLimits::default(),
+        )?;
+        for bin in bins {
+            store.add(bin.0, bin.1);
+        }
+        store.zero_count.store(zero_count, Ordering::Relaxed);
+        store.dropped_count.store(dropped, Ordering::Relaxed);
+        store.nan_count.store(nan, Ordering::Relaxed);
+        store.infinite_count.store(infinite, Ordering::Relaxed);
+        Ok(store)
+    }
+}
@@ -4,6 +4,7 @@ use crate::serde;
 #[derive(Clone)]
 pub struct CollapsingHighestDenseStore {
     counts: Vec<f64>,
+    total_count: f64,
     offset: i32,
     min_index: i32,
     max_index: i32,
@@ -25,6 +26,7 @@ impl CollapsingHighestDenseStore {
             max_num_bins,
             is_collapsed: false,
             counts: Vec::new(),
+            total_count: 0.0,
             offset: 0,
             min_index: i32::MAX,
             max_index: i32::MIN,
@@ -219,11 +221,9 @@ impl Store for CollapsingHighestDenseStore {
         }
 
         let array_index = serde::i32_to_usize_exact(self.normalize(index));
-        match array_index {
-            Ok(index) => {
-                self.counts[index] += count;
-            }
-            _ => {}
+        if let Ok(index) = array_index {
+            self.counts[index] += count;
+            self.total_count += count;
         }
     }
 
@@ -232,16 +232,15 @@ impl Store for CollapsingHighestDenseStore {
             return;
         }
         let array_index = serde::i32_to_usize_exact(self.normalize(bin.0));
-        match array_index {
-            Ok(index) => {
-                self.counts[index] += bin.1;
-            }
-            _ => {}
+        if let Ok(index) = array_index {
+            self.counts[index] += bin.1;
+            self.total_count += bin.1;
         }
     }
 
     fn clear(&mut self) {
         self.counts.fill(0.0);
+        self.total_count = 0.0;
         self.max_index = i32::MIN;
         self.min_index = i32::MAX;
         self.offset = 0;
@@ -253,7 +252,11 @@ impl Store for CollapsingHighestDenseStore {
     }
 
     fn get_total_count(&self) -> f64 {
-        self.get_total_count_with_range(self.min_index, self.max_index)
+        self.total_count
+    }
+
+    fn is_bounded(&self) -> bool {
+        true
     }
 
     fn get_offset(&self) -> i32 {
@@ -295,12 +298,12 @@ impl Store for CollapsingHighestDenseStore {
                 let bin = (index, value);
                 bins.push(bin);
             }
-            index -= 1;
+            index += 1;
         }
         bins
     }
 
-    fn get_descending_iter(&self) -> StoreIter {
+    fn get_descending_iter(&self) -> StoreIter<'_> {
         StoreIter::new(
             self.min_index,
             self.max_index,
@@ -310,7 +313,7 @@ impl Store for CollapsingHighestDenseStore {
         )
     }
 
-    fn get_ascending_iter(&self) -> StoreIter {
+    fn get_ascending_iter(&self) -> StoreIter<'_> {
         StoreIter::new(
             self.min_index,
             self.max_index,
@@ -4,6 +4,7 @@ use crate::serde;
 #[derive(Clone)]
 pub struct CollapsingLowestDenseStore {
     counts: Vec<f64>,
+    total_count: f64,
     offset: i32,
     min_index: i32,
     max_index: i32,
@@ -25,6 +26,7 @@ impl CollapsingLowestDenseStore {
             max_num_bins,
             is_collapsed: false,
             counts: Vec::new(),
+            total_count: 0.0,
             offset: 0,
             min_index: i32::MAX,
             max_index: i32::MIN,
@@ -218,11 +220,9 @@ impl Store for CollapsingLowestDenseStore {
         }
 
         let array_index = serde::i32_to_usize_exact(self.normalize(index));
-        match array_index {
-            Ok(index) => {
-                self.counts[index] += count;
-            }
-            _ => {}
+        if let Ok(index) = array_index {
+            self.counts[index] += count;
+            self.total_count += count;
         }
     }
 
@@ -231,16 +231,15 @@ impl Store for CollapsingLowestDenseStore {
             return;
         }
         let array_index = serde::i32_to_usize_exact(self.normalize(bin.0));
-        match array_index {
-            Ok(index) => {
-                self.counts[index] += bin.1;
-            }
-            _ => {}
+        if let Ok(index) = array_index {
+            self.counts[index] += bin.1;
+            self.total_count += bin.1;
         }
     }
 
     fn clear(&mut self) {
         self.counts.fill(0.0);
+        self.total_count = 0.0;
         self.max_index = i32::MIN;
         self.min_index = i32::MAX;
         self.offset = 0;
@@ -252,7 +251,7 @@ impl Store for CollapsingLowestDenseStore {
     }
 
     fn get_total_count(&self) -> f64 {
-        self.get_total_count_with_range(self.min_index, self.max_index)
+        self.total_count
     }
 
     fn get_min_index(&self) -> i32 {
@@ -263,6 +262,10 @@ impl Store for CollapsingLowestDenseStore {
         self.max_index
     }
 
+    fn is_bounded(&self) -> bool {
+        true
+    }
+
     fn get_offset(&self) -> i32 {
         self.offset
     }
@@ -294,12 +297,12 @@ impl Store for CollapsingLowestDenseStore {
                 let bin = (index, value);
                 bins.push(bin);
             }
-            index -= 1;
+            index += 1;
         }
         bins
     }
 
-    fn get_descending_iter(&self) -> StoreIter {
+    fn get_descending_iter(&self) -> StoreIter<'_> {
         StoreIter::new(
             self.min_index,
             self.max_index,
@@ -309,7 +312,7 @@ impl Store for CollapsingLowestDenseStore {
         )
     }
 
-    fn get_ascending_iter(&self) -> StoreIter {
+    fn get_ascending_iter(&self) -> StoreIter<'_> {
         StoreIter::new(
             self.min_index,
             self.max_index,
@@ -0,0 +1,440 @@
+//! The bin encodings of a store.
+//!
+//! The legacy encoding, which the other implementations produce, writes all the bins in a
+//! single block, either as contiguous counts or as index deltas and counts. It is the default.
+//! With the compact encoding, when it is strictly smaller, the bins are instead split into
+//! runs, each of them written in its own block with the smallest of the modes it allows: the
+//! runs of bins holding exactly one value, as raw unweighted samples produce, need no counts at
+//! all. The blocks are written in ascending order of index, and this crate merges them as they
+//! come, but the other implementations may not.
+
+use super::{BinEncodingMode, Store};
+use crate::error::Error;
+use crate::output::Output;
+use crate::serde;
+use crate::sketch::FlagType;
+
+pub(super) fn encode<S: Store + ?Sized>(
+    store: &S,
+    output: &mut Output,
+    store_flag_type: FlagType,
+    compact: bool,
+) -> Result<(), Error> {
+    if store.is_empty() {
+        return Ok(());
+    }
+    let (start, end) = array_range(store);
+    let legacy = Block::legacy(store, start, end);
+    if compact && multi_run_len(store) < legacy.len() {
+        let mut start = start;
+        while let Some(block) = Block::next(store, start, end) {
+            block.encode(store, output, store_flag_type)?;
+            start = block.end;
+        }
+        Ok(())
+    } else {
+        legacy.encode(store, output, store_flag_type)
+    }
+}
+
+/// Returns the number of bytes that `encode` writes.
+pub(super) fn encoded_len<S: Store + ?Sized>(store: &S, compact: bool) -> usize {
+    if store.is_empty() {
+        return 0;
+    }
+    let (start, end) = array_range(store);
+    let legacy_len = Block::legacy(store, start, end).len();
+    let len = if compact {
+        multi_run_len(store).min(legacy_len)
+    } else {
+        legacy_len
+    };
+    len as usize
+}
+
+fn multi_run_len<S: Store + ?Sized>(store: &S) -> i64 {
+    let (mut start, end) = array_range(store);
+    let mut len = 0;
+    while let Some(block) = Block::next(store, start, end) {
+        len += block.len();
+        start = block.end;
+    }
+    len
+}
+
+/// Returns the range of the array indexes of the bins of a non-empty store.
+fn array_range<S: Store + ?Sized>(store: &S) -> (i32, i32) {
+    let offset = store.get_offset();
+    (
+        store.get_min_index() - offset,
+        store.get_max_index() - offset + 1,
+    )
+}
+
+/// A block of bins, between two array indexes, and the mode it is encoded with.
+struct Block {
+    mode: BinEncodingMode,
+    start: i32,
+    end: i32,
+    sizes: BinEncodingSizes,
+}
+
+impl Block {
+    /// The single block of the legacy encoding, which picks the contiguous counts as soon as
+    /// their size doesn't exceed the size of the index deltas and counts, their number aside.
+    fn legacy<S: Store + ?Sized>(store: &S, start: i32, end: i32) -> Block {
+        let sizes = BinEncodingSizes::of(store, start, end);
+        let mode = if sizes.dense_encoding_size <= sizes.sparse_encoding_size {
+            BinEncodingMode::ContiguousCounts
+        } else {
+            BinEncodingMode::IndexDeltasAndCounts
+        };
+        Block {
+            mode,
+            start,
+            end,
+            sizes,
+        }
+    }
+
+    /// Returns the block starting from the first non-empty bin at or after `start`, if any.
+    ///
+    /// A run of unit bins gets its own block if writing it without counts saves more than
+    /// the cost of starting the block and of restarting the index deltas after it. The other
+    /// bins go to blocks with counts, which stretch until the next such run.
+    fn next<S: Store + ?Sized>(store: &S, start: i32, end: i32) -> Option<Block> {
+        let start = next_non_empty(store, start, end)?;
+        if store.get_count(start) == 1.0 {
+            let run_end = unit_run_end(store, start, end);
+            if is_worth_a_block(store, None, start, run_end, end) {
+                return Some(Block::smallest(store, start, run_end, true));
+            }
+        }
+
+        let mut block_end = start + 1;
+        let mut previous = start;
+        let mut i = start;
+        while let Some(next) = next_non_empty(store, i + 1, end) {
+            if store.get_count(next) == 1.0 {
+                let run_end = unit_run_end(store, next, end);
+                if is_worth_a_block(store, Some(previous), next, run_end, end) {
+                    break;
+                }
+                block_end = run_end;
+                previous = last_non_empty(store, run_end);
+                i = previous;
+            } else {
+                block_end = next + 1;
+                previous = next;
+                i = next;
+            }
+        }
+        Some(Block::smallest(store, start, block_end, false))
+    }
+
+    fn smallest<S: Store + ?Sized>(store: &S, start: i32, end: i32, is_unit: bool) -> Block {
+        let sizes = BinEncodingSizes::of(store, start, end);
+        let mut mode = if sizes.len(BinEncodingMode::ContiguousCounts)
+            <= sizes.len(BinEncodingMode::IndexDeltasAndCounts)
+        {
+            BinEncodingMode::ContiguousCounts
+        } else {
+            BinEncodingMode::IndexDeltasAndCounts
+        };
+        if is_unit && sizes.len(BinEncodingMode::IndexDeltas) < sizes.len(mode) {
+            mode = BinEncodingMode::IndexDeltas;
+        }
+        Block {
+            mode,
+            start,
+            end,
+            sizes,
+        }
+    }
+
+    /// Returns the number of bytes of the block, its flag included.
+    fn len(&self) -> i64 {
+        1 + self.sizes.len(self.mode)
+    }
+
+    fn encode<S: Store + ?Sized>(
+        &self,
+        store: &S,
+        output: &mut Output,
+        store_flag_type: FlagType,
+    ) -> Result<(), Error> {
+        let offset = store.get_offset();
+        self.mode.to_flag(store_flag_type).encode(output)?;
+        match self.mode {
+            BinEncodingMode::ContiguousCounts => {
+                serde::encode_unsigned_var_long(output, self.sizes.num_bins)?;
+                serde::encode_signed_var_long(output, (self.start + offset) as i64)?;
+                serde::encode_signed_var_long(output, 1)?;
+                for i in self.start..self.end {
+                    serde::encode_var_double(output, store.get_count(i))?;
+                }
+            }
+            BinEncodingMode::IndexDeltasAndCounts | BinEncodingMode::IndexDeltas => {
+                serde::encode_unsigned_var_long(output, self.sizes.num_non_empty_bins)?;
+                let mut previous_index = 0;
+                for i in self.start..self.end {
+                    let count = store.get_count(i);
+                    if count != 0.0 {
+                        let index: i64 = offset as i64 + i as i64;
+                        serde::encode_signed_var_long(output, index - previous_index)?;
+                        if let BinEncodingMode::IndexDeltasAndCounts = self.mode {
+                            serde::encode_var_double(output, count)?;
+                        }
+                        previous_index = index;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The sizes of the bin encodings of a range of bins, their flag aside.
+struct BinEncodingSizes {
+    num_bins: i64,
+    num_non_empty_bins: i64,
+    /// The size of the contiguous counts, their number, first index and index delta included.
+    dense_encoding_size: i64,
+    /// The size of the index deltas and counts, their number excluded.
+    sparse_encoding_size: i64,
+    /// The size of the counts of the non-empty bins.
+    counts_size: i64,
+}
+
+impl BinEncodingSizes {
+    fn of<S: Store + ?Sized>(store: &S, start: i32, end: i32) -> BinEncodingSizes {
+        let offset = store.get_offset();
+
+        let mut dense_encoding_size: i64 = 0;
+        let num_bins: i64 = end as i64 - start as i64;
+        dense_encoding_size += serde::unsigned_var_long_encoded_length(num_bins);
+        dense_encoding_size += serde::signed_var_long_encoded_length(start as i64 + offset as i64);
+        dense_encoding_size += serde::signed_var_long_encoded_length(1);
+
+        let mut sparse_encoding_size: i64 = 0;
+        let mut counts_size: i64 = 0;
+        let mut num_non_empty_bins: i64 = 0;
+        let mut previous_index: i64 = 0;
+
+        for i in start..end {
+            let count = store.get_count(i);
+            let count_var_double_encoded_length = serde::var_double_encoded_length(count);
+            dense_encoding_size += count_var_double_encoded_length;
+            if count != 0.0 {
+                num_non_empty_bins += 1;
+                let index: i64 = offset as i64 + i as i64;
+                sparse_encoding_size +=
+                    serde::signed_var_long_encoded_length(index - previous_index);
+                sparse_encoding_size += count_var_double_encoded_length;
+                counts_size += count_var_double_encoded_length;
+                previous_index = index;
+            }
+        }
+
+        BinEncodingSizes {
+            num_bins,
+            num_non_empty_bins,
+            dense_encoding_size,
+            sparse_encoding_size,
+            counts_size,
+        }
+    }
+
+    fn len(&self, mode: BinEncodingMode) -> i64 {
+        let num_non_empty_bins_size =
+            serde::unsigned_var_long_encoded_length(self.num_non_empty_bins);
+        match mode {
+            BinEncodingMode::ContiguousCounts => self.dense_encoding_size,
+            BinEncodingMode::IndexDeltasAndCounts => {
+                num_non_empty_bins_size + self.sparse_encoding_size
+            }
+            BinEncodingMode::IndexDeltas => {
+                num_non_empty_bins_size + self.sparse_encoding_size - self.counts_size
+            }
+        }
+    }
+}
+
+/// Returns whether the run of unit bins between `start` and `run_end` is worth its own block,
+/// rather than being written with counts after the non-empty bin `previous`, if any.
+fn is_worth_a_block<S: Store + ?Sized>(
+    store: &S,
+    previous: Option<i32>,
+    start: i32,
+    run_end: i32,
+    end: i32,
+) -> bool {
+    let offset = store.get_offset() as i64;
+    let unit_count_size = serde::var_double_encoded_length(1.0);
+    let mut num_bins = 0;
+    let mut deltas_size = 0;
+    let mut last = start;
+    for i in start..run_end {
+        if store.get_count(i) != 0.0 {
+            num_bins += 1;
+            if i != start {
+                deltas_size += serde::signed_var_long_encoded_length((i - last) as i64);
+            }
+            last = i;
+        }
+    }
+    let first_index = offset + start as i64;
+
+    let with_counts = serde::signed_var_long_encoded_length(match previous {
+        Some(previous) => (start - previous) as i64,
+        None => first_index,
+    }) + deltas_size
+        + num_bins * unit_count_size;
+    let alone = 1
+        + serde::unsigned_var_long_encoded_length(num_bins)
+        + serde::signed_var_long_encoded_length(first_index)
+        + deltas_size;
+    // The bins after the run start a new block, with a flag, a number of bins that most
+    // likely fits in one byte, and an index that is no longer a delta.
+    let restart = match next_non_empty(store, run_end, end) {
+        Some(next) => {
+            2 + serde::signed_var_long_encoded_length(offset + next as i64)
+                - serde::signed_var_long_encoded_length((next - last) as i64)
+        }
+        None => 0,
+    };
+    alone + restart < with_counts
+}
+
+fn next_non_empty<S: Store + ?Sized>(store: &S, start: i32, end: i32) -> Option<i32> {
+    (start..end).find(|i| store.get_count(*i) != 0.0)
+}
+
+/// Returns the index of the last non-empty bin before `end`, which must exist.
+fn last_non_empty<S: Store + ?Sized>(store: &S, end: i32) -> i32 {
+    let mut i = end - 1;
+    while store.get_count(i) == 0.0 {
+        i -= 1;
+    }
+    i
+}
+
+/// Returns the end of the run of unit bins starting at `start`, right after its last bin.
+fn unit_run_end<S: Store + ?Sized>(store: &S, start: i32, end: i32) -> i32 {
+    let mut run_end = start + 1;
+    let mut i = start + 1;
+    while i < end {
+        let count = store.get_count(i);
+        if count == 1.0 {
+            run_end = i + 1;
+        } else if count != 0.0 {
+            break;
+        }
+        i += 1;
+    }
+    run_end
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::Input;
+    use crate::store::{decode_bins, UnboundedSizeDenseStore};
+
+    fn encode_store(store: &UnboundedSizeDenseStore) -> Vec<u8> {
+        let mut output = Output::with_capacity(64);
+        encode(store, &mut output, FlagType::PositiveStore, true).unwrap();
+        output.trim()
+    }
+
+    fn decode_store(bytes: &[u8]) -> (UnboundedSizeDenseStore, Vec<u8>) {
+        let mut store = UnboundedSizeDenseStore::new();
+        let mut markers = Vec::new();
+        let mut input = Input::wrap(bytes);
+        while input.has_remaining() {
+            let marker = input.read_byte().unwrap();
+            markers.push(marker);
+            let mode = BinEncodingMode::of_flag(marker).unwrap();
+            decode_bins(&mut input, mode, |index, count| {
+                store.add(index, count);
+                Ok(())
+            })
+            .unwrap();
+        }
+        (store, markers)
+    }
+
+    fn legacy_len(store: &UnboundedSizeDenseStore) -> i64 {
+        let (start, end) = array_range(store);
+        Block::legacy(store, start, end).len()
+    }
+
+    #[test]
+    fn test_encode_unit_bins() {
+        let mut store = UnboundedSizeDenseStore::new();
+        for i in 0..100 {
+            store.add(1000 + 7 * i, 1.0);
+        }
+        let bytes = encode_store(&store);
+        assert_eq!(bytes.len(), encoded_len(&store, true));
+        assert!((bytes.len() as i64) < legacy_len(&store));
+
+        let (decoded, markers) = decode_store(&bytes);
+        let index_deltas = BinEncodingMode::IndexDeltas.to_flag(FlagType::PositiveStore);
+        assert_eq!(markers, vec![index_deltas.get_marker()]);
+        assert_eq!(decoded.get_ascending_stream(), store.get_ascending_stream());
+    }
+
+    #[test]
+    fn test_encode_mixed_bins() {
+        let mut store = UnboundedSizeDenseStore::new();
+        for i in 0..50 {
+            store.add(i, 2.5 + i as f64);
+        }
+        for i in 0..200 {
+            store.add(100 + 3 * i, 1.0);
+        }
+        store.add(2000, 4.0);
+        store.add(2001, 1.0);
+        let bytes = encode_store(&store);
+        assert_eq!(bytes.len(), encoded_len(&store, true));
+        assert!((bytes.len() as i64) < legacy_len(&store));
+
+        let (decoded, markers) = decode_store(&bytes);
+        assert!(markers.len() > 1);
+        assert_eq!(decoded.get_ascending_stream(), store.get_ascending_stream());
+    }
+
+    #[test]
+    fn test_encode_weighted_bins_as_legacy() {
+        let mut store = UnboundedSizeDenseStore::new();
+        for i in 0..30 {
+            store.add(-10 + 5 * i, 0.5 * (i + 1) as f64);
+        }
+        let bytes = encode_store(&store);
+        assert_eq!(bytes.len() as i64, legacy_len(&store));
+
+        let (start, end) = array_range(&store);
+        let mut output = Output::with_capacity(64);
+        Block::legacy(&store, start, end)
+            .encode(&store, &mut output, FlagType::PositiveStore)
+            .unwrap();
+        assert_eq!(bytes, output.trim());
+    }
+
+    #[test]
+    fn test_encode_legacy_by_default() {
+        let mut store = UnboundedSizeDenseStore::new();
+        for i in 0..100 {
+            store.add(1000 + 7 * i, 1.0);
+        }
+        let mut output = Output::with_capacity(64);
+        encode(&store, &mut output, FlagType::PositiveStore, false).unwrap();
+        let bytes = output.trim();
+        assert_eq!(bytes.len() as i64, legacy_len(&store));
+        assert_eq!(bytes.len(), encoded_len(&store, false));
+        let (decoded, markers) = decode_store(&bytes);
+        assert_eq!(1, markers.len());
+        assert_eq!(decoded.get_ascending_stream(), store.get_ascending_stream());
+    }
+}
@@ -2,18 +2,22 @@ use crate::error::Error;
 use crate::input::Input;
 use crate::serde;
 
+mod atomic;
 mod collapsing_highest;
 mod collapsing_lowest;
+mod encoding;
 mod unbounded;
 
 use crate::index_mapping::IndexMapping;
+use crate::limits::DecodeLimits;
 use crate::output::Output;
 use crate::sketch::{Flag, FlagType};
+pub use atomic::AtomicDenseStore;
 pub use collapsing_highest::CollapsingHighestDenseStore;
 pub use collapsing_lowest::CollapsingLowestDenseStore;
 pub use unbounded::UnboundedSizeDenseStore;
 
-pub trait Store {
+pub trait Store: Send + Sync {
     fn add(&mut self, index: i32, count: f64);
     fn add_bin(&mut self, bin: (i32, f64));
     fn merge_with(&mut self, bins: Vec<(i32, f64)>) {
@@ -28,121 +32,80 @@ pub trait Store {
     fn get_min_index(&self) -> i32;
     fn get_max_index(&self) -> i32;
     fn get_count(&self, i: i32) -> f64;
-    fn encode(&self, output: &mut Output, store_flag_type: FlagType) -> Result<(), Error> {
-        if self.is_empty() {
-            return Ok(());
-        }
-
-        let min_index = self.get_min_index();
-        let max_index = self.get_max_index();
-        let offset = self.get_offset();
-
-        let mut dense_encoding_size: i64 = 0;
-        let num_bins: i64 = max_index as i64 - min_index as i64 + 1;
-        dense_encoding_size += serde::unsigned_var_long_encoded_length(num_bins);
-        dense_encoding_size += serde::signed_var_long_encoded_length(min_index as i64);
-        dense_encoding_size += serde::signed_var_long_encoded_length(1);
-
-        let mut sparse_encoding_size: i64 = 0;
-        let mut num_non_empty_bins: i64 = 0;
-        let mut previous_index: i64 = 0;
-
-        for i in min_index - offset..max_index - offset + 1 {
-            let count = self.get_count(i);
-            let count_var_double_encoded_length = serde::var_double_encoded_length(count);
-            dense_encoding_size += count_var_double_encoded_length;
-            if count != 0.0 {
-                num_non_empty_bins += 1;
-                let index: i64 = offset as i64 + i as i64;
-                sparse_encoding_size +=
-                    serde::signed_var_long_encoded_length(index - previous_index);
-                sparse_encoding_size += count_var_double_encoded_length;
-                previous_index = index;
-            }
-        }
-
-        if dense_encoding_size <= sparse_encoding_size {
-            BinEncodingMode::ContiguousCounts
-                .to_flag(store_flag_type)
-                .encode(output)?;
-            serde::encode_unsigned_var_long(output, num_bins)?;
-            serde::encode_signed_var_long(output, min_index as i64)?;
-            serde::encode_signed_var_long(output, 1)?;
-            for i in min_index - offset..max_index - offset + 1 {
-                serde::encode_var_double(output, self.get_count(i))?;
-            }
-        } else {
-            BinEncodingMode::IndexDeltasAndCounts
-                .to_flag(store_flag_type)
-                .encode(output)?;
-            serde::encode_unsigned_var_long(output, num_non_empty_bins)?;
-            let mut previous_index = 0;
-            for i in min_index - offset..max_index - offset + 1 {
-                let count = self.get_count(i);
-                if count != 0.0 {
-                    let index: i64 = offset as i64 + i as i64;
-                    serde::encode_signed_var_long(output, index - previous_index)?;
-                    serde::encode_var_double(output, count)?;
-                    previous_index = index;
-                }
-            }
-        }
-        Ok(())
+    /// Returns whether the number of bins is bounded whatever the indexes added, in which case
+    /// the index span of decoded bins doesn't need to be limited.
+    fn is_bounded(&self) -> bool {
+        false
+    }
+    /// Encodes the bins, in several blocks if `compact` and if it is smaller, see the
+    /// `encoding` module.
+    fn encode(
+        &self,
+        output: &mut Output,
+        store_flag_type: FlagType,
+        compact: bool,
+    ) -> Result<(), Error> {
+        encoding::encode(self, output, store_flag_type, compact)
+    }
+    /// Returns the number of bytes that `encode` writes.
+    fn encoded_len(&self, compact: bool) -> usize {
+        encoding::encoded_len(self, compact)
     }
+    /// Decodes bins and adds them, within `limits`, `num_bins` being the number of bins of the
+    /// encoding decoded so far and `span` the range of their indexes for this store. Only the
+    /// span of the decoded bins is limited, not the one of the bins already in the store.
     fn decode_and_merge_with(
         &mut self,
         input: &mut Input,
         mode: BinEncodingMode,
+        limits: &DecodeLimits,
+        num_bins: &mut usize,
+        span: &mut Option<(i32, i32)>,
     ) -> Result<(), Error> {
-        match mode {
-            BinEncodingMode::IndexDeltasAndCounts => {
-                let num_bins = serde::decode_unsigned_var_long(input)?;
-                let mut index: i64 = 0;
-                let mut i = 0;
-                while i < num_bins {
-                    let index_delta = serde::decode_signed_var_long(input)?;
-                    let count = serde::decode_var_double(input)?;
-                    index += index_delta;
-                    self.add(serde::i64_to_i32_exact(index)?, count);
-                    i += 1;
-                }
-
-                Ok(())
+        let is_bounded = self.is_bounded();
+        decode_bins(input, mode, |index, count| {
+            *num_bins += 1;
+            limits.check_num_bins(*num_bins)?;
+            if !is_bounded {
+                let (min_index, max_index) = span
+                    .map_or((index, index), |(min_index, max_index)| {
+                        (min_index.min(index), max_index.max(index))
+                    });
+                limits.check_index_span(min_index, max_index)?;
+                *span = Some((min_index, max_index));
             }
-
-            BinEncodingMode::IndexDeltas => {
-                let num_bins = serde::decode_unsigned_var_long(input)?;
-                let mut index: i64 = 0;
-                let mut i = 0;
-                while i < num_bins {
-                    let index_delta = serde::decode_signed_var_long(input)?;
-                    index += index_delta;
-                    self.add(serde::i64_to_i32_exact(index)?, 1.0);
-                    i += 1;
-                }
-                Ok(())
-            }
-
-            BinEncodingMode::ContiguousCounts => {
-                let num_bins = serde::decode_unsigned_var_long(input)?;
-                let mut index: i64 = serde::decode_signed_var_long(input)?;
-                let index_delta = serde::decode_signed_var_long(input)?;
-
-                let mut i = 0;
-                while i < num_bins {
-                    let count = serde::decode_var_double(input)?;
-                    self.add(serde::i64_to_i32_exact(index)?, count);
-                    index += index_delta;
-                    i += 1;
-                }
-                Ok(())
-            }
-        }
+            self.add(index, count);
+            Ok(())
+        })
     }
     fn get_descending_stream(&self) -> Vec<(i32, f64)>;
     fn get_ascending_stream(&self) -> Vec<(i32, f64)>;
-    fn get_descending_iter(&self) -> StoreIter;
-    fn get_ascending_iter(&self) -> StoreIter;
+    fn multiply_counts(&mut self, factor: f64) {
+        let bins = self.get_ascending_stream();
+        self.clear();
+        for (index, count) in bins {
+            self.add(index, count * factor);
+        }
+    }
+    /// Removes the bins whose index is lower than `index` and returns their total count.
+    fn remove_below(&mut self, index: i32) -> f64 {
+        if self.is_empty() || self.get_min_index() >= index {
+            return 0.0;
+        }
+        let bins = self.get_ascending_stream();
+        self.clear();
+        let mut removed = 0.0;
+        for (i, count) in bins {
+            if i < index {
+                removed += count;
+            } else {
+                self.add(i, count);
+            }
+        }
+        removed
+    }
+    fn get_descending_iter(&self) -> StoreIter<'_>;
+    fn get_ascending_iter(&self) -> StoreIter<'_>;
     fn get_sum(&self, index_mapping: &IndexMapping) -> f64 {
         let mut sum = 0.0;
         if self.is_empty() {
@@ -165,6 +128,98 @@ pub trait Store {
     }
 }
 
+/// The largest magnitude of the indexes of the decoded bins, which leaves the stores room for
+/// their arithmetic.
+pub(crate) const MAX_DECODED_INDEX: i64 = 1 << 30;
+
+/// Returns `index` if it is a valid index for a decoded bin.
+pub(crate) fn decoded_index(index: i64) -> Result<i32, Error> {
+    if !(-MAX_DECODED_INDEX..=MAX_DECODED_INDEX).contains(&index) {
+        return Err(Error::IndexOutOfRange(index));
+    }
+    serde::i64_to_i32_exact(index)
+}
+
+/// Decodes the bins of a store encoded with `mode`, passing each of them to `add`, and stops at
+/// the first error it returns.
+pub(crate) fn decode_bins<F>(
+    input: &mut Input,
+    mode: BinEncodingMode,
+    mut add: F,
+) -> Result<(), Error>
+where
+    F: FnMut(i32, f64) -> Result<(), Error>,
+{
+    match mode {
+        BinEncodingMode::IndexDeltasAndCounts => {
+            let num_bins = serde::decode_unsigned_var_long(input)?;
+            let mut index: i64 = 0;
+            let mut i = 0;
+            while i < num_bins {
+                let index_delta = serde::decode_signed_var_long(input)?;
+                let count = serde::decode_var_double(input)?;
+                let bin_index = decoded_index(index.saturating_add(index_delta))?;
+                index = bin_index as i64;
+                add(bin_index, count)?;
+                i += 1;
+            }
+            Ok(())
+        }
+
+        BinEncodingMode::IndexDeltas => {
+            let num_bins = serde::decode_unsigned_var_long(input)?;
+            let mut index: i64 = 0;
+            let mut i = 0;
+            while i < num_bins {
+                let index_delta = serde::decode_signed_var_long(input)?;
+                let bin_index = decoded_index(index.saturating_add(index_delta))?;
+                index = bin_index as i64;
+                add(bin_index, 1.0)?;
+                i += 1;
+            }
+            Ok(())
+        }
+
+        BinEncodingMode::ContiguousCounts => {
+            let num_bins = serde::decode_unsigned_var_long(input)?;
+            let mut index: i64 = serde::decode_signed_var_long(input)?;
+            let index_delta = serde::decode_signed_var_long(input)?;
+
+            let mut i = 0;
+            while i < num_bins {
+                let count = serde::decode_var_double(input)?;
+                add(decoded_index(index)?, count)?;
+                index = index.saturating_add(index_delta);
+                i += 1;
+            }
+            Ok(())
+        }
+    }
+}
+
+/// The kind of store holding the bins of one sign of a sketch, along with its capacity.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub enum StoreType {
+    CollapsingLowestDense(usize),
+    CollapsingHighestDense(usize),
+    UnboundedDense,
+}
+
+impl StoreType {
+    pub(crate) fn build(&self) -> Result<Box<dyn Store>, Error> {
+        match *self {
+            StoreType::CollapsingLowestDense(max_num_bins) => Ok(Box::new(
+                CollapsingLowestDenseStore::with_capacity(max_num_bins)?,
+            )),
+            StoreType::CollapsingHighestDense(max_num_bins) => Ok(Box::new(
+                CollapsingHighestDenseStore::with_capacity(max_num_bins)?,
+            )),
+            StoreType::UnboundedDense => Ok(Box::new(UnboundedSizeDenseStore::new())),
+        }
+    }
+}
+
 pub struct StoreIter<'a> {
     min_index: i32,
     max_index: i32,
@@ -180,7 +235,7 @@ impl<'a> StoreIter<'a> {
         offset: i32,
         desc: bool,
         counts: &'a [f64],
-    ) -> StoreIter {
+    ) -> StoreIter<'a> {
         StoreIter {
             desc,
             min_index,
@@ -234,6 +289,7 @@ impl<'a> Iterator for StoreIter<'a> {
     }
 }
 
+#[derive(Clone, Copy)]
 pub enum BinEncodingMode {
     IndexDeltasAndCounts = 1,
     IndexDeltas = 2,
@@ -242,12 +298,11 @@ pub enum BinEncodingMode {
 
 impl BinEncodingMode {
     pub fn of_flag(marker: u8) -> Result<BinEncodingMode, Error> {
-        let index = (marker >> 2) - 1;
-        match index {
-            0 => Ok(BinEncodingMode::IndexDeltasAndCounts),
-            1 => Ok(BinEncodingMode::IndexDeltas),
-            2 => Ok(BinEncodingMode::ContiguousCounts),
-            _ => Err(Error::InvalidArgument("Unknown BinEncodingMode.")),
+        match marker >> 2 {
+            1 => Ok(BinEncodingMode::IndexDeltasAndCounts),
+            2 => Ok(BinEncodingMode::IndexDeltas),
+            3 => Ok(BinEncodingMode::ContiguousCounts),
+            _ => Err(Error::UnknownFlag(marker)),
         }
     }
 
@@ -302,4 +357,47 @@ mod tests {
         assert_eq!(6, store.get_min_index());
         assert_eq!(20.0, store.get_total_count());
     }
+
+    #[test]
+    fn test_store_ascending_stream() {
+        let stores: Vec<Box<dyn Store>> = vec![
+            Box::new(CollapsingLowestDenseStore::with_capacity(16).unwrap()),
+            Box::new(CollapsingHighestDenseStore::with_capacity(16).unwrap()),
+            Box::new(UnboundedSizeDenseStore::new()),
+        ];
+        for mut store in stores {
+            store.add(-3, 1.0);
+            store.add(0, 2.0);
+            store.add(5, 4.0);
+            let expected = vec![(-3, 1.0), (0, 2.0), (5, 4.0)];
+            assert_eq!(expected, store.get_ascending_stream());
+            let mut descending = store.get_descending_stream();
+            descending.reverse();
+            assert_eq!(expected, descending);
+        }
+    }
+
+    #[test]
+    fn test_store_total_count_cached() {
+        let mut stores: Vec<Box<dyn Store>> = vec![
+            Box::new(CollapsingLowestDenseStore::with_capacity(4).unwrap()),
+            Box::new(CollapsingHighestDenseStore::with_capacity(4).unwrap()),
+            Box::new(UnboundedSizeDenseStore::new()),
+        ];
+        for store in stores.iter_mut() {
+            store.add(3, 1.5);
+            store.add(-40, 2.0);
+            store.add_bin((100, 0.5));
+            store.merge_with(vec![(7, 1.0), (-2, 3.0)]);
+            assert_eq!(8.0, store.get_total_count());
+            assert_eq!(
+                8.0,
+                store.get_ascending_iter().map(|bin| bin.1).sum::<f64>()
+            );
+            store.clear();
+            assert_eq!(0.0, store.get_total_count());
+            store.add(5, 1.0);
+            assert_eq!(1.0, store.get_total_count());
+        }
+    }
 }
@@ -4,6 +4,7 @@ use crate::serde;
 #[derive(Clone)]
 pub struct UnboundedSizeDenseStore {
     counts: Vec<f64>,
+    total_count: f64,
     offset: i32,
     min_index: i32,
     max_index: i32,
@@ -15,6 +16,7 @@ impl UnboundedSizeDenseStore {
     pub fn new() -> Self {
         UnboundedSizeDenseStore {
             counts: Vec::new(),
+            total_count: 0.0,
             offset: 0,
             min_index: i32::MAX,
             max_index: i32::MIN,
@@ -23,6 +25,22 @@ impl UnboundedSizeDenseStore {
         }
     }
 
+    /// Creates a store holding `counts`, the first of them being the count of the bin of index
+    /// `offset`.
+    pub(crate) fn with_counts(offset: i32, counts: Vec<f64>) -> Self {
+        let mut store = UnboundedSizeDenseStore::new();
+        let first = counts.iter().position(|count| *count > 0.0);
+        let last = counts.iter().rposition(|count| *count > 0.0);
+        if let (Some(first), Some(last)) = (first, last) {
+            store.total_count = counts.iter().sum();
+            store.offset = offset;
+            store.min_index = offset + first as i32;
+            store.max_index = offset + last as i32;
+            store.counts = counts;
+        }
+        store
+    }
+
     fn normalize(&mut self, index: i32) -> i32 {
         if index < self.min_index || index > self.max_index {
             self.extend_range(index, index);
@@ -126,21 +144,6 @@ impl UnboundedSizeDenseStore {
             }
         }
     }
-
-    fn get_total_count_with_range(&self, from_index: i32, to_index: i32) -> f64 {
-        if self.is_empty() {
-            return 0.0;
-        }
-
-        let from_array_index = i32::max(from_index - self.offset, 0);
-        let to_array_index = i32::min(to_index - self.offset, self.get_length() - 1) + 1;
-
-        let mut total_count: f64 = 0.0;
-        for array_index in from_array_index..to_array_index {
-            total_count += self.counts[array_index as usize];
-        }
-        total_count
-    }
 }
 
 impl Store for UnboundedSizeDenseStore {
@@ -150,11 +153,9 @@ impl Store for UnboundedSizeDenseStore {
         }
 
         let array_index = serde::i32_to_usize_exact(self.normalize(index));
-        match array_index {
-            Ok(index) => {
-                self.counts[index] += count;
-            }
-            _ => {}
+        if let Ok(index) = array_index {
+            self.counts[index] += count;
+            self.total_count += count;
         }
     }
 
@@ -163,16 +164,15 @@ impl Store for UnboundedSizeDenseStore {
             return;
         }
         let array_index = serde::i32_to_usize_exact(self.normalize(bin.0));
-        match array_index {
-            Ok(index) => {
-                self.counts[index] += bin.1;
-            }
-            _ => {}
+        if let Ok(index) = array_index {
+            self.counts[index] += bin.1;
+            self.total_count += bin.1;
         }
     }
 
     fn clear(&mut self) {
         self.counts.fill(0.0);
+        self.total_count = 0.0;
         self.max_index = i32::MIN;
         self.min_index = i32::MAX;
         self.offset = 0;
@@ -183,7 +183,7 @@ impl Store for UnboundedSizeDenseStore {
     }
 
     fn get_total_count(&self) -> f64 {
-        self.get_total_count_with_range(self.min_index, self.max_index)
+        self.total_count
     }
 
     fn get_min_index(&self) -> i32 {
@@ -225,12 +225,12 @@ impl Store for UnboundedSizeDenseStore {
                 let bin = (index, value);
                 bins.push(bin);
             }
-            index -= 1;
+            index += 1;
         }
         bins
     }
 
-    fn get_descending_iter(&self) -> StoreIter {
+    fn get_descending_iter(&self) -> StoreIter<'_> {
         StoreIter::new(
             self.min_index,
             self.max_index,
@@ -240,7 +240,7 @@ impl Store for UnboundedSizeDenseStore {
         )
     }
 
-    fn get_ascending_iter(&self) -> StoreIter {
+    fn get_ascending_iter(&self) -> StoreIter<'_> {
         StoreIter::new(
             self.min_index,
             self.max_index,
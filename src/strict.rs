@@ -0,0 +1,272 @@
+use crate::error::Error;
+use crate::index_mapping::{IndexMapping, IndexMappingLayout};
+use crate::input::Input;
+use crate::limits::DecodeLimits;
+use crate::serde;
+use crate::sketch::{DDSketch, Flag, FlagType};
+use crate::store::{self, BinEncodingMode, Store, UnboundedSizeDenseStore};
+
+impl DDSketch {
+    /// Decodes a sketch as `decode` does, within the default `DecodeLimits`, but rejects the
+    /// encodings that `decode` lets through, for sketches coming from untrusted sources: counts
+    /// that are NaN, infinite or negative, several index mappings, a mapping whose gamma is not
+    /// finite and greater than 1, flags that are unknown and indexes out of range. The bins
+    /// are added to the stores as they are read, and the limits are checked on each of them.
+    ///
+    /// The `Error::InvalidEncoding` returned tells the byte offset of the value or of the flag
+    /// that was rejected, the marker of the flag being decoded and the reason.
+    pub fn decode_strict(bytes: &[u8]) -> Result<DDSketch, Error> {
+        StrictDecoder {
+            input: Input::wrap(bytes),
+            limits: DecodeLimits::default(),
+            num_bins: 0,
+            flag: None,
+            index_mapping: None,
+            zero_threshold: 0.0,
+            zero_count: 0.0,
+            value_counters: None,
+            negative_bins: DecodedBins::new(),
+            positive_bins: DecodedBins::new(),
+        }
+        .decode()
+    }
+}
+
+struct StrictDecoder<'a> {
+    input: Input<'a>,
+    limits: DecodeLimits,
+    /// The number of bins decoded so far.
+    num_bins: usize,
+    /// The marker of the flag being decoded.
+    flag: Option<u8>,
+    index_mapping: Option<IndexMapping>,
+    zero_threshold: f64,
+    zero_count: f64,
+    value_counters: Option<(f64, f64, f64)>,
+    negative_bins: DecodedBins,
+    positive_bins: DecodedBins,
+}
+
+/// The bins of one sign decoded so far, added to a store as they come.
+struct DecodedBins {
+    store: UnboundedSizeDenseStore,
+    min_index: i32,
+    max_index: i32,
+}
+
+impl DecodedBins {
+    fn new() -> DecodedBins {
+        DecodedBins {
+            store: UnboundedSizeDenseStore::new(),
+            min_index: i32::MAX,
+            max_index: i32::MIN,
+        }
+    }
+}
+
+impl<'a> StrictDecoder<'a> {
+    fn decode(mut self) -> Result<DDSketch, Error> {
+        while self.input.has_remaining() {
+            let offset = self.input.position();
+            let flag = Flag::decode(&mut self.input)?;
+            self.flag = Some(flag.get_marker());
+            match flag.get_type()? {
+                FlagType::PositiveStore => {
+                    let mode = self.decode_bin_encoding_mode(offset, &flag)?;
+                    self.decode_bins(mode, FlagType::PositiveStore)?;
+                }
+                FlagType::NegativeStore => {
+                    let mode = self.decode_bin_encoding_mode(offset, &flag)?;
+                    self.decode_bins(mode, FlagType::NegativeStore)?;
+                }
+                FlagType::IndexMapping => self.decode_index_mapping(offset, &flag)?,
+                FlagType::SketchFeatures => self.decode_sketch_feature(offset, &flag)?,
+            }
+        }
+
+        let offset = self.input.position();
+        self.flag = None;
+        let index_mapping = self
+            .index_mapping
+            .take()
+            .ok_or_else(|| self.error(offset, "No IndexMapping decoded."))?;
+        Ok(DDSketch::from_decoded_stores(
+            index_mapping,
+            self.zero_threshold,
+            self.zero_count,
+            self.negative_bins.store,
+            self.positive_bins.store,
+            self.value_counters,
+        ))
+    }
+
+    fn decode_bin_encoding_mode(
+        &self,
+        offset: usize,
+        flag: &Flag,
+    ) -> Result<BinEncodingMode, Error> {
+        if flag.get_marker() >> 2 == 0 {
+            return Err(self.error(offset, "Unknown BinEncodingMode."));
+        }
+        BinEncodingMode::of_flag(flag.get_marker())
+            .map_err(|_| self.error(offset, "Unknown BinEncodingMode."))
+    }
+
+    fn decode_bins(&mut self, mode: BinEncodingMode, flag_type: FlagType) -> Result<(), Error> {
+        let num_bins = self.read(serde::decode_unsigned_var_long)?.1;
+        match mode {
+            BinEncodingMode::IndexDeltasAndCounts | BinEncodingMode::IndexDeltas => {
+                let mut index: i64 = 0;
+                for _ in 0..num_bins {
+                    let (offset, index_delta) = self.read(serde::decode_signed_var_long)?;
+                    index = self.checked_index(offset, index.checked_add(index_delta))?;
+                    let count = match mode {
+                        BinEncodingMode::IndexDeltasAndCounts => self.read_count()?,
+                        _ => 1.0,
+                    };
+                    self.add_bin(offset, flag_type, index, count)?;
+                }
+            }
+            BinEncodingMode::ContiguousCounts => {
+                let (offset, mut index) = self.read(serde::decode_signed_var_long)?;
+                self.checked_index(offset, Some(index))?;
+                let (delta_offset, index_delta) = self.read(serde::decode_signed_var_long)?;
+                for i in 0..num_bins {
+                    if i > 0 {
+                        index = self.checked_index(delta_offset, index.checked_add(index_delta))?;
+                    }
+                    let offset = self.input.position();
+                    let count = self.read_count()?;
+                    self.add_bin(offset, flag_type, index, count)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Adds a bin whose index has been checked, the offset being the one it starts at. The
+    /// index span is checked before the store grows to hold the bin.
+    fn add_bin(
+        &mut self,
+        offset: usize,
+        flag_type: FlagType,
+        index: i64,
+        count: f64,
+    ) -> Result<(), Error> {
+        self.num_bins += 1;
+        if self.limits.check_num_bins(self.num_bins).is_err() {
+            return Err(self.error(offset, "Too many bins."));
+        }
+        if count == 0.0 {
+            return Ok(());
+        }
+        let index = index as i32;
+        let bins = match flag_type {
+            FlagType::NegativeStore => &self.negative_bins,
+            _ => &self.positive_bins,
+        };
+        let min_index = bins.min_index.min(index);
+        let max_index = bins.max_index.max(index);
+        if self.limits.check_index_span(min_index, max_index).is_err() {
+            return Err(self.error(offset, "Index span too large."));
+        }
+        let bins = match flag_type {
+            FlagType::NegativeStore => &mut self.negative_bins,
+            _ => &mut self.positive_bins,
+        };
+        bins.min_index = min_index;
+        bins.max_index = max_index;
+        bins.store.add(index, count);
+        Ok(())
+    }
+
+    /// Returns `index` if it is a valid index for a decoded bin, the offset being the one of the
+    /// index delta it results from.
+    fn checked_index(&self, offset: usize, index: Option<i64>) -> Result<i64, Error> {
+        index
+            .filter(|index| store::decoded_index(*index).is_ok())
+            .ok_or_else(|| self.error(offset, "Index out of range."))
+    }
+
+    fn decode_index_mapping(&mut self, offset: usize, flag: &Flag) -> Result<(), Error> {
+        if self.index_mapping.is_some() {
+            return Err(self.error(offset, "Repeated IndexMapping."));
+        }
+        let layout = IndexMappingLayout::of_flag(flag)
+            .map_err(|_| self.error(offset, "Unknown Index Flag."))?;
+        let (offset, gamma) = self.read(Input::read_double_le)?;
+        let index_offset = self.read(Input::read_double_le)?.1;
+        let index_mapping = IndexMapping::with_checked_gamma_offset(layout, gamma, index_offset)
+            .map_err(|error| self.cause(offset, error))?;
+        self.index_mapping = Some(index_mapping);
+        Ok(())
+    }
+
+    fn decode_sketch_feature(&mut self, offset: usize, flag: &Flag) -> Result<(), Error> {
+        if Flag::ZERO_COUNT == *flag {
+            self.zero_count += self.read_count()?;
+        } else if Flag::ZERO_THRESHOLD == *flag {
+            let (offset, zero_threshold) = self.read(Input::read_double_le)?;
+            if !zero_threshold.is_finite() || zero_threshold < 0.0 {
+                return Err(self.error(offset, "Invalid zero threshold."));
+            }
+            self.zero_threshold = zero_threshold;
+        } else if Flag::DROPPED_COUNT == *flag
+            || Flag::NAN_COUNT == *flag
+            || Flag::INFINITE_COUNT == *flag
+        {
+            let count = self.read_count()?;
+            let (dropped, nan, infinite) = self.value_counters.get_or_insert((0.0, 0.0, 0.0));
+            if Flag::DROPPED_COUNT == *flag {
+                *dropped += count;
+            } else if Flag::NAN_COUNT == *flag {
+                *nan += count;
+            } else {
+                *infinite += count;
+            }
+        } else if Flag::COUNT == *flag {
+            self.read_count()?;
+        } else if Flag::SUM == *flag || Flag::MIN == *flag || Flag::MAX == *flag {
+            self.read(Input::read_double_le)?;
+        } else {
+            return Err(self.error(offset, "Unknown Flag."));
+        }
+        Ok(())
+    }
+
+    /// Reads a count, which must be finite and non-negative.
+    fn read_count(&mut self) -> Result<f64, Error> {
+        let (offset, count) = self.read(serde::decode_var_double)?;
+        if !count.is_finite() || count < 0.0 {
+            return Err(self.error(offset, "The counts must be finite and non-negative."));
+        }
+        Ok(count)
+    }
+
+    /// Reads a value with `read`, and returns it along with the offset it starts at.
+    fn read<T>(
+        &mut self,
+        read: fn(&mut Input<'a>) -> Result<T, Error>,
+    ) -> Result<(usize, T), Error> {
+        let offset = self.input.position();
+        match read(&mut self.input) {
+            Ok(value) => Ok((offset, value)),
+            Err(error) => Err(self.cause(offset, error)),
+        }
+    }
+
+    fn cause(&self, offset: usize, error: Error) -> Error {
+        match error {
+            Error::Truncated { .. } => self.error(offset, "Unexpected end of input."),
+            error => error.at(offset, self.flag),
+        }
+    }
+
+    fn error(&self, offset: usize, reason: &'static str) -> Error {
+        Error::InvalidEncoding {
+            offset,
+            flag: self.flag,
+            reason,
+        }
+    }
+}
@@ -0,0 +1,201 @@
+/// Exact running summary of the accepted values: count, sum, extremes and the central moments
+/// up to the fourth order, updated with the pairwise formulas of Pébay (2008) so that weighted
+/// samples and merges of two summaries are handled the same way.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SummaryStatistics {
+    count: f64,
+    sum: f64,
+    min: f64,
+    max: f64,
+    mean: f64,
+    m2: f64,
+    m3: f64,
+    m4: f64,
+}
+
+impl SummaryStatistics {
+    pub fn new() -> Self {
+        SummaryStatistics {
+            count: 0.0,
+            sum: 0.0,
+            min: f64::INFINITY,
+            max: f64::NEG_INFINITY,
+            mean: 0.0,
+            m2: 0.0,
+            m3: 0.0,
+            m4: 0.0,
+        }
+    }
+
+    pub fn add(&mut self, value: f64, count: f64) {
+        if count <= 0.0 {
+            return;
+        }
+        let single = SummaryStatistics {
+            count,
+            sum: value * count,
+            min: value,
+            max: value,
+            mean: value,
+            m2: 0.0,
+            m3: 0.0,
+            m4: 0.0,
+        };
+        self.merge_with(&single);
+    }
+
+    pub fn merge_with(&mut self, other: &SummaryStatistics) {
+        if other.count <= 0.0 {
+            return;
+        }
+        if self.count <= 0.0 {
+            *self = other.clone();
+            return;
+        }
+
+        let na = self.count;
+        let nb = other.count;
+        let n = na + nb;
+        let delta = other.mean - self.mean;
+        let delta_n = delta / n;
+        let delta_n2 = delta_n * delta_n;
+
+        let m4 = self.m4
+            + other.m4
+            + delta * delta_n2 * delta_n * na * nb * (na * na - na * nb + nb * nb)
+            + 6.0 * delta_n2 * (na * na * other.m2 + nb * nb * self.m2)
+            + 4.0 * delta_n * (na * other.m3 - nb * self.m3);
+        let m3 = self.m3
+            + other.m3
+            + delta * delta_n2 * na * nb * (na - nb)
+            + 3.0 * delta_n * (na * other.m2 - nb * self.m2);
+        let m2 = self.m2 + other.m2 + delta * delta_n * na * nb;
+
+        self.count = n;
+        self.sum += other.sum;
+        self.min = self.min.min(other.min);
+        self.max = self.max.max(other.max);
+        self.mean += delta_n * nb;
+        self.m2 = m2;
+        self.m3 = m3;
+        self.m4 = m4;
+    }
+
+    pub fn clear(&mut self) {
+        *self = SummaryStatistics::new();
+    }
+
+    /// Multiplies the weight of every value by `factor`, leaving the mean and extremes as is.
+    pub fn multiply_counts(&mut self, factor: f64) {
+        self.count *= factor;
+        self.sum *= factor;
+        self.m2 *= factor;
+        self.m3 *= factor;
+        self.m4 *= factor;
+    }
+
+    /// Multiplies every value by `factor`, leaving the weights as is.
+    pub fn scale(&mut self, factor: f64) {
+        if self.count <= 0.0 {
+            return;
+        }
+        let (min, max) = (self.min * factor, self.max * factor);
+        self.min = min.min(max);
+        self.max = min.max(max);
+        self.sum *= factor;
+        self.mean *= factor;
+        self.m2 *= factor * factor;
+        self.m3 *= factor * factor * factor;
+        self.m4 *= factor * factor * factor * factor;
+    }
+
+    pub fn count(&self) -> f64 {
+        self.count
+    }
+
+    pub fn sum(&self) -> f64 {
+        self.sum
+    }
+
+    pub fn min(&self) -> f64 {
+        self.min
+    }
+
+    pub fn max(&self) -> f64 {
+        self.max
+    }
+
+    /// Returns the central moments `(m2, m3, m4)`, each normalized by the count.
+    pub fn central_moments(&self) -> (f64, f64, f64) {
+        (
+            self.m2 / self.count,
+            self.m3 / self.count,
+            self.m4 / self.count,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EPSILON: f64 = 1e-9;
+
+    fn naive_central_moments(values: &[(f64, f64)]) -> (f64, f64, f64) {
+        let count: f64 = values.iter().map(|v| v.1).sum();
+        let mean = values.iter().map(|v| v.0 * v.1).sum::<f64>() / count;
+        let mut moments = (0.0, 0.0, 0.0);
+        for (value, weight) in values {
+            let d = value - mean;
+            moments.0 += weight * d * d;
+            moments.1 += weight * d * d * d;
+            moments.2 += weight * d * d * d * d;
+        }
+        (moments.0 / count, moments.1 / count, moments.2 / count)
+    }
+
+    #[test]
+    fn test_summary_statistics_add() {
+        let values = [(-3.0, 1.0), (0.0, 2.0), (1.5, 1.0), (2.0, 3.0), (10.0, 0.5)];
+        let mut summary = SummaryStatistics::new();
+        for (value, weight) in values {
+            summary.add(value, weight);
+        }
+        assert_eq!(7.5, summary.count());
+        assert_eq!(-3.0, summary.min());
+        assert_eq!(10.0, summary.max());
+        assert!((summary.sum() - 9.5).abs() < EPSILON);
+
+        let expected = naive_central_moments(&values);
+        let actual = summary.central_moments();
+        assert!((expected.0 - actual.0).abs() < EPSILON);
+        assert!((expected.1 - actual.1).abs() < EPSILON);
+        assert!((expected.2 - actual.2).abs() < EPSILON);
+    }
+
+    #[test]
+    fn test_summary_statistics_merge() {
+        let mut left = SummaryStatistics::new();
+        let mut right = SummaryStatistics::new();
+        let mut all = SummaryStatistics::new();
+        for i in 0..50 {
+            let value = (i * i % 17) as f64 - 4.0;
+            if i % 3 == 0 {
+                left.add(value, 1.0);
+            } else {
+                right.add(value, 1.0);
+            }
+            all.add(value, 1.0);
+        }
+        left.merge_with(&right);
+
+        assert_eq!(all.count(), left.count());
+        assert_eq!(all.min(), left.min());
+        assert_eq!(all.max(), left.max());
+        let expected = all.central_moments();
+        let actual = left.central_moments();
+        assert!((expected.0 - actual.0).abs() < EPSILON);
+        assert!((expected.1 - actual.1).abs() < EPSILON);
+        assert!((expected.2 - actual.2).abs() < EPSILON);
+    }
+}
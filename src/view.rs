@@ -0,0 +1,286 @@
+use crate::error::Error;
+use crate::index_mapping::IndexMapping;
+use crate::limits::DecodeLimits;
+use crate::sketch::{visit_encoded, EncodedItem};
+
+/// A read-only view over an encoded sketch, which answers the count, the extremes and the
+/// quantiles straight from the encoded bins, without decoding them into stores.
+///
+/// The bins are read through once when the view is created, to check the encoding and to
+/// gather the counts and the extremes, and once more for each quantile. Nothing is allocated
+/// when the bins of each store are encoded in ascending order of index, as the stores of this
+/// crate and of the other implementations encode them. Otherwise, as in the concatenation of
+/// several encodings, the bins are sorted into a buffer when the view is created, and the
+/// quantiles are read from it. To query many quantiles, decoding the sketch into a
+/// `DDSketchSnapshot` is faster.
+///
+/// ```rust
+///     use sketches_rust::{DDSketch, EncodedSketchView};
+///     let mut d = DDSketch::unbounded_dense(2e-2).unwrap();
+///     d.accept(1.0);
+///     d.accept(2.0);
+///     d.accept(3.0);
+///     let encoded = d.encode().unwrap();
+///     let view = EncodedSketchView::new(&encoded).unwrap();
+///     assert_eq!(view.get_count(), 3.0);
+///     assert_eq!(view.get_value_at_quantile(0.5), d.get_value_at_quantile(0.5));
+/// ```
+pub struct EncodedSketchView<'a> {
+    bytes: &'a [u8],
+    index_mapping: IndexMapping,
+    /// The index below which the bins belong to the zero bucket, if the sketch has a zero
+    /// threshold.
+    zero_index: Option<i32>,
+    zero_count: f64,
+    negative_bins: BinsSummary,
+    positive_bins: BinsSummary,
+    /// The bins in ascending order of index for each sign, if they are not encoded that way.
+    sorted_bins: Option<Vec<Bin>>,
+}
+
+/// The total count and the index range of the bins of one sign, out of the zero bucket.
+#[derive(Clone, Copy)]
+struct BinsSummary {
+    count: f64,
+    min_index: i32,
+    max_index: i32,
+}
+
+impl BinsSummary {
+    fn new() -> BinsSummary {
+        BinsSummary {
+            count: 0.0,
+            min_index: i32::MAX,
+            max_index: i32::MIN,
+        }
+    }
+
+    fn add(&mut self, index: i32, count: f64) {
+        self.count += count;
+        self.min_index = self.min_index.min(index);
+        self.max_index = self.max_index.max(index);
+    }
+
+    fn is_empty(&self) -> bool {
+        self.max_index < self.min_index
+    }
+}
+
+impl<'a> EncodedSketchView<'a> {
+    pub fn new(bytes: &'a [u8]) -> Result<EncodedSketchView<'a>, Error> {
+        let mut index_mapping = None;
+        let mut zero_threshold: f64 = 0.0;
+        let mut last_negative_index = None;
+        let mut last_positive_index = None;
+        let mut is_sorted = true;
+        visit_encoded(bytes, &DecodeLimits::default(), |item| {
+            let (last_index, index) = match item {
+                EncodedItem::IndexMapping(decoded_index_mapping) => {
+                    match index_mapping.take() {
+                        Some(mapping) if mapping != decoded_index_mapping => {
+                            return Err(Error::UnmatchedMapping {
+                                expected: mapping,
+                                actual: decoded_index_mapping,
+                            });
+                        }
+                        _ => index_mapping = Some(decoded_index_mapping),
+                    }
+                    return Ok(());
+                }
+                EncodedItem::ZeroThreshold(threshold) => {
+                    zero_threshold = zero_threshold.max(threshold);
+                    return Ok(());
+                }
+                EncodedItem::ZeroCount(_) | EncodedItem::ValueCounters(_) => return Ok(()),
+                EncodedItem::NegativeBin(index, _) => (&mut last_negative_index, index),
+                EncodedItem::PositiveBin(index, _) => (&mut last_positive_index, index),
+            };
+            if last_index.is_some_and(|last_index| last_index >= index) {
+                is_sorted = false;
+            }
+            *last_index = Some(index);
+            Ok(())
+        })?;
+        let index_mapping = index_mapping.ok_or(Error::InvalidEncoding {
+            offset: bytes.len(),
+            flag: None,
+            reason: "No IndexMapping decoded.",
+        })?;
+
+        // As `DDSketch::decode` does, the bins entirely below the zero threshold go to the zero
+        // bucket.
+        let zero_index = (zero_threshold > 0.0).then(|| {
+            index_mapping.index(f64::max(
+                zero_threshold,
+                index_mapping.min_indexable_value(),
+            ))
+        });
+        let mut view = EncodedSketchView {
+            bytes,
+            index_mapping,
+            zero_index,
+            zero_count: 0.0,
+            negative_bins: BinsSummary::new(),
+            positive_bins: BinsSummary::new(),
+            sorted_bins: None,
+        };
+        if !is_sorted {
+            view.sorted_bins = Some(view.sort_bins());
+        }
+        let mut zero_count = 0.0;
+        let mut negative_bins = BinsSummary::new();
+        let mut positive_bins = BinsSummary::new();
+        view.visit_bins(|item| match item {
+            Bin::Zero(count) => zero_count += count,
+            Bin::Negative(index, count) => negative_bins.add(index, count),
+            Bin::Positive(index, count) => positive_bins.add(index, count),
+        });
+        view.zero_count = zero_count;
+        view.negative_bins = negative_bins;
+        view.positive_bins = positive_bins;
+        Ok(view)
+    }
+
+    pub fn get_index_mapping(&self) -> &IndexMapping {
+        &self.index_mapping
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.get_count() == 0.0
+    }
+
+    pub fn get_count(&self) -> f64 {
+        self.zero_count + self.negative_bins.count + self.positive_bins.count
+    }
+
+    pub fn get_min(&self) -> Option<f64> {
+        if !self.negative_bins.is_empty() {
+            Some(-self.index_mapping.value(self.negative_bins.max_index))
+        } else if self.zero_count > 0.0 {
+            Some(0.0)
+        } else if !self.positive_bins.is_empty() {
+            Some(self.index_mapping.value(self.positive_bins.min_index))
+        } else {
+            None
+        }
+    }
+
+    pub fn get_max(&self) -> Option<f64> {
+        if !self.positive_bins.is_empty() {
+            Some(self.index_mapping.value(self.positive_bins.max_index))
+        } else if self.zero_count > 0.0 {
+            Some(0.0)
+        } else if !self.negative_bins.is_empty() {
+            Some(-self.index_mapping.value(self.negative_bins.min_index))
+        } else {
+            None
+        }
+    }
+
+    /// Returns the same value as `DDSketch::get_value_at_quantile` on the decoded sketch, up to
+    /// the rounding of the cumulative counts.
+    pub fn get_value_at_quantile(&self, quantile: f64) -> Option<f64> {
+        if !(0.0..=1.0).contains(&quantile) {
+            return None;
+        }
+
+        let count = self.get_count();
+        if count <= 0.0 {
+            return None;
+        }
+
+        let rank = quantile * (count - 1.0);
+        let negative_count = self.negative_bins.count;
+        if rank < negative_count {
+            // The negative bins are encoded in descending order of value, so the bin of the rank
+            // is the last one with fewer than `negative_count - rank` values below its index.
+            let mut below = 0.0;
+            let mut max_index = self.negative_bins.min_index;
+            self.visit_bins(|item| {
+                if let Bin::Negative(index, count) = item {
+                    if below < negative_count - rank {
+                        max_index = index;
+                    }
+                    below += count;
+                }
+            });
+            return Some(-self.index_mapping.value(max_index));
+        }
+
+        let mut n = negative_count + self.zero_count;
+        if rank < n {
+            return Some(0.0);
+        }
+
+        let mut rank_index = None;
+        self.visit_bins(|item| {
+            if let Bin::Positive(index, count) = item {
+                n += count;
+                if n > rank && rank_index.is_none() {
+                    rank_index = Some(index);
+                }
+            }
+        });
+        let index = rank_index.unwrap_or(self.positive_bins.max_index);
+        Some(self.index_mapping.value(index))
+    }
+
+    /// Passes the non-empty bins to `visit`, along with the zero count, the bins below the zero
+    /// threshold being counted as zeros.
+    fn visit_bins<F: FnMut(Bin)>(&self, mut visit: F) {
+        if let Some(sorted_bins) = &self.sorted_bins {
+            sorted_bins.iter().for_each(|bin| visit(*bin));
+            return;
+        }
+        let zero_index = self.zero_index.unwrap_or(i32::MIN);
+        // The encoding has been read through when creating the view.
+        let _ = visit_encoded(self.bytes, &DecodeLimits::default(), |item| {
+            match item {
+                EncodedItem::ZeroCount(count) => visit(Bin::Zero(count)),
+                EncodedItem::NegativeBin(index, count) | EncodedItem::PositiveBin(index, count)
+                    if index < zero_index =>
+                {
+                    visit(Bin::Zero(count))
+                }
+                EncodedItem::NegativeBin(index, count) => visit(Bin::Negative(index, count)),
+                EncodedItem::PositiveBin(index, count) => visit(Bin::Positive(index, count)),
+                _ => {}
+            }
+            Ok(())
+        });
+    }
+
+    /// Returns the bins of `visit_bins` with the bins of each sign in ascending order of index,
+    /// those of the same index being merged.
+    fn sort_bins(&self) -> Vec<Bin> {
+        let mut bins = Vec::new();
+        self.visit_bins(|bin| bins.push(bin));
+        bins.sort_unstable_by_key(|bin| match *bin {
+            Bin::Zero(_) => (0, 0),
+            Bin::Negative(index, _) => (1, index),
+            Bin::Positive(index, _) => (2, index),
+        });
+        bins.dedup_by(|bin, kept| match (kept, *bin) {
+            (Bin::Zero(count), Bin::Zero(other)) => {
+                *count += other;
+                true
+            }
+            (Bin::Negative(index, count), Bin::Negative(other_index, other))
+            | (Bin::Positive(index, count), Bin::Positive(other_index, other))
+                if *index == other_index =>
+            {
+                *count += other;
+                true
+            }
+            _ => false,
+        });
+        bins
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Bin {
+    Zero(f64),
+    Negative(i32, f64),
+    Positive(i32, f64),
+}
@@ -0,0 +1,230 @@
+use crate::builder::DDSketchBuilder;
+use crate::clock::{Clock, SystemClock};
+use crate::error::Error;
+use crate::sketch::DDSketch;
+use std::time::Duration;
+
+/// A sketch of the values accepted over the trailing intervals of time.
+///
+/// The sketch holds a ring of sub-sketches, one per interval, all built from the same builder
+/// and therefore sharing one mapping. The ring rotates as the clock moves forward: values are
+/// recorded into the sub-sketch of the current interval, and the sub-sketches of intervals
+/// older than the window are cleared for reuse.
+///
+/// Queries merge the sub-sketches of the window incrementally. Until the next rotation, the
+/// merges of the closed intervals are cached for each number of trailing intervals, each one
+/// built from the previous one, so that every closed interval is merged once per rotation. The
+/// result of the last query is kept as well, and values accepted in the current interval are
+/// recorded into it, so that repeated queries over the same intervals merge nothing.
+///
+/// ```rust
+///     use sketches_rust::{DDSketch, ManualClock, WindowedDDSketch};
+///     use std::sync::Arc;
+///     use std::time::Duration;
+///     let clock = Arc::new(ManualClock::default());
+///     let mut d = WindowedDDSketch::with_clock(
+///         DDSketch::builder(0.02),
+///         Duration::from_secs(60),
+///         5,
+///         Arc::clone(&clock),
+///     )
+///     .unwrap();
+///     d.accept(1.0);
+///     clock.advance(Duration::from_secs(5 * 60));
+///     d.accept(2.0);
+///     assert_eq!(d.get_count(), 1.0);
+/// ```
+pub struct WindowedDDSketch<C: Clock = SystemClock> {
+    builder: DDSketchBuilder,
+    clock: C,
+    interval: Duration,
+    sketches: Vec<DDSketch>,
+    current_slot: usize,
+    current_epoch: u64,
+    /// The merges of the closed intervals, the `i`-th one covering the `i + 1` most recent.
+    closed_intervals: Vec<DDSketch>,
+    /// The number of trailing intervals of the last query, along with their merge.
+    merged: Option<(usize, DDSketch)>,
+}
+
+impl WindowedDDSketch<SystemClock> {
+    pub fn new(
+        builder: DDSketchBuilder,
+        interval: Duration,
+        num_intervals: usize,
+    ) -> Result<WindowedDDSketch<SystemClock>, Error> {
+        WindowedDDSketch::with_clock(builder, interval, num_intervals, SystemClock)
+    }
+}
+
+impl<C: Clock> WindowedDDSketch<C> {
+    pub fn with_clock(
+        builder: DDSketchBuilder,
+        interval: Duration,
+        num_intervals: usize,
+        clock: C,
+    ) -> Result<WindowedDDSketch<C>, Error> {
+        if interval.is_zero() {
+            return Err(Error::InvalidArgument("The interval must be positive."));
+        }
+        if num_intervals == 0 {
+            return Err(Error::InvalidArgument(
+                "The number of intervals must be positive.",
+            ));
+        }
+        let sketches = (0..num_intervals)
+            .map(|_| builder.build())
+            .collect::<Result<Vec<_>, Error>>()?;
+        let current_epoch = epoch(clock.now(), interval);
+        Ok(WindowedDDSketch {
+            builder,
+            clock,
+            interval,
+            sketches,
+            current_slot: 0,
+            current_epoch,
+            closed_intervals: Vec::new(),
+            merged: None,
+        })
+    }
+
+    pub fn accept(&mut self, value: f64) {
+        self.accept_with_count(value, 1.0);
+    }
+
+    pub fn accept_with_count(&mut self, value: f64, count: f64) {
+        self.rotate();
+        self.sketches[self.current_slot].accept_with_count(value, count);
+        if let Some((num_intervals, merged)) = self.merged.as_mut() {
+            if *num_intervals > 0 {
+                merged.accept_with_count(value, count);
+            }
+        }
+    }
+
+    pub fn clear(&mut self) {
+        for sketch in self.sketches.iter_mut() {
+            sketch.clear();
+        }
+        self.closed_intervals.clear();
+        self.merged = None;
+    }
+
+    pub fn get_count(&mut self) -> f64 {
+        self.rotate();
+        self.sketches.iter().map(|sketch| sketch.get_count()).sum()
+    }
+
+    pub fn get_value_at_quantile(&mut self, quantile: f64) -> Option<f64> {
+        self.get_merged().get_value_at_quantile(quantile)
+    }
+
+    /// Returns the merge of the sub-sketches of all the intervals of the window, the current
+    /// one included.
+    pub fn get_merged(&mut self) -> &DDSketch {
+        self.get_merged_last(self.sketches.len())
+    }
+
+    /// Returns the merge of the sub-sketches of the `num_intervals` most recent intervals, the
+    /// current one included.
+    pub fn get_merged_last(&mut self, num_intervals: usize) -> &DDSketch {
+        self.rotate();
+        let num_intervals = num_intervals.min(self.sketches.len());
+        let merged = match self.merged.take() {
+            Some((cached, merged)) if cached == num_intervals => merged,
+            _ => {
+                let mut merged = self.builder.build_validated();
+                if num_intervals > 1 {
+                    merged.merge_with_same_mapping(self.get_closed_intervals(num_intervals - 1));
+                }
+                if num_intervals > 0 {
+                    merged.merge_with_same_mapping(&self.sketches[self.current_slot]);
+                }
+                merged
+            }
+        };
+        &self.merged.insert((num_intervals, merged)).1
+    }
+
+    /// Returns the merge of the `num_intervals` most recent closed intervals, extending the
+    /// cached merges up to it.
+    fn get_closed_intervals(&mut self, num_intervals: usize) -> &DDSketch {
+        while self.closed_intervals.len() < num_intervals {
+            let age = self.closed_intervals.len() + 1;
+            let mut closed_intervals = self.builder.build_validated();
+            if let Some(previous) = self.closed_intervals.last() {
+                closed_intervals.merge_with_same_mapping(previous);
+            }
+            closed_intervals.merge_with_same_mapping(&self.sketches[self.slot_of_age(age)]);
+            self.closed_intervals.push(closed_intervals);
+        }
+        &self.closed_intervals[num_intervals - 1]
+    }
+
+    fn rotate(&mut self) {
+        let now = epoch(self.clock.now(), self.interval);
+        if now <= self.current_epoch {
+            return;
+        }
+        let elapsed = now - self.current_epoch;
+        let num_slots = self.sketches.len();
+        for _ in 0..elapsed.min(num_slots as u64) {
+            self.current_slot = (self.current_slot + 1) % num_slots;
+            self.sketches[self.current_slot].clear();
+        }
+        self.current_epoch = now;
+        self.closed_intervals.clear();
+        self.merged = None;
+    }
+
+    fn slot_of_age(&self, age: usize) -> usize {
+        (self.current_slot + self.sketches.len() - age) % self.sketches.len()
+    }
+}
+
+fn epoch(now: Duration, interval: Duration) -> u64 {
+    (now.as_nanos() / interval.as_nanos()) as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::ManualClock;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_closed_intervals_merged_once() {
+        let clock = Arc::new(ManualClock::default());
+        let mut sketch = WindowedDDSketch::with_clock(
+            DDSketch::builder(2e-2),
+            Duration::from_secs(1),
+            4,
+            clock.clone(),
+        )
+        .unwrap();
+        for value in [1.0, 2.0, 3.0] {
+            sketch.accept(value);
+            clock.advance(Duration::from_secs(1));
+        }
+        sketch.accept(4.0);
+        assert_eq!(3.0, sketch.get_merged_last(3).get_count());
+        assert_eq!(2, sketch.closed_intervals.len());
+
+        // Marks the cached merges: the queries that follow only read them.
+        for closed_intervals in sketch.closed_intervals.iter_mut() {
+            closed_intervals.accept(1000.0);
+        }
+        assert_eq!(3.0, sketch.get_merged_last(2).get_count());
+        assert_eq!(4.0, sketch.get_merged_last(3).get_count());
+        sketch.accept(5.0);
+        assert_eq!(5.0, sketch.get_merged_last(3).get_count());
+        assert_eq!(2, sketch.closed_intervals.len());
+
+        // The full window extends the cached merges by the oldest interval only.
+        assert_eq!(6.0, sketch.get_merged().get_count());
+        assert_eq!(3, sketch.closed_intervals.len());
+
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(4.0, sketch.get_merged().get_count());
+    }
+}
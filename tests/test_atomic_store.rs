@@ -0,0 +1,132 @@
+use sketches_rust::{
+    AtomicDenseStore, DDSketch, DecodeLimits, Error, IndexMapping, IndexMappingLayout,
+};
+use std::sync::Arc;
+use std::thread;
+
+#[test]
+fn test_atomic_store_concurrent_recording() {
+    let mapping = IndexMapping::with_relative_accuracy(IndexMappingLayout::LogCubic, 2e-2).unwrap();
+    let store = Arc::new(AtomicDenseStore::with_value_range(mapping, 1.0, 1e4).unwrap());
+
+    let handles: Vec<_> = (0..8)
+        .map(|_| {
+            let store = Arc::clone(&store);
+            thread::spawn(move || {
+                for i in 1..10_001 {
+                    store.accept(i as f64);
+                }
+            })
+        })
+        .collect();
+    for handle in handles {
+        handle.join().unwrap();
+    }
+    assert_eq!(80_000, store.get_total_count());
+
+    let sketch = store.snapshot_and_reset();
+    assert_eq!(0, store.get_total_count());
+    assert_eq!(80_000.0, sketch.get_count());
+
+    let mut expected = DDSketch::unbounded_dense(2e-2).unwrap();
+    for i in 1..10_001 {
+        expected.accept_with_count(i as f64, 8.0);
+    }
+    for quantile in [0.0, 0.25, 0.5, 0.99, 1.0] {
+        assert_eq!(
+            expected.get_value_at_quantile(quantile),
+            sketch.get_value_at_quantile(quantile)
+        );
+    }
+    // The exported sketch can be merged with regular sketches sharing the mapping.
+    expected.merge_with(&sketch).unwrap();
+}
+
+#[test]
+fn test_atomic_store_collapse_to_edges() {
+    let mapping = IndexMapping::with_relative_accuracy(IndexMappingLayout::LOG, 1e-2).unwrap();
+    let store = AtomicDenseStore::with_value_range(mapping, 10.0, 100.0).unwrap();
+    store.accept(1.0);
+    store.accept(50.0);
+    store.accept_with_count(1e9, 2);
+    assert_eq!(4, store.get_total_count());
+
+    let sketch = store.snapshot_and_reset();
+    let min = sketch.get_min().unwrap();
+    let max = sketch.get_max().unwrap();
+    assert!(f64::abs(min - 10.0) / 10.0 <= 1e-2);
+    assert!(f64::abs(max - 100.0) / 100.0 <= 1e-2);
+    assert_eq!(Some(min), sketch.get_value_at_quantile(0.2));
+    assert_eq!(Some(max), sketch.get_value_at_quantile(0.9));
+
+    store.add(store.get_min_index() - 10, 1);
+    assert_eq!(1, store.get_total_count());
+    assert!(store.snapshot_and_reset().get_max().unwrap() < 10.0 * 1.02);
+}
+
+#[test]
+fn test_atomic_store_zero_and_negative_values() {
+    let mapping = IndexMapping::with_relative_accuracy(IndexMappingLayout::LOG, 1e-2).unwrap();
+    let store = AtomicDenseStore::with_value_range(mapping, 10.0, 100.0).unwrap();
+    store.accept(50.0);
+    store.accept_with_count(0.0, 3);
+    assert!(store.try_accept(-0.0).is_ok());
+    assert!(store.try_accept(-5.0).is_err());
+    assert!(store.try_accept_with_count(f64::NAN, 2).is_err());
+    store.accept(f64::NEG_INFINITY);
+    assert_eq!(5, store.get_total_count());
+    assert_eq!(4, store.get_zero_count());
+    assert_eq!(4, store.get_dropped_count());
+
+    let sketch = store.snapshot_and_reset();
+    assert_eq!(5.0, sketch.get_count());
+    assert_eq!(Some(0.0), sketch.get_min());
+    assert_eq!(Some(0.0), sketch.get_value_at_quantile(0.5));
+    assert_eq!(4.0, sketch.get_dropped_count());
+    assert_eq!(2.0, sketch.get_nan_count());
+    assert_eq!(1.0, sketch.get_infinite_count());
+    assert_eq!(0, store.get_zero_count());
+    assert_eq!(0, store.get_dropped_count());
+}
+
+#[test]
+fn test_atomic_store_infinite_values() {
+    let mapping = IndexMapping::with_relative_accuracy(IndexMappingLayout::LOG, 1e-2).unwrap();
+    let store = AtomicDenseStore::with_value_range(mapping, 10.0, 100.0).unwrap();
+    let mut expected = DDSketch::builder(1e-2)
+        .layout(IndexMappingLayout::LOG)
+        .build()
+        .unwrap();
+    for value in [50.0, f64::INFINITY, f64::INFINITY] {
+        expected.accept(value);
+        store.accept(value);
+    }
+    assert!(store.try_accept(f64::INFINITY).is_err());
+    expected.accept(f64::INFINITY);
+
+    let sketch = store.snapshot_and_reset();
+    assert_eq!(expected.get_count(), sketch.get_count());
+    assert_eq!(expected.get_max(), sketch.get_max());
+    assert_eq!(expected.get_infinite_count(), sketch.get_infinite_count());
+    assert_eq!(expected.get_dropped_count(), sketch.get_dropped_count());
+    assert_eq!(3.0, sketch.get_infinite_count());
+}
+
+#[test]
+fn test_atomic_store_invalid_range() {
+    let mapping = IndexMapping::with_relative_accuracy(IndexMappingLayout::LOG, 1e-2).unwrap();
+    assert!(AtomicDenseStore::with_value_range(mapping.clone(), 0.0, 1.0).is_err());
+    assert!(AtomicDenseStore::with_value_range(mapping.clone(), 2.0, 1.0).is_err());
+    assert!(AtomicDenseStore::with_value_range(mapping.clone(), 1.0, f64::INFINITY).is_err());
+
+    // The range of the doubles at a fine accuracy takes far more bins than the default limits.
+    let fine = IndexMapping::with_relative_accuracy(IndexMappingLayout::LOG, 1e-6).unwrap();
+    match AtomicDenseStore::with_value_range(fine, 1e-300, 1e300) {
+        Err(Error::LimitExceeded(_)) => {}
+        _ => panic!("expected a too wide index span"),
+    }
+    let limits = DecodeLimits::new().max_index_span(100);
+    assert!(AtomicDenseStore::with_value_range_and_limits(mapping, 1.0, 2.0, &limits).is_ok());
+    let mapping = IndexMapping::with_relative_accuracy(IndexMappingLayout::LOG, 1e-2).unwrap();
+    assert!(AtomicDenseStore::with_value_range_and_limits(mapping, 1.0, 1e3, &limits).is_err());
+}
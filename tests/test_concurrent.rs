@@ -0,0 +1,99 @@
+use sketches_rust::{ConcurrentDDSketch, DDSketch, StoreType};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+
+const THREADS: usize = 8;
+const VALUES_PER_THREAD: usize = 20_000;
+
+fn value(thread: usize, i: usize) -> f64 {
+    ((thread * VALUES_PER_THREAD + i) % 5000) as f64 - 1000.0
+}
+
+#[test]
+fn test_concurrent_sketch_no_lost_counts() {
+    let sketch = Arc::new(ConcurrentDDSketch::with_shards(DDSketch::builder(2e-2), 3).unwrap());
+    let done = Arc::new(AtomicBool::new(false));
+
+    // Readers merge the shards while the writers are recording.
+    let readers: Vec<_> = (0..2)
+        .map(|_| {
+            let sketch = Arc::clone(&sketch);
+            let done = Arc::clone(&done);
+            thread::spawn(move || {
+                let mut last_count = 0.0;
+                while !done.load(Ordering::Acquire) {
+                    let count = sketch.merged().get_count();
+                    assert!(count >= last_count);
+                    last_count = count;
+                }
+            })
+        })
+        .collect();
+
+    let writers: Vec<_> = (0..THREADS)
+        .map(|t| {
+            let sketch = Arc::clone(&sketch);
+            thread::spawn(move || {
+                for i in 0..VALUES_PER_THREAD {
+                    sketch.accept(value(t, i));
+                }
+            })
+        })
+        .collect();
+    for writer in writers {
+        writer.join().unwrap();
+    }
+    done.store(true, Ordering::Release);
+    for reader in readers {
+        reader.join().unwrap();
+    }
+
+    let mut expected = DDSketch::unbounded_dense(2e-2).unwrap();
+    for t in 0..THREADS {
+        for i in 0..VALUES_PER_THREAD {
+            expected.accept(value(t, i));
+        }
+    }
+    assert_eq!((THREADS * VALUES_PER_THREAD) as f64, sketch.get_count());
+    assert_eq!(expected.get_count(), sketch.merged().get_count());
+    for quantile in [0.0, 0.1, 0.5, 0.9, 0.99, 1.0] {
+        assert_eq!(
+            expected.get_value_at_quantile(quantile),
+            sketch.get_value_at_quantile(quantile)
+        );
+    }
+    assert_eq!(expected.get_min(), sketch.get_min());
+    assert_eq!(expected.get_max(), sketch.get_max());
+}
+
+#[test]
+fn test_concurrent_sketch_encode_and_merge() {
+    let sketch = ConcurrentDDSketch::new(
+        DDSketch::builder(2e-2).store(StoreType::CollapsingLowestDense(512)),
+    )
+    .unwrap();
+    assert!(sketch.is_empty());
+    for i in 1..101 {
+        sketch.accept(i as f64);
+    }
+    let mut other = DDSketch::collapsing_lowest_dense(2e-2, 512).unwrap();
+    other.accept(1000.0);
+    sketch.merge_with(&other).unwrap();
+    sketch
+        .decode_and_merge_with(&other.encode().unwrap())
+        .unwrap();
+
+    let decoded = DDSketch::decode(&sketch.encode().unwrap()).unwrap();
+    assert_eq!(102.0, decoded.get_count());
+    assert_eq!(sketch.get_max(), decoded.get_max());
+
+    let mut unmatched = DDSketch::unbounded_dense(1e-2).unwrap();
+    unmatched.accept(1.0);
+    assert!(sketch.merge_with(&unmatched).is_err());
+
+    sketch.clear();
+    assert!(sketch.is_empty());
+    assert_eq!(None, sketch.get_value_at_quantile(0.5));
+    assert!(ConcurrentDDSketch::with_shards(DDSketch::builder(2e-2), 0).is_err());
+}
@@ -0,0 +1,96 @@
+use sketches_rust::{DDSketch, DecayedDDSketch, ManualClock};
+use std::sync::Arc;
+use std::time::Duration;
+
+const MINUTE: Duration = Duration::from_secs(60);
+const EPSILON: f64 = 1e-9;
+
+fn decayed(clock: &Arc<ManualClock>) -> DecayedDDSketch<Arc<ManualClock>> {
+    DecayedDDSketch::with_clock(DDSketch::builder(2e-2), MINUTE, Arc::clone(clock)).unwrap()
+}
+
+#[test]
+fn test_decayed_sketch_weights() {
+    let clock = Arc::new(ManualClock::new(Duration::from_secs(1_000_000)));
+    let mut sketch = decayed(&clock);
+
+    sketch.accept_with_count(10.0, 4.0);
+    assert!((sketch.get_count() - 4.0).abs() < EPSILON);
+    clock.advance(MINUTE);
+    assert!((sketch.get_count() - 2.0).abs() < EPSILON);
+    assert!((sketch.get_sum().unwrap() - 20.0).abs() / 20.0 <= 2e-2);
+
+    // The new values weigh twice as much as the old ones.
+    sketch.accept_with_count(1000.0, 4.0);
+    assert!((sketch.get_count() - 6.0).abs() < EPSILON);
+    let average = sketch.get_average().unwrap();
+    let expected_average = (10.0 * 2.0 + 1000.0 * 4.0) / 6.0;
+    assert!((average - expected_average).abs() / expected_average <= 2e-2);
+    let median = sketch.get_value_at_quantile(0.5).unwrap();
+    assert!((median - 1000.0).abs() / 1000.0 <= 2e-2);
+    assert!((sketch.get_min().unwrap() - 10.0).abs() / 10.0 <= 2e-2);
+    assert!((sketch.get_max().unwrap() - 1000.0).abs() / 1000.0 <= 2e-2);
+
+    let exported = sketch.to_sketch();
+    assert!((exported.get_count() - 6.0).abs() < EPSILON);
+    assert_eq!(
+        sketch.get_value_at_quantile(0.1),
+        exported.get_value_at_quantile(0.1)
+    );
+
+    sketch.clear();
+    assert!(sketch.is_empty());
+    assert_eq!(0.0, sketch.get_count());
+}
+
+#[test]
+fn test_decayed_sketch_renormalization() {
+    let clock = Arc::new(ManualClock::default());
+    let mut sketch = decayed(&clock);
+
+    // Without renormalization, the weights would overflow after about 1024 half-lives.
+    for _ in 0..5_000 {
+        sketch.accept(1.0);
+        clock.advance(MINUTE);
+    }
+    sketch.accept_with_count(2.0, 2.0);
+    let count = sketch.get_count();
+    assert!(count.is_finite());
+    // The geometric series 1/2 + 1/4 + ... converges to 1.
+    assert!((count - 3.0).abs() < 1e-6);
+    let mut expected = DDSketch::builder(2e-2).build().unwrap();
+    expected.accept(1.0);
+    expected.accept(2.0);
+    assert_eq!(
+        expected.get_value_at_quantile(0.0),
+        sketch.get_value_at_quantile(0.25)
+    );
+    assert_eq!(
+        expected.get_value_at_quantile(1.0),
+        sketch.get_value_at_quantile(1.0)
+    );
+}
+
+#[test]
+fn test_decayed_sketch_extremes_dont_decay() {
+    let clock = Arc::new(ManualClock::default());
+    let mut sketch = decayed(&clock);
+
+    sketch.accept(1000.0);
+    clock.advance(MINUTE * 100);
+    sketch.accept(10.0);
+    // The old value weighs about 2^-100 of the new one, which alone makes up the quantiles.
+    let max = sketch.get_value_at_quantile(1.0).unwrap();
+    assert!((max - 10.0).abs() / 10.0 <= 2e-2);
+    assert!((sketch.get_max().unwrap() - 1000.0).abs() / 1000.0 <= 2e-2);
+    assert!((sketch.get_min().unwrap() - 10.0).abs() / 10.0 <= 2e-2);
+
+    sketch.clear();
+    sketch.accept(10.0);
+    assert_eq!(sketch.get_min(), sketch.get_max());
+}
+
+#[test]
+fn test_decayed_sketch_invalid_half_life() {
+    assert!(DecayedDDSketch::new(DDSketch::builder(2e-2), Duration::ZERO).is_err());
+}
@@ -0,0 +1,232 @@
+use sketches_rust::{DDSketch, DecodeLimits, EncodedSketchView, Error, StoreType};
+
+fn var_long(value: i64) -> Vec<u8> {
+    let mut value = value as u64;
+    let mut bytes = Vec::new();
+    while value >= 0x80 {
+        bytes.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    bytes.push(value as u8);
+    bytes
+}
+
+fn signed_var_long(value: i64) -> Vec<u8> {
+    var_long((value << 1) ^ (value >> 63))
+}
+
+/// The encoding of an index mapping, followed by `blocks`.
+fn encoding(blocks: &[&[u8]]) -> Vec<u8> {
+    let mut bytes = DDSketch::unbounded_dense(2e-2).unwrap().encode().unwrap()[..17].to_vec();
+    for block in blocks {
+        bytes.extend_from_slice(block);
+    }
+    bytes
+}
+
+/// A positive store block of contiguous counts of 1.
+fn contiguous_block(num_bins: i64, index: i64, index_delta: i64) -> Vec<u8> {
+    let mut bytes = vec![0x0d];
+    bytes.extend(var_long(num_bins));
+    bytes.extend(signed_var_long(index));
+    bytes.extend(signed_var_long(index_delta));
+    // The count 1.0 is a single byte.
+    bytes.extend(std::iter::repeat_n(
+        0x02,
+        num_bins.clamp(0, 1 << 16) as usize,
+    ));
+    bytes
+}
+
+fn decode_all(bytes: &[u8]) {
+    let _ = DDSketch::decode(bytes);
+    let _ = DDSketch::decode_from(bytes);
+    let _ = DDSketch::decode_strict(bytes);
+    let _ = DDSketch::from_proto_bytes(bytes);
+    let _ = DDSketch::merge_all_encoded([bytes, bytes]);
+    if let Ok(view) = EncodedSketchView::new(bytes) {
+        let _ = view.get_value_at_quantile(0.5);
+    }
+    let mut sketch = DDSketch::unbounded_dense(2e-2).unwrap();
+    let _ = sketch.decode_and_merge_with(bytes);
+}
+
+#[test]
+fn test_decode_limits() {
+    let bytes = encoding(&[&contiguous_block(100, 0, 1)]);
+    assert_eq!(100.0, DDSketch::decode(&bytes).unwrap().get_count());
+    match DDSketch::decode_with_limits(&bytes, DecodeLimits::new().max_num_bins(99)) {
+        Err(Error::LimitExceeded(_)) => {}
+        _ => panic!("expected too many bins"),
+    }
+
+    // Two bins, far apart.
+    let bytes = encoding(&[&contiguous_block(2, -1000, 1 << 23)]);
+    for result in [
+        DDSketch::decode(&bytes),
+        DDSketch::decode_from(bytes.as_slice()),
+        DDSketch::merge_all_encoded([bytes.as_slice()]),
+    ] {
+        match result {
+            Err(Error::LimitExceeded(_)) => {}
+            _ => panic!("expected a too wide index span"),
+        }
+    }
+    let limits = DecodeLimits::new().max_index_span(1 << 24);
+    let sketch = DDSketch::decode_with_limits(&bytes, limits).unwrap();
+    assert_eq!(2.0, sketch.get_count());
+}
+
+#[test]
+fn test_decode_with_limits() {
+    let mut sketch = DDSketch::unbounded_dense(2e-2).unwrap();
+    sketch.accept(1.0);
+    sketch.accept(1e6);
+    let encoded = sketch.encode().unwrap();
+    let mut length_prefixed = Vec::new();
+    sketch
+        .encode_length_prefixed_to(&mut length_prefixed)
+        .unwrap();
+    let proto = sketch.to_proto_bytes().unwrap();
+    let new_sketch = || DDSketch::unbounded_dense(2e-2).unwrap();
+
+    // The default limits let the sketch through, narrower ones don't.
+    let limits = DecodeLimits::new().max_index_span(100);
+    let results = [
+        (
+            DDSketch::decode_from(encoded.as_slice()).map(|_| ()),
+            DDSketch::decode_from_with_limits(encoded.as_slice(), limits).map(|_| ()),
+        ),
+        (
+            DDSketch::decode_length_prefixed_from(length_prefixed.as_slice()).map(|_| ()),
+            DDSketch::decode_length_prefixed_from_with_limits(length_prefixed.as_slice(), limits)
+                .map(|_| ()),
+        ),
+        (
+            DDSketch::merge_all_encoded([encoded.as_slice()]).map(|_| ()),
+            DDSketch::merge_all_encoded_with_limits([encoded.as_slice()], limits).map(|_| ()),
+        ),
+        (
+            DDSketch::from_proto_bytes(&proto).map(|_| ()),
+            DDSketch::from_proto_bytes_with_limits(&proto, limits).map(|_| ()),
+        ),
+        (
+            new_sketch().decode_and_merge_with(&encoded),
+            new_sketch().decode_and_merge_with_limits(&encoded, limits),
+        ),
+        (
+            new_sketch().decode_and_merge_with_reader(encoded.as_slice()),
+            new_sketch().decode_and_merge_with_reader_with_limits(encoded.as_slice(), limits),
+        ),
+        (
+            new_sketch().decode_and_merge_length_prefixed_from(length_prefixed.as_slice()),
+            new_sketch().decode_and_merge_length_prefixed_from_with_limits(
+                length_prefixed.as_slice(),
+                limits,
+            ),
+        ),
+    ];
+    for (default, limited) in results {
+        default.unwrap();
+        match limited {
+            Err(Error::LimitExceeded(_)) => {}
+            _ => panic!("expected a too wide index span"),
+        }
+    }
+}
+
+#[test]
+fn test_decode_and_merge_limits_the_decoded_span() {
+    // The bins already in the sketch don't count against the index span.
+    let mut wide = DDSketch::unbounded_dense(1e-4).unwrap();
+    wide.accept(1e-300);
+    wide.accept(1e300);
+    let mut other = DDSketch::unbounded_dense(1e-4).unwrap();
+    other.accept(1.0);
+    wide.decode_and_merge_with(&other.encode().unwrap())
+        .unwrap();
+    assert_eq!(3.0, wide.get_count());
+
+    // The span of the decoded bins is limited, across the blocks of a store.
+    let bytes = encoding(&[
+        &contiguous_block(1, -1000, 1),
+        &contiguous_block(1, 1 << 23, 1),
+    ]);
+    let mut sketch = DDSketch::unbounded_dense(2e-2).unwrap();
+    match sketch.decode_and_merge_with(&bytes) {
+        Err(Error::LimitExceeded(_)) => {}
+        _ => panic!("expected a too wide index span"),
+    }
+    // Unless the store is bounded.
+    let mut collapsing = DDSketch::builder(2e-2)
+        .store(StoreType::CollapsingLowestDense(64))
+        .build()
+        .unwrap();
+    collapsing.decode_and_merge_with(&bytes).unwrap();
+    assert_eq!(2.0, collapsing.get_count());
+}
+
+#[test]
+fn test_decode_out_of_range_indexes() {
+    for block in [
+        contiguous_block(1, 1 << 31, 1),
+        contiguous_block(3, i64::MAX - 1, i64::MAX),
+        // Index deltas that overflow.
+        [
+            &[0x09, 0x02][..],
+            &signed_var_long(i64::MAX),
+            &signed_var_long(i64::MAX),
+        ]
+        .concat(),
+    ] {
+        let bytes = encoding(&[&block]);
+        match DDSketch::decode(&bytes) {
+            Err(Error::IndexOutOfRange(_)) => {}
+            _ => panic!("expected an index out of range"),
+        }
+        decode_all(&bytes);
+    }
+}
+
+#[test]
+fn test_decode_arbitrary_bytes() {
+    // A store flag with no bin encoding mode.
+    match DDSketch::decode(&encoding(&[&[0x01, 0x00]])) {
+        Err(Error::UnknownFlag(0x01)) => {}
+        _ => panic!("expected an unknown flag"),
+    }
+    // Many more bins than bytes.
+    match DDSketch::decode(&encoding(&[&[0x05], &var_long(i64::MAX)])) {
+        Err(Error::Truncated { .. }) => {}
+        _ => panic!("expected a truncated encoding"),
+    }
+
+    let mut sketch = DDSketch::builder(2e-2)
+        .zero_threshold(0.5)
+        .encode_value_counters(true)
+        .build()
+        .unwrap();
+    for i in -200..200 {
+        sketch.accept_with_count(i as f64 * 1.3, (i % 5 + 5) as f64 * 0.5);
+    }
+    let encoded = sketch.encode().unwrap();
+    let proto = sketch.to_proto_bytes().unwrap();
+    let mut state: u64 = 0x2545f4914f6cdd1d;
+    let mut next = move || {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        state
+    };
+    for source in [&encoded, &proto] {
+        for _ in 0..500 {
+            let mut bytes = source.clone();
+            for _ in 0..next() % 8 + 1 {
+                let i = next() as usize % bytes.len();
+                bytes[i] = next() as u8;
+            }
+            bytes.truncate(next() as usize % (bytes.len() + 1));
+            decode_all(&bytes);
+        }
+    }
+}
@@ -0,0 +1,177 @@
+use sketches_rust::{DDSketch, Error};
+
+fn var_double(value: f64) -> Vec<u8> {
+    let mut bits = ((value + 1.0).to_bits() as i64 - 1.0f64.to_bits() as i64).rotate_left(6) as u64;
+    let mut bytes = Vec::new();
+    for _ in 0..8 {
+        let next = (bits >> 57) as u8;
+        bits <<= 7;
+        if bits == 0 {
+            bytes.push(next);
+            return bytes;
+        }
+        bytes.push(next | 0x80);
+    }
+    bytes.push((bits >> 56) as u8);
+    bytes
+}
+
+/// An encoded sketch, starting with its 17 bytes long index mapping.
+fn valid_encoding() -> Vec<u8> {
+    let mut sketch = DDSketch::unbounded_dense(2e-2).unwrap();
+    sketch.accept(1.0);
+    sketch.accept_with_count(-3.0, 2.5);
+    sketch.encode().unwrap()
+}
+
+fn with(prefix: &[u8], suffix: &[u8]) -> Vec<u8> {
+    let mut bytes = prefix.to_vec();
+    bytes.extend_from_slice(suffix);
+    bytes
+}
+
+fn assert_invalid(bytes: &[u8], offset: usize, flag: Option<u8>, reason: &str) {
+    match DDSketch::decode_strict(bytes) {
+        Err(Error::InvalidEncoding {
+            offset: actual_offset,
+            flag: actual_flag,
+            reason: actual_reason,
+        }) => assert_eq!(
+            (offset, flag, reason),
+            (actual_offset, actual_flag, actual_reason)
+        ),
+        Err(error) => panic!("unexpected error: {}", error),
+        Ok(_) => panic!("decoded an invalid encoding"),
+    }
+}
+
+#[test]
+fn test_decode_strict_matches_decode() {
+    let mut sketches = [
+        DDSketch::unbounded_dense(2e-2).unwrap(),
+        DDSketch::collapsing_lowest_dense(1e-2, 64).unwrap(),
+        DDSketch::builder(2e-2)
+            .zero_threshold(0.5)
+            .encode_value_counters(true)
+            .build()
+            .unwrap(),
+    ];
+    for sketch in sketches.iter_mut() {
+        let encoded = sketch.encode().unwrap();
+        assert_eq!(
+            DDSketch::decode(&encoded).unwrap().encode().unwrap(),
+            DDSketch::decode_strict(&encoded).unwrap().encode().unwrap()
+        );
+        // Unit bins, encoded in several runs.
+        for i in 0..300 {
+            sketch.accept(i as f64 * 3.7 - 100.0);
+        }
+        sketch.accept(0.0);
+        sketch.accept(f64::NAN);
+        sketch.accept_with_count(42.0, 0.25);
+        let encoded = sketch.encode().unwrap();
+        assert_eq!(
+            DDSketch::decode(&encoded).unwrap().encode().unwrap(),
+            DDSketch::decode_strict(&encoded).unwrap().encode().unwrap()
+        );
+    }
+}
+
+#[test]
+fn test_decode_strict_rejects_invalid_counts() {
+    let prefix = valid_encoding();
+    // A positive bin, encoded with index deltas and counts.
+    for count in [f64::NAN, f64::INFINITY, -1.0] {
+        let bytes = with(&prefix, &with(&[0x05, 0x01, 0x06], &var_double(count)));
+        let reason = "The counts must be finite and non-negative.";
+        assert_invalid(&bytes, prefix.len() + 3, Some(0x05), reason);
+        // The zero count.
+        let bytes = with(&prefix, &with(&[0x04], &var_double(count)));
+        assert_invalid(&bytes, prefix.len() + 1, Some(0x04), reason);
+    }
+    let bytes = with(&prefix, &with(&[0x05, 0x01, 0x06], &var_double(0.0)));
+    assert!(DDSketch::decode_strict(&bytes).is_ok());
+}
+
+#[test]
+fn test_decode_strict_rejects_invalid_index_mappings() {
+    let prefix = valid_encoding();
+    let flag = prefix[0];
+    assert_invalid(
+        &with(&prefix, &prefix[..17]),
+        prefix.len(),
+        Some(flag),
+        "Repeated IndexMapping.",
+    );
+    assert_invalid(
+        &prefix[17..],
+        prefix.len() - 17,
+        None,
+        "No IndexMapping decoded.",
+    );
+    for gamma in [1.0, 0.5, -2.0, f64::NAN, f64::INFINITY] {
+        let mapping = with(&with(&[flag], &gamma.to_le_bytes()), &0.0f64.to_le_bytes());
+        assert_invalid(&mapping, 1, Some(flag), "Invalid IndexMapping parameters.");
+    }
+    assert_invalid(
+        &with(&[0x7e], &prefix[1..17]),
+        0,
+        Some(0x7e),
+        "Unknown Index Flag.",
+    );
+}
+
+#[test]
+fn test_decode_strict_rejects_unknown_flags() {
+    let prefix = valid_encoding();
+    assert_invalid(
+        &with(&prefix, &[0x7c]),
+        prefix.len(),
+        Some(0x7c),
+        "Unknown Flag.",
+    );
+    assert_invalid(
+        &with(&prefix, &[0x01, 0x00]),
+        prefix.len(),
+        Some(0x01),
+        "Unknown BinEncodingMode.",
+    );
+    assert_invalid(
+        &with(&prefix, &[0x13, 0x00]),
+        prefix.len(),
+        Some(0x13),
+        "Unknown BinEncodingMode.",
+    );
+}
+
+#[test]
+fn test_decode_strict_rejects_truncated_and_out_of_range() {
+    let prefix = valid_encoding();
+    assert_invalid(
+        &prefix[..12],
+        9,
+        Some(prefix[0]),
+        "Unexpected end of input.",
+    );
+    assert_invalid(
+        &with(&prefix, &[0x05, 0x01, 0x06]),
+        prefix.len() + 3,
+        Some(0x05),
+        "Unexpected end of input.",
+    );
+    // A positive bin, encoded with index deltas only, of index 2^31.
+    assert_invalid(
+        &with(&prefix, &[0x09, 0x01, 0x80, 0x80, 0x80, 0x80, 0x10]),
+        prefix.len() + 2,
+        Some(0x09),
+        "Index out of range.",
+    );
+    // Two positive bins, encoded with index deltas only, of indexes 0 and 2^23, beyond the
+    // default index span.
+    assert_invalid(
+        &with(&prefix, &[0x09, 0x02, 0x00, 0x80, 0x80, 0x80, 0x08]),
+        prefix.len() + 3,
+        Some(0x09),
+        "Index span too large.",
+    );
+}
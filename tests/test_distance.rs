@@ -0,0 +1,71 @@
+use sketches_rust::{
+    kolmogorov_smirnov_distance, quantile_relative_differences, wasserstein_distance, DDSketch,
+};
+
+fn sketch_of(values: impl Iterator<Item = f64>) -> DDSketch {
+    let mut sketch = DDSketch::unbounded_dense(1e-2).unwrap();
+    for value in values {
+        sketch.accept(value);
+    }
+    sketch
+}
+
+#[test]
+fn test_distance_identical() {
+    let a = sketch_of((-500..500).map(|i| i as f64));
+    let b = sketch_of((-500..500).rev().map(|i| i as f64));
+    assert_eq!(Some(0.0), kolmogorov_smirnov_distance(&a, &b).unwrap());
+    assert_eq!(Some(0.0), wasserstein_distance(&a, &b).unwrap());
+    let differences = quantile_relative_differences(&a, &b, &[0.0, 0.5, 0.99])
+        .unwrap()
+        .unwrap();
+    assert_eq!(vec![0.0, 0.0, 0.0], differences);
+}
+
+#[test]
+fn test_distance_shifted() {
+    let a = sketch_of((1..1001).map(|i| i as f64));
+    let b = sketch_of((101..1101).map(|i| i as f64));
+
+    let ks = kolmogorov_smirnov_distance(&a, &b).unwrap().unwrap();
+    assert!(f64::abs(ks - 0.1) < 0.02);
+    let emd = wasserstein_distance(&a, &b).unwrap().unwrap();
+    assert!(f64::abs(emd - 100.0) / 100.0 < 0.05);
+    assert_eq!(emd, wasserstein_distance(&b, &a).unwrap().unwrap());
+
+    let differences = quantile_relative_differences(&a, &b, &[0.5, 1.0])
+        .unwrap()
+        .unwrap();
+    assert!(f64::abs(differences[0] - 100.0 / 600.0) < 0.03);
+    assert!(f64::abs(differences[1] - 100.0 / 1100.0) < 0.03);
+}
+
+#[test]
+fn test_distance_disjoint_with_negative_and_zero() {
+    let a = sketch_of([-3.0, -2.0, 0.0].into_iter());
+    let b = sketch_of([1.0, 2.0, 3.0].into_iter());
+    assert_eq!(Some(1.0), kolmogorov_smirnov_distance(&a, &b).unwrap());
+    let emd = wasserstein_distance(&a, &b).unwrap().unwrap();
+    // Every unit of mass moves from {-3, -2, 0} to {1, 2, 3}: (4 + 4 + 3) / 3.
+    assert!(f64::abs(emd - 11.0 / 3.0) / (11.0 / 3.0) < 0.02);
+}
+
+#[test]
+fn test_distance_empty() {
+    let a = sketch_of([1.0].into_iter());
+    let b = sketch_of([].into_iter());
+    assert_eq!(None, kolmogorov_smirnov_distance(&a, &b).unwrap());
+    assert_eq!(None, wasserstein_distance(&b, &a).unwrap());
+    assert_eq!(None, quantile_relative_differences(&a, &b, &[0.5]).unwrap());
+}
+
+#[test]
+fn test_distance_unmatched_mapping() {
+    let a = sketch_of([1.0].into_iter());
+    let mut b = DDSketch::unbounded_dense(2e-2).unwrap();
+    b.accept(1.0);
+    assert!(kolmogorov_smirnov_distance(&a, &b).is_err());
+    assert!(wasserstein_distance(&a, &b).is_err());
+    assert!(quantile_relative_differences(&a, &b, &[0.5]).is_err());
+    assert!(quantile_relative_differences(&a, &a, &[1.5]).is_err());
+}
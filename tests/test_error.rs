@@ -0,0 +1,136 @@
+use sketches_rust::{DDSketch, DDSketchBuilder, EncodedSketchView, Error, IndexMappingLayout};
+use std::io::{self, Write};
+
+struct FailingWriter;
+
+impl Write for FailingWriter {
+    fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+        Err(io::Error::new(io::ErrorKind::BrokenPipe, "closed"))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+fn encoded() -> Vec<u8> {
+    let mut sketch = DDSketch::unbounded_dense(2e-2).unwrap();
+    sketch.accept(1.0);
+    sketch.encode().unwrap()
+}
+
+#[test]
+fn test_error_unmatched_mapping() {
+    let mut sketch = DDSketch::unbounded_dense(2e-2).unwrap();
+    let other = DDSketch::unbounded_dense(1e-2).unwrap();
+    match sketch.merge_with(&other) {
+        Err(Error::UnmatchedMapping { expected, actual }) => {
+            assert_eq!(&expected, sketch.get_index_mapping());
+            assert_eq!(&actual, other.get_index_mapping());
+        }
+        _ => panic!("expected an unmatched mapping"),
+    }
+    match sketch.decode_and_merge_with(&other.encode().unwrap()) {
+        Err(Error::UnmatchedMapping { .. }) => {}
+        _ => panic!("expected an unmatched mapping"),
+    }
+}
+
+#[test]
+fn test_error_invalid_relative_accuracy() {
+    for relative_accuracy in [0.0, 1.0, -0.5, 2.0] {
+        match DDSketch::unbounded_dense(relative_accuracy) {
+            Err(Error::InvalidRelativeAccuracy(actual)) => assert_eq!(relative_accuracy, actual),
+            _ => panic!("expected an invalid relative accuracy"),
+        }
+    }
+    match DDSketchBuilder::new(f64::NAN)
+        .layout(IndexMappingLayout::LOG)
+        .build()
+    {
+        Err(Error::InvalidRelativeAccuracy(actual)) => assert!(actual.is_nan()),
+        _ => panic!("expected an invalid relative accuracy"),
+    }
+}
+
+#[test]
+fn test_error_decode() {
+    let mut bytes = encoded();
+    let len = bytes.len();
+    match DDSketch::decode(&bytes[..len - 1]) {
+        Err(Error::Truncated { offset }) => assert!(offset < len),
+        _ => panic!("expected a truncated encoding"),
+    }
+    match DDSketch::decode_from(&bytes[..10]) {
+        Err(Error::Truncated { offset: 9 }) => {}
+        _ => panic!("expected a truncated encoding"),
+    }
+
+    bytes.push(0x7c);
+    match DDSketch::decode(&bytes) {
+        Err(Error::UnknownFlag(0x7c)) => {}
+        _ => panic!("expected an unknown flag"),
+    }
+
+    // A positive bin, encoded with index deltas only, of index 2^31.
+    bytes.pop();
+    bytes.extend_from_slice(&[0x09, 0x01, 0x80, 0x80, 0x80, 0x80, 0x10]);
+    match DDSketch::decode(&bytes) {
+        Err(Error::IndexOutOfRange(index)) => assert_eq!(1 << 31, index),
+        _ => panic!("expected an index out of range"),
+    }
+}
+
+fn assert_invalid_encoding<T>(result: Result<T, Error>, offset: usize, reason: &str) {
+    match result {
+        Err(Error::InvalidEncoding {
+            offset: actual_offset,
+            reason: actual_reason,
+            ..
+        }) => assert_eq!((offset, reason), (actual_offset, actual_reason)),
+        Err(error) => panic!("unexpected error: {}", error),
+        Ok(_) => panic!("decoded an invalid encoding"),
+    }
+}
+
+#[test]
+fn test_error_invalid_encoding() {
+    let bytes = encoded();
+    let no_mapping = "No IndexMapping decoded.";
+    assert_invalid_encoding(DDSketch::decode(&bytes[17..]), bytes.len() - 17, no_mapping);
+    assert_invalid_encoding(EncodedSketchView::new(&[]), 0, no_mapping);
+    assert_invalid_encoding(
+        DDSketch::from_proto_bytes(&[33, 0, 0, 0, 0, 0, 0, 240, 63]),
+        9,
+        no_mapping,
+    );
+
+    // A gamma below 1.
+    let mut mapping = bytes[..1].to_vec();
+    mapping.extend_from_slice(&0.5f64.to_le_bytes());
+    mapping.extend_from_slice(&0.0f64.to_le_bytes());
+    let invalid_mapping = "Invalid IndexMapping parameters.";
+    assert_invalid_encoding(DDSketch::decode(&mapping), 1, invalid_mapping);
+    assert_invalid_encoding(EncodedSketchView::new(&mapping), 1, invalid_mapping);
+    assert_invalid_encoding(
+        DDSketch::from_proto_bytes(&[10, 9, 9, 0, 0, 0, 0, 0, 0, 224, 63]),
+        1,
+        invalid_mapping,
+    );
+}
+
+#[test]
+fn test_error_source() {
+    let sketch = DDSketch::unbounded_dense(2e-2).unwrap();
+    let error = sketch.encode_to(FailingWriter).unwrap_err();
+    let source = std::error::Error::source(&error).unwrap();
+    assert_eq!(
+        io::ErrorKind::BrokenPipe,
+        source.downcast_ref::<io::Error>().unwrap().kind()
+    );
+    assert_eq!("Io Error: closed", error.to_string());
+
+    let boxed =
+        || -> Result<DDSketch, Box<dyn std::error::Error>> { Ok(DDSketch::decode(&[0x7c])?) };
+    assert_eq!("Unknown flag: 0x7c", boxed().err().unwrap().to_string());
+}
@@ -0,0 +1,150 @@
+use sketches_rust::{DDSketch, IndexMappingLayout};
+
+// mapping { gamma: 1.02 }
+// positiveValues { contiguousBinCounts: [1, 2], contiguousBinIndexOffset: 5 }
+// negativeValues { binCounts: { -3: 4 } }
+// zeroCount: 1
+const SPARSE_GOLDEN: [u8; 57] = [
+    10, 9, 9, 82, 184, 30, 133, 235, 81, 240, 63, 18, 20, 18, 16, 0, 0, 0, 0, 0, 0, 240, 63, 0, 0,
+    0, 0, 0, 0, 0, 64, 24, 10, 26, 13, 10, 11, 8, 5, 17, 0, 0, 0, 0, 0, 0, 16, 64, 33, 0, 0, 0, 0,
+    0, 0, 240, 63,
+];
+
+// The same sketch, with the negative bins as contiguous counts:
+// negativeValues { contiguousBinCounts: [4], contiguousBinIndexOffset: -3 }
+const DENSE_GOLDEN: [u8; 56] = [
+    10, 9, 9, 82, 184, 30, 133, 235, 81, 240, 63, 18, 20, 18, 16, 0, 0, 0, 0, 0, 0, 240, 63, 0, 0,
+    0, 0, 0, 0, 0, 64, 24, 10, 26, 12, 18, 8, 0, 0, 0, 0, 0, 0, 16, 64, 24, 5, 33, 0, 0, 0, 0, 0,
+    0, 240, 63,
+];
+
+#[test]
+fn test_proto_golden() {
+    let sketch = DDSketch::from_proto_bytes(&SPARSE_GOLDEN).unwrap();
+    let mapping = sketch.get_index_mapping();
+    assert_eq!(IndexMappingLayout::LOG, mapping.layout());
+    assert_eq!(1.02, mapping.gamma());
+    assert_eq!(0.0, mapping.index_offset());
+    assert_eq!(8.0, sketch.get_count());
+    assert_eq!(
+        -mapping.value(-3),
+        sketch.get_value_at_quantile(0.0).unwrap()
+    );
+    assert_eq!(mapping.value(6), sketch.get_value_at_quantile(1.0).unwrap());
+    assert_eq!(DENSE_GOLDEN.to_vec(), sketch.to_proto_bytes().unwrap());
+
+    let sketch = DDSketch::from_proto_bytes(&DENSE_GOLDEN).unwrap();
+    assert_eq!(DENSE_GOLDEN.to_vec(), sketch.to_proto_bytes().unwrap());
+}
+
+// The fixtures below follow the layout that the `ToProto` methods of sketches-go give to its
+// sketches: proto3 fields in number order, the default values left out, dense stores as
+// contiguous counts starting at `contiguousBinIndexOffset` and sparse ones as `binCounts`.
+// They are built by hand, the reference implementations not being available to this test
+// suite, with the bin indices of its mappings: `floor(log(v) / log(gamma)) + indexOffset`,
+// `log` being the natural logarithm for `NONE` and the cubic approximation of `log2`, with
+// `gamma` in base 2, for `CUBIC`.
+
+// NewDefaultDDSketch(0.01) with 1, 1.01, 1.05, -0.5 and two zeros:
+// mapping { gamma: 1.01 / 0.99 }
+// positiveValues { contiguousBinCounts: [2, 0, 1] }
+// negativeValues { contiguousBinCounts: [1], contiguousBinIndexOffset: -35 }
+// zeroCount: 2
+const GO_DENSE_FIXTURE: [u8; 62] = [
+    10, 9, 9, 253, 74, 129, 90, 191, 82, 240, 63, 18, 26, 18, 24, 0, 0, 0, 0, 0, 0, 0, 64, 0, 0, 0,
+    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 240, 63, 26, 12, 18, 8, 0, 0, 0, 0, 0, 0, 240, 63, 24, 69, 33,
+    0, 0, 0, 0, 0, 0, 0, 64,
+];
+
+// A cubically interpolated mapping of relative accuracy 0.02 and index offset 3.5, with sparse
+// stores holding 10 twice and 1000:
+// mapping { gamma: (1.02 / 0.98) ^ (10 * ln(2) / 7), indexOffset: 3.5, interpolation: CUBIC }
+// positiveValues { binCounts: { 61: 2, 177: 1 } }
+// negativeValues { }
+const GO_CUBIC_SPARSE_FIXTURE: [u8; 53] = [
+    10, 20, 9, 100, 244, 7, 173, 131, 165, 240, 63, 17, 0, 0, 0, 0, 0, 0, 12, 64, 24, 3, 18, 27,
+    10, 11, 8, 122, 17, 0, 0, 0, 0, 0, 0, 0, 64, 10, 12, 8, 226, 2, 17, 0, 0, 0, 0, 0, 0, 240, 63,
+    26, 0,
+];
+
+fn assert_close(expected: f64, actual: f64, relative_accuracy: f64) {
+    assert!(
+        (actual - expected).abs() <= expected.abs() * relative_accuracy * (1.0 + 1e-9),
+        "{} is not within {} of {}",
+        actual,
+        relative_accuracy,
+        expected
+    );
+}
+
+#[test]
+fn test_proto_reference_fixtures() {
+    let sketch = DDSketch::from_proto_bytes(&GO_DENSE_FIXTURE).unwrap();
+    let mapping = sketch.get_index_mapping();
+    assert_eq!(IndexMappingLayout::LOG, mapping.layout());
+    assert_eq!(1.01 / 0.99, mapping.gamma());
+    assert!((mapping.relative_accuracy() - 0.01).abs() < 1e-12);
+    assert_eq!(6.0, sketch.get_count());
+    assert_eq!(-35, mapping.index(0.5));
+    assert_eq!(2, mapping.index(1.05));
+    assert_close(-0.5, sketch.get_value_at_quantile(0.0).unwrap(), 0.01);
+    assert_eq!(0.0, sketch.get_value_at_quantile(0.3).unwrap());
+    assert_close(1.0, sketch.get_value_at_quantile(0.6).unwrap(), 0.01);
+    assert_close(1.01, sketch.get_value_at_quantile(0.8).unwrap(), 0.01);
+    assert_close(1.05, sketch.get_value_at_quantile(1.0).unwrap(), 0.01);
+    assert_eq!(GO_DENSE_FIXTURE.to_vec(), sketch.to_proto_bytes().unwrap());
+
+    let sketch = DDSketch::from_proto_bytes(&GO_CUBIC_SPARSE_FIXTURE).unwrap();
+    let mapping = sketch.get_index_mapping();
+    assert_eq!(IndexMappingLayout::LogCubic, mapping.layout());
+    assert_eq!(3.5, mapping.index_offset());
+    assert!((mapping.relative_accuracy() - 0.02).abs() < 1e-12);
+    assert_eq!(3.0, sketch.get_count());
+    assert_eq!(61, mapping.index(10.0));
+    assert_eq!(177, mapping.index(1000.0));
+    assert_close(10.0, sketch.get_value_at_quantile(0.0).unwrap(), 0.02);
+    assert_close(10.0, sketch.get_value_at_quantile(0.5).unwrap(), 0.02);
+    assert_close(1000.0, sketch.get_value_at_quantile(1.0).unwrap(), 0.02);
+}
+
+#[test]
+fn test_proto_round_trip() {
+    let mut sketch = DDSketch::collapsing_lowest_dense(1e-2, 1024).unwrap();
+    for i in -100..1000 {
+        sketch.accept(i as f64 * 0.37);
+    }
+    let bytes = sketch.to_proto_bytes().unwrap();
+    // mapping { gamma, indexOffset: 0, interpolation: CUBIC }
+    assert_eq!([10, 11, 9], bytes[..3]);
+    assert_eq!([24, 3], bytes[11..13]);
+
+    let decoded = DDSketch::from_proto_bytes(&bytes).unwrap();
+    assert_eq!(sketch.get_index_mapping(), decoded.get_index_mapping());
+    assert_eq!(sketch.get_count(), decoded.get_count());
+    for q in [0.0, 0.1, 0.5, 0.9, 0.99, 1.0] {
+        assert_eq!(
+            sketch.get_value_at_quantile(q),
+            decoded.get_value_at_quantile(q)
+        );
+    }
+    assert_eq!(bytes, decoded.to_proto_bytes().unwrap());
+}
+
+#[test]
+fn test_proto_unknown_fields_and_errors() {
+    // An unknown varint field and an unknown length-delimited field around the golden payload.
+    let mut bytes = vec![40, 1];
+    bytes.extend_from_slice(&SPARSE_GOLDEN);
+    bytes.extend_from_slice(&[50, 2, 0, 0]);
+    assert_eq!(8.0, DDSketch::from_proto_bytes(&bytes).unwrap().get_count());
+
+    // An empty sketch, with empty stores.
+    let empty = DDSketch::unbounded_dense(2e-2).unwrap();
+    let decoded = DDSketch::from_proto_bytes(&empty.to_proto_bytes().unwrap()).unwrap();
+    assert!(decoded.is_empty());
+
+    // No mapping, a truncated payload, and a gamma below 1.
+    assert!(DDSketch::from_proto_bytes(&[33, 0, 0, 0, 0, 0, 0, 240, 63]).is_err());
+    assert!(DDSketch::from_proto_bytes(&SPARSE_GOLDEN[..30]).is_err());
+    assert!(DDSketch::from_proto_bytes(&[10, 9, 9, 0, 0, 0, 0, 0, 0, 224, 63]).is_err());
+}
@@ -0,0 +1,147 @@
+#![cfg(feature = "serde")]
+
+use sketches_rust::{
+    AtomicDenseStore, DDSketch, IndexMapping, IndexMappingLayout, OutOfRangePolicy, StoreType,
+};
+
+fn new_sketch() -> DDSketch {
+    let mut sketch = DDSketch::builder(2e-2)
+        .zero_threshold(0.5)
+        .encode_zero_threshold(true)
+        .encode_value_counters(true)
+        .build()
+        .unwrap();
+    for value in [-3.0, -1.0, 0.1, 1.0, 2.0, 2.0, 100.0, f64::NAN] {
+        sketch.accept(value);
+    }
+    sketch
+}
+
+#[test]
+fn test_serde_compact() {
+    let sketch = new_sketch();
+    let bytes = bincode::serialize(&sketch).unwrap();
+    // The buffer is the binary encoding, prefixed with its length.
+    assert_eq!(sketch.encode().unwrap(), bytes[8..]);
+
+    let decoded: DDSketch = bincode::deserialize(&bytes).unwrap();
+    assert_eq!(sketch.encode().unwrap(), decoded.encode().unwrap());
+    assert_eq!(1.0, decoded.get_nan_count());
+
+    let mapping = IndexMapping::with_relative_accuracy(IndexMappingLayout::LogCubic, 1e-2).unwrap();
+    let decoded: IndexMapping =
+        bincode::deserialize(&bincode::serialize(&mapping).unwrap()).unwrap();
+    assert_eq!(mapping, decoded);
+
+    assert!(bincode::deserialize::<DDSketch>(&bincode::serialize(&[3u8, 0]).unwrap()).is_err());
+}
+
+#[test]
+fn test_serde_human_readable() {
+    let sketch = new_sketch();
+    let json = serde_json::to_value(&sketch).unwrap();
+    assert_eq!("LogCubic", json["mapping"]["layout"]);
+    assert_eq!(0.5, json["zero_threshold"]);
+    assert_eq!(1.0, json["zero_count"]);
+    assert_eq!(1.0, json["value_counters"]["nan"]);
+    assert_eq!(2, json["negative_bins"].as_array().unwrap().len());
+    assert_eq!(3, json["positive_bins"].as_array().unwrap().len());
+
+    let decoded: DDSketch = serde_json::from_value(json).unwrap();
+    assert_eq!(sketch.encode().unwrap(), decoded.encode().unwrap());
+    assert_eq!(
+        sketch.get_value_at_quantile(0.9),
+        decoded.get_value_at_quantile(0.9)
+    );
+
+    let json = r#"{"mapping": {"layout": "LOG", "gamma": 1.02, "index_offset": 0.0},
+        "positive_bins": [[0, 1.0], [10, 2.0]]}"#;
+    let decoded: DDSketch = serde_json::from_str(json).unwrap();
+    assert_eq!(3.0, decoded.get_count());
+
+    let invalid = r#"{"mapping": {"layout": "LOG", "gamma": 0.5, "index_offset": 0.0}}"#;
+    assert!(serde_json::from_str::<DDSketch>(invalid).is_err());
+    let invalid = r#"{"mapping": {"layout": "LOG", "gamma": 1.02, "index_offset": 0.0},
+        "positive_bins": [[0, -1.0]]}"#;
+    assert!(serde_json::from_str::<DDSketch>(invalid).is_err());
+
+    // A threshold left out of the binary encoding is left out of the structured form as well.
+    let mut compatible = DDSketch::builder(2e-2).zero_threshold(0.5).build().unwrap();
+    compatible.accept(1.0);
+    let json = serde_json::to_value(&compatible).unwrap();
+    assert!(json.get("zero_threshold").is_none());
+    let decoded: DDSketch = serde_json::from_value(json).unwrap();
+    assert_eq!(compatible.encode().unwrap(), decoded.encode().unwrap());
+
+    let store_type = StoreType::CollapsingLowestDense(2048);
+    let json = serde_json::to_string(&store_type).unwrap();
+    assert_eq!(store_type, serde_json::from_str(&json).unwrap());
+}
+
+#[test]
+fn test_serde_drops_what_the_encoding_lacks() {
+    let new_sketch = || {
+        let mut sketch = DDSketch::builder(2e-2)
+            .store(StoreType::CollapsingLowestDense(16))
+            .out_of_range_policy(OutOfRangePolicy::Clamp)
+            .build()
+            .unwrap()
+            .with_exact_summary_statistics()
+            .unwrap();
+        sketch.accept(1.0);
+        sketch.accept(1.5);
+        sketch
+    };
+    let sketch = new_sketch();
+    let json = serde_json::to_value(&sketch).unwrap();
+    let bytes = bincode::serialize(&sketch).unwrap();
+    let decoded: [DDSketch; 2] = [
+        serde_json::from_value(json).unwrap(),
+        bincode::deserialize(&bytes).unwrap(),
+    ];
+
+    for mut decoded in decoded {
+        let mut sketch = new_sketch();
+        // The sum is estimated from the bins instead of being exact.
+        assert_eq!(Some(2.5), sketch.get_sum());
+        assert_ne!(sketch.get_sum(), decoded.get_sum());
+        // The stores are unbounded, the lowest values are not collapsed.
+        sketch.accept(1e6);
+        decoded.accept(1e6);
+        assert!(sketch.get_value_at_quantile(0.0).unwrap() > 1.1);
+        assert!(decoded.get_value_at_quantile(0.0).unwrap() < 1.1);
+        // The default policy drops the infinite values instead of clamping them.
+        sketch.accept(f64::INFINITY);
+        decoded.accept(f64::INFINITY);
+        assert_eq!(0.0, sketch.get_dropped_count());
+        assert_eq!(1.0, decoded.get_dropped_count());
+    }
+}
+
+#[test]
+fn test_serde_atomic_dense_store() {
+    let mapping = IndexMapping::with_relative_accuracy(IndexMappingLayout::LOG, 1e-2).unwrap();
+    let store = AtomicDenseStore::with_value_range(mapping, 10.0, 100.0).unwrap();
+    for value in [0.0, 1.0, 20.0, 20.0, 50.0, -1.0, f64::NAN, f64::INFINITY] {
+        store.accept(value);
+    }
+    let json = serde_json::to_value(&store).unwrap();
+    assert_eq!(3, json["bins"].as_array().unwrap().len());
+    let decoded = [
+        serde_json::from_value::<AtomicDenseStore>(json).unwrap(),
+        bincode::deserialize(&bincode::serialize(&store).unwrap()).unwrap(),
+    ];
+    let expected = store.snapshot_and_reset().encode().unwrap();
+    for decoded in decoded {
+        assert_eq!(store.get_min_index(), decoded.get_min_index());
+        assert_eq!(store.get_max_index(), decoded.get_max_index());
+        assert_eq!(3, decoded.get_dropped_count());
+        let sketch = decoded.snapshot_and_reset();
+        assert_eq!(expected, sketch.encode().unwrap());
+        assert_eq!(1.0, sketch.get_infinite_count());
+    }
+
+    let invalid = r#"{"mapping": {"layout": "LOG", "gamma": 1.02, "index_offset": 0.0},
+        "min_index": 0, "max_index": 10, "bins": [[11, 1]]}"#;
+    assert!(serde_json::from_str::<AtomicDenseStore>(invalid).is_err());
+}
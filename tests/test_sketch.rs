@@ -1,4 +1,7 @@
-use sketches_rust::DDSketch;
+use sketches_rust::{
+    DDSketch, DDSketchBuilder, DecodeLimits, Error, IndexMapping, IndexMappingLayout,
+    OutOfRangePolicy, StoreType,
+};
 
 #[test]
 #[should_panic]
@@ -117,7 +120,7 @@ fn test_sketch_merge_1() {
         sketch2.accept(i as f64);
     }
 
-    sketch1.merge_with(&mut sketch2).unwrap();
+    sketch1.merge_with(&sketch2).unwrap();
     assert_eq!(300.0, sketch1.get_count());
 }
 
@@ -135,7 +138,7 @@ fn test_sketch_merge_2() {
         sketch2.accept(i as f64);
     }
 
-    sketch1.merge_with(&mut sketch2).unwrap();
+    sketch1.merge_with(&sketch2).unwrap();
     assert_eq!(300.0, sketch1.get_count());
 }
 
@@ -152,7 +155,7 @@ fn test_sketch_merge_panic() {
         sketch2.accept(i as f64);
     }
 
-    sketch1.merge_with(&mut sketch2).unwrap();
+    sketch1.merge_with(&sketch2).unwrap();
 }
 
 #[test]
@@ -199,7 +202,7 @@ fn test_sketch_decode_4() {
         3, 0, 0, 0, 3, 0, 2, 0, 0, 3, 3, 2, 2, 3, 3, 2, 0, 0, 0, 0, 2, 0, 2, 2, 2, 4, 4, 132, 64,
         0, 4, 2, 0, 2, 2, 3, 132, 64, 4, 132, 64, 4, 2, 2, 0, 6, 4, 6, 132, 64, 2, 6,
     ];
-    let mut sketch = DDSketch::decode(&input).unwrap();
+    let sketch = DDSketch::decode(&input).unwrap();
     assert_eq!(sketch.get_count(), 100.0);
 }
 
@@ -258,3 +261,1286 @@ fn test_sketch_create() {
     let mut sketch6: DDSketch = DDSketch::logarithmic_unbounded_size_dense_store(2e-2).unwrap();
     sketch6.accept(1.0);
 }
+
+#[test]
+fn test_sketch_moments() {
+    let accuracy = 2e-2;
+    let mut sketch = DDSketch::unbounded_dense(accuracy).unwrap();
+    for i in -50..51 {
+        sketch.accept(i as f64);
+    }
+
+    // The values are symmetric around zero: variance is (n^2 - 1) / 12 for n = 101.
+    let variance = sketch.get_variance().unwrap();
+    assert!((f64::abs(variance - 850.0) / 850.0) <= 2.0 * accuracy);
+    let std = sketch.get_standard_deviation().unwrap();
+    assert!((f64::abs(std - 850.0_f64.sqrt()) / 850.0_f64.sqrt()) <= accuracy);
+    assert!(f64::abs(sketch.get_skewness().unwrap()) < 1e-9);
+    assert!(f64::abs(sketch.get_kurtosis().unwrap() - -1.2) < 0.05);
+}
+
+#[test]
+fn test_sketch_moments_skewed() {
+    let mut sketch = DDSketch::unbounded_dense(1e-2).unwrap();
+    sketch.accept_with_count(1.0, 9.0);
+    sketch.accept(10.0);
+    assert!(sketch.get_skewness().unwrap() > 2.0);
+
+    let mut sketch = DDSketch::unbounded_dense(1e-2).unwrap();
+    sketch.accept_with_count(-1.0, 9.0);
+    sketch.accept(-10.0);
+    assert!(sketch.get_skewness().unwrap() < -2.0);
+}
+
+#[test]
+fn test_sketch_moments_degenerate() {
+    let mut sketch = DDSketch::unbounded_dense(2e-2).unwrap();
+    assert_eq!(None, sketch.get_variance());
+    assert_eq!(None, sketch.get_skewness());
+
+    sketch.accept(0.0);
+    sketch.accept(0.0);
+    assert_eq!(Some(0.0), sketch.get_variance());
+    assert_eq!(Some(0.0), sketch.get_skewness());
+    assert_eq!(Some(0.0), sketch.get_kurtosis());
+}
+
+#[test]
+fn test_sketch_exact_summary_statistics() {
+    let mut sketch = DDSketch::unbounded_dense(2e-2)
+        .unwrap()
+        .with_exact_summary_statistics()
+        .unwrap();
+    let values = [-7.5, -1.0, 0.0, 0.25, 3.0, 3.0, 12.0];
+    for value in values {
+        sketch.accept(value);
+    }
+    let mean = values.iter().sum::<f64>() / values.len() as f64;
+    let variance =
+        values.iter().map(|v| (v - mean) * (v - mean)).sum::<f64>() / values.len() as f64;
+
+    assert_eq!(Some(-7.5), sketch.get_min());
+    assert_eq!(Some(12.0), sketch.get_max());
+    assert!(f64::abs(sketch.get_sum().unwrap() - 9.75) < 1e-12);
+    assert!(f64::abs(sketch.get_variance().unwrap() - variance) < 1e-9);
+
+    let mut other = DDSketch::unbounded_dense(2e-2).unwrap();
+    other.accept(1.0);
+    sketch.merge_with(&other).unwrap();
+    assert!(f64::abs(sketch.get_sum().unwrap() - 10.75) / 10.75 < 2e-2);
+}
+
+#[test]
+fn test_sketch_exact_summary_statistics_decode_and_merge() {
+    let mut sketch = DDSketch::unbounded_dense(2e-2)
+        .unwrap()
+        .with_exact_summary_statistics()
+        .unwrap();
+    sketch.accept(0.3);
+    sketch.accept(7.0);
+
+    let empty = DDSketch::unbounded_dense(2e-2).unwrap().encode().unwrap();
+    sketch.decode_and_merge_with(&empty).unwrap();
+    assert_eq!(Some(0.3), sketch.get_min());
+    assert_eq!(Some(7.0), sketch.get_max());
+
+    let mismatched = DDSketch::unbounded_dense(1e-2).unwrap().encode().unwrap();
+    assert!(sketch.decode_and_merge_with(&mismatched).is_err());
+    assert_eq!(Some(0.3), sketch.get_min());
+
+    let mut other = DDSketch::unbounded_dense(2e-2).unwrap();
+    other.accept(100.0);
+    sketch
+        .decode_and_merge_with(&other.encode().unwrap())
+        .unwrap();
+    assert_eq!(3.0, sketch.get_count());
+    assert!(f64::abs(sketch.get_min().unwrap() - 0.3) / 0.3 <= 2e-2);
+    assert_ne!(Some(0.3), sketch.get_min());
+}
+
+#[test]
+fn test_sketch_exact_summary_statistics_decode_and_merge_truncated() {
+    let mut sketch = DDSketch::unbounded_dense(2e-2)
+        .unwrap()
+        .with_exact_summary_statistics()
+        .unwrap();
+    sketch.accept(1.0);
+
+    let mut other = DDSketch::unbounded_dense(2e-2).unwrap();
+    other.accept(2.0);
+    other.accept(3.0);
+    other.accept(5.0);
+    // A positive store holding one bin, cut before its index.
+    let mut bytes = other.encode().unwrap();
+    bytes.extend_from_slice(&[0x05, 0x01]);
+    assert!(sketch.decode_and_merge_with(&bytes).is_err());
+    assert_eq!(4.0, sketch.get_count());
+    assert!(f64::abs(sketch.get_max().unwrap() - 5.0) / 5.0 <= 2e-2);
+    assert!(f64::abs(sketch.get_sum().unwrap() - 11.0) / 11.0 <= 2e-2);
+}
+
+#[test]
+#[should_panic]
+fn test_sketch_exact_summary_statistics_panic() {
+    let mut sketch = DDSketch::unbounded_dense(2e-2).unwrap();
+    sketch.accept(1.0);
+    sketch.with_exact_summary_statistics().unwrap();
+}
+
+#[test]
+fn test_sketch_accept_with_count() {
+    let mut sketch = DDSketch::unbounded_dense(2e-2).unwrap();
+    sketch.accept_with_count(1.0, 2.5);
+    sketch.accept_with_count(0.0, 1.5);
+    sketch.accept_with_count(-1.0, 3.0);
+    sketch.accept_with_count(2.0, f64::NAN);
+    assert_eq!(7.0, sketch.get_count());
+}
+
+#[test]
+fn test_sketch_accept_with_count_weights_bins() {
+    for store_type in [
+        StoreType::CollapsingLowestDense(1000),
+        StoreType::CollapsingHighestDense(1000),
+        StoreType::UnboundedDense,
+    ] {
+        let mut sketch = DDSketch::builder(2e-2).store(store_type).build().unwrap();
+        sketch.accept(1.0);
+        sketch.accept_with_count(100.0, 3.0);
+        sketch.accept_with_count(-100.0, 0.5);
+        sketch.accept_with_count(0.0, 0.25);
+        assert_eq!(4.75, sketch.get_count());
+        let median = sketch.get_value_at_quantile(0.5).unwrap();
+        assert!((f64::abs(median - 100.0) / 100.0) <= 2e-2);
+        let sum = sketch.get_sum().unwrap();
+        assert!((f64::abs(sum - 251.0) / 251.0) <= 2e-2);
+    }
+}
+
+#[test]
+fn test_sketch_trimmed_mean() {
+    let accuracy = 2e-2;
+    let mut sketch = DDSketch::unbounded_dense(accuracy).unwrap();
+    for i in 1..101 {
+        sketch.accept(i as f64);
+    }
+    sketch.accept(1e6);
+
+    let mean = sketch.get_average().unwrap();
+    assert!(mean > 5e3);
+    // The 5% cut points fall at ranks 5.05 and 95.95, leaving the values from 6 to 96.
+    let trimmed = sketch.trimmed_mean(0.05, 0.95).unwrap();
+    assert!((f64::abs(trimmed - 51.0) / 51.0) <= accuracy);
+    let winsorized = sketch.winsorized_mean(0.05, 0.95).unwrap();
+    assert!((f64::abs(winsorized - 51.0) / 51.0) <= accuracy);
+
+    let full = sketch.trimmed_mean(0.0, 1.0).unwrap();
+    assert!((f64::abs(full - mean) / mean) <= 1e-9);
+    let median = sketch.trimmed_mean(0.5, 0.5).unwrap();
+    assert!((f64::abs(median - 51.0) / 51.0) <= accuracy);
+}
+
+#[test]
+fn test_sketch_trimmed_mean_negative_and_zero() {
+    let mut sketch = DDSketch::unbounded_dense(1e-2).unwrap();
+    sketch.accept_with_count(-1000.0, 1.0);
+    sketch.accept_with_count(-2.0, 4.0);
+    sketch.accept_with_count(0.0, 10.0);
+    sketch.accept_with_count(2.0, 4.0);
+    sketch.accept_with_count(1000.0, 1.0);
+
+    // Trimming 10% on each side removes both outliers and one value from the -2 and 2 bins.
+    let trimmed = sketch.trimmed_mean(0.1, 0.9).unwrap();
+    assert!(f64::abs(trimmed) < 1e-9);
+    let trimmed = sketch.trimmed_mean(0.1, 0.5).unwrap();
+    assert!(f64::abs(trimmed - -0.75) < 0.02);
+
+    let winsorized = sketch.winsorized_mean(0.1, 0.75).unwrap();
+    assert!(f64::abs(winsorized - -0.5) < 0.02);
+
+    assert_eq!(None, sketch.trimmed_mean(0.6, 0.4));
+    assert_eq!(None, sketch.winsorized_mean(-0.1, 0.4));
+    assert_eq!(
+        None,
+        DDSketch::unbounded_dense(1e-2)
+            .unwrap()
+            .trimmed_mean(0.1, 0.9)
+    );
+}
+
+#[test]
+fn test_sketch_shared_query() {
+    use std::sync::{Arc, RwLock};
+
+    let sketch = Arc::new(RwLock::new(DDSketch::unbounded_dense(2e-2).unwrap()));
+    {
+        let mut sketch = sketch.write().unwrap();
+        for i in 1..101 {
+            sketch.accept(i as f64);
+        }
+    }
+
+    let reader = Arc::clone(&sketch);
+    let handle = std::thread::spawn(move || {
+        let sketch = reader.read().unwrap();
+        (
+            sketch.get_count(),
+            sketch.get_value_at_quantile(0.5).unwrap(),
+        )
+    });
+    let (count, median) = handle.join().unwrap();
+    assert_eq!(100.0, count);
+    assert!((f64::abs(median - 50.0) / 50.0) <= 2e-2);
+
+    let sketch = sketch.read().unwrap();
+    assert_eq!(100.0, sketch.get_count());
+    assert!(sketch.get_min().unwrap() <= sketch.get_max().unwrap());
+}
+
+#[test]
+fn test_sketch_snapshot() {
+    let mut sketch = DDSketch::collapsing_lowest_dense(2e-2, 100).unwrap();
+    for i in -99..101 {
+        sketch.accept(i as f64);
+    }
+    sketch.accept_with_count(0.0, 3.0);
+
+    let snapshot = sketch.snapshot();
+    assert_eq!(sketch.get_count(), snapshot.get_count());
+    assert_eq!(sketch.get_sum(), snapshot.get_sum());
+    assert_eq!(sketch.get_min(), snapshot.get_min());
+    assert_eq!(sketch.get_max(), snapshot.get_max());
+    for i in 0..=100 {
+        let quantile = i as f64 / 100.0;
+        assert_eq!(
+            sketch.get_value_at_quantile(quantile),
+            snapshot.get_value_at_quantile(quantile)
+        );
+    }
+    assert_eq!(None, snapshot.get_value_at_quantile(1.1));
+
+    assert_eq!(Some(0.0), snapshot.get_rank(-1000.0));
+    assert_eq!(Some(1.0), snapshot.get_rank(1000.0));
+    let rank = snapshot.get_rank(0.0).unwrap();
+    assert!(f64::abs(rank - 103.0 / 203.0) < 1e-9);
+
+    // The snapshot is frozen while the live sketch keeps ingesting.
+    sketch.accept(1000.0);
+    assert_eq!(203.0, snapshot.get_count());
+}
+
+#[test]
+fn test_sketch_snapshot_shared() {
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<sketches_rust::DDSketchSnapshot>();
+
+    let mut sketch = DDSketch::unbounded_dense(2e-2).unwrap();
+    for i in 1..101 {
+        sketch.accept(i as f64);
+    }
+    let snapshot = sketch.snapshot();
+    let handles: Vec<_> = (0..4)
+        .map(|_| {
+            let snapshot = snapshot.clone();
+            std::thread::spawn(move || snapshot.get_value_at_quantile(0.5).unwrap())
+        })
+        .collect();
+    let median = sketch.get_value_at_quantile(0.5).unwrap();
+    for handle in handles {
+        assert_eq!(median, handle.join().unwrap());
+    }
+
+    let empty = DDSketch::unbounded_dense(2e-2).unwrap().snapshot();
+    assert!(empty.is_empty());
+    assert_eq!(None, empty.get_value_at_quantile(0.5));
+    assert_eq!(None, empty.get_rank(1.0));
+}
+
+#[test]
+fn test_sketch_builder() {
+    let mut sketch = DDSketchBuilder::new(2e-2)
+        .layout(IndexMappingLayout::LOG)
+        .negative_store(StoreType::CollapsingHighestDense(10))
+        .positive_store(StoreType::CollapsingLowestDense(1000))
+        .build()
+        .unwrap();
+    for i in 1..1001 {
+        sketch.accept(i as f64);
+        sketch.accept(-i as f64);
+    }
+    assert_eq!(2000.0, sketch.get_count());
+    // The positive store keeps every bin, the negative one collapsed its highest indices.
+    assert!((f64::abs(sketch.get_value_at_quantile(0.75).unwrap() - 500.0) / 500.0) <= 2e-2);
+    assert!(sketch.get_min().unwrap() > -1000.0 * 0.5);
+
+    // The factory methods are shortcuts for the builder.
+    let mut other = DDSketch::logarithmic_collapsing_lowest_dense(2e-2, 100).unwrap();
+    other.accept(1.0);
+    sketch.merge_with(&other).unwrap();
+    let mut other = DDSketch::collapsing_lowest_dense(2e-2, 100).unwrap();
+    other.accept(1.0);
+    assert!(sketch.merge_with(&other).is_err());
+}
+
+#[test]
+fn test_sketch_builder_index_offset() {
+    let mut sketch = DDSketch::builder(1e-2)
+        .index_offset(-123.5)
+        .build()
+        .unwrap();
+    for i in 1..101 {
+        sketch.accept(i as f64);
+    }
+    assert!((f64::abs(sketch.get_value_at_quantile(0.5).unwrap() - 50.0) / 50.0) <= 1e-2);
+
+    let decoded = DDSketch::decode(&sketch.encode().unwrap()).unwrap();
+    assert_eq!(
+        sketch.get_value_at_quantile(0.5),
+        decoded.get_value_at_quantile(0.5)
+    );
+    assert!(DDSketch::builder(1e-2)
+        .index_offset(f64::NAN)
+        .build()
+        .is_err());
+}
+
+#[test]
+fn test_sketch_builder_zero_threshold() {
+    let mut sketch = DDSketch::builder(2e-2)
+        .zero_threshold(1e-3)
+        .build()
+        .unwrap();
+    sketch.accept(1e-300);
+    sketch.accept(-5e-4);
+    sketch.accept(1e-3);
+    sketch.accept(2e-3);
+    assert_eq!(Some(0.0), sketch.get_value_at_quantile(0.5));
+    assert_eq!(Some(0.0), sketch.get_min());
+    assert!(sketch.get_max().unwrap() > 1e-3);
+
+    assert!(DDSketch::builder(2e-2)
+        .zero_threshold(-1.0)
+        .build()
+        .is_err());
+    assert!(DDSketch::builder(2e-2)
+        .zero_threshold(f64::INFINITY)
+        .build()
+        .is_err());
+}
+
+#[test]
+fn test_sketch_builder_out_of_range_policy() {
+    let mut sketch = DDSketch::builder(2e-2).build().unwrap();
+    sketch.accept(f64::INFINITY);
+    sketch.accept(f64::NEG_INFINITY);
+    assert_eq!(0.0, sketch.get_count());
+
+    let mut sketch = DDSketch::builder(2e-2)
+        .out_of_range_policy(OutOfRangePolicy::Clamp)
+        .build()
+        .unwrap();
+    sketch.accept(f64::INFINITY);
+    sketch.accept(f64::NEG_INFINITY);
+    assert_eq!(2.0, sketch.get_count());
+    assert!(sketch.get_max().unwrap() > 1e307);
+    assert!(sketch.get_min().unwrap() < -1e307);
+}
+
+#[test]
+fn test_sketch_builder_exact_summary_statistics() {
+    let mut sketch = DDSketch::builder(2e-2)
+        .exact_summary_statistics(true)
+        .build()
+        .unwrap();
+    sketch.accept(1.0);
+    sketch.accept(2.0);
+    assert_eq!(Some(3.0), sketch.get_sum());
+    assert_eq!(Some(1.0), sketch.get_min());
+    assert_eq!(Some(0.25), sketch.get_variance());
+}
+
+#[test]
+#[should_panic]
+fn test_sketch_builder_panic() {
+    DDSketch::builder(2e-2)
+        .positive_store(StoreType::CollapsingHighestDense(0))
+        .build()
+        .unwrap();
+}
+
+#[test]
+fn test_sketch_merge_all() {
+    let builder = DDSketch::builder(2e-2).store(StoreType::CollapsingLowestDense(1024));
+    let mut sketches = Vec::new();
+    let mut expected = builder.build().unwrap();
+    for i in 0..20 {
+        let mut sketch = builder.build().unwrap();
+        for j in 0..100 {
+            let value = ((i * 100 + j) as f64 - 500.0) * 1.5;
+            sketch.accept(value);
+            expected.accept(value);
+        }
+        sketches.push(sketch);
+    }
+    sketches.push(builder.build().unwrap());
+
+    let merged = DDSketch::merge_all(&sketches).unwrap();
+    assert_eq!(expected.get_count(), merged.get_count());
+    assert_eq!(expected.get_min(), merged.get_min());
+    assert_eq!(expected.get_max(), merged.get_max());
+    for quantile in [0.0, 0.1, 0.25, 0.5, 0.75, 0.99, 1.0] {
+        assert_eq!(
+            expected.get_value_at_quantile(quantile),
+            merged.get_value_at_quantile(quantile)
+        );
+    }
+
+    let encoded: Vec<Vec<u8>> = sketches.iter().map(|s| s.encode().unwrap()).collect();
+    let merged = DDSketch::merge_all_encoded(encoded.iter().map(|b| b.as_slice())).unwrap();
+    assert_eq!(expected.get_count(), merged.get_count());
+    for quantile in [0.0, 0.1, 0.25, 0.5, 0.75, 0.99, 1.0] {
+        assert_eq!(
+            expected.get_value_at_quantile(quantile),
+            merged.get_value_at_quantile(quantile)
+        );
+    }
+}
+
+#[test]
+fn test_sketch_merge_all_invalid() {
+    assert!(DDSketch::merge_all(Vec::<&DDSketch>::new()).is_err());
+    assert!(DDSketch::merge_all_encoded(Vec::<&[u8]>::new()).is_err());
+
+    let a = DDSketch::unbounded_dense(2e-2).unwrap();
+    let b = DDSketch::unbounded_dense(1e-2).unwrap();
+    assert!(DDSketch::merge_all([&a, &b]).is_err());
+    let encoded = [a.encode().unwrap(), b.encode().unwrap()];
+    assert!(DDSketch::merge_all_encoded(encoded.iter().map(|b| b.as_slice())).is_err());
+    let truncated = &encoded[0][..encoded[0].len() - 1];
+    assert!(DDSketch::merge_all_encoded([truncated]).is_err());
+}
+
+#[test]
+fn test_sketch_merge_all_wide_index_span() {
+    // Each sketch spans one bin, but their union spans millions of them.
+    let mut tiny = DDSketch::unbounded_dense(1e-4).unwrap();
+    tiny.accept(1e-300);
+    let mut huge = DDSketch::unbounded_dense(1e-4).unwrap();
+    huge.accept(1e300);
+    match DDSketch::merge_all([&tiny, &huge]) {
+        Err(Error::LimitExceeded(_)) => {}
+        _ => panic!("expected a too wide index span"),
+    }
+    let limits = DecodeLimits::new().max_index_span(1 << 23);
+    let merged = DDSketch::merge_all_with_limits([&tiny, &huge], limits).unwrap();
+    assert_eq!(2.0, merged.get_count());
+}
+
+#[test]
+fn test_sketch_merge_all_exact_summary_statistics() {
+    let builder = DDSketch::builder(2e-2).exact_summary_statistics(true);
+    let mut a = builder.build().unwrap();
+    let mut b = builder.build().unwrap();
+    a.accept(1.0);
+    b.accept(3.0);
+    let merged = DDSketch::merge_all([&a, &b]).unwrap();
+    assert_eq!(Some(4.0), merged.get_sum());
+    assert_eq!(Some(1.0), merged.get_min());
+
+    let mut c = DDSketch::unbounded_dense(2e-2).unwrap();
+    c.accept(2.0);
+    let merged = DDSketch::merge_all([&a, &b, &c]).unwrap();
+    assert_eq!(3.0, merged.get_count());
+    assert_ne!(Some(1.0), merged.get_min());
+}
+
+#[test]
+fn test_sketch_scale() {
+    let values: Vec<f64> = (1..=1000).map(|i| i as f64 * 7.3 - 2000.0).collect();
+    for (layout, factor) in [
+        (IndexMappingLayout::LOG, 1e-3),
+        (IndexMappingLayout::LOG, -250.0),
+        (IndexMappingLayout::LogCubic, 0.25),
+        (IndexMappingLayout::LogCubic, -8.0),
+        (IndexMappingLayout::LogCubic, 1e-3),
+        (IndexMappingLayout::LogCubic, -3.0),
+    ] {
+        let builder = DDSketch::builder(1e-2).layout(layout);
+        let mut sketch = builder.build().unwrap();
+        let mut expected: Vec<f64> = Vec::new();
+        for value in values.iter() {
+            sketch.accept(*value);
+            expected.push(value * factor);
+        }
+        expected.sort_by(|a, b| a.total_cmp(b));
+
+        let (scaled, relative_accuracy) = sketch.scale(factor).unwrap();
+        assert!(relative_accuracy >= 1e-2 * 0.99);
+        assert!(relative_accuracy <= 2.02e-2);
+        assert_eq!(values.len() as f64, scaled.get_count());
+        for quantile in [0.0, 0.1, 0.25, 0.5, 0.75, 0.9, 1.0] {
+            let rank = (quantile * (expected.len() - 1) as f64) as usize;
+            let actual = scaled.get_value_at_quantile(quantile).unwrap();
+            assert!(
+                (actual - expected[rank]).abs() <= relative_accuracy * expected[rank].abs() + 1e-12,
+                "{:?} {} {} {} {}",
+                layout,
+                factor,
+                quantile,
+                actual,
+                expected[rank]
+            );
+        }
+    }
+}
+
+#[test]
+fn test_sketch_scale_exact() {
+    let mut sketch = DDSketch::builder(2e-2)
+        .layout(IndexMappingLayout::LOG)
+        .build()
+        .unwrap();
+    let mut reference = DDSketch::logarithmic_unbounded_size_dense_store(2e-2).unwrap();
+    for i in 1..100 {
+        sketch.accept(i as f64);
+        reference.accept(i as f64);
+    }
+    let (scaled, relative_accuracy) = sketch.scale(-1e-3).unwrap();
+    assert_eq!(
+        reference.get_index_mapping().relative_accuracy(),
+        relative_accuracy
+    );
+    for quantile in [0.0, 0.5, 1.0] {
+        let expected = reference.get_value_at_quantile(1.0 - quantile).unwrap() * -1e-3;
+        let actual = scaled.get_value_at_quantile(quantile).unwrap();
+        assert!((actual - expected).abs() <= 1e-12);
+    }
+    // The scaled sketch holds the same bins under another index offset.
+    let decoded = DDSketch::decode(&scaled.encode().unwrap()).unwrap();
+    assert_eq!(scaled.get_index_mapping(), decoded.get_index_mapping());
+    let mut native = DDSketch::logarithmic_unbounded_size_dense_store(2e-2).unwrap();
+    native.accept(-0.05);
+    match native.merge_with(&scaled) {
+        Err(Error::UnmatchedMapping { .. }) => {}
+        _ => panic!("expected an unmatched mapping"),
+    }
+
+    // Keeping the mapping rebins the values instead, so that they can be merged.
+    let (rebinned, relative_accuracy) = reference.scale_keeping_mapping(-1e-3).unwrap();
+    assert!(relative_accuracy > 2e-2);
+    native.merge_with(&rebinned).unwrap();
+    assert_eq!(100.0, native.get_count());
+    for quantile in [0.0, 0.5, 1.0] {
+        let expected = scaled.get_value_at_quantile(quantile).unwrap();
+        let actual = rebinned.get_value_at_quantile(quantile).unwrap();
+        assert!((actual - expected).abs() <= relative_accuracy * expected.abs());
+    }
+}
+
+#[test]
+fn test_sketch_scale_out_of_range() {
+    let mut sketch = DDSketch::builder(2e-2)
+        .out_of_range_policy(OutOfRangePolicy::Error)
+        .build()
+        .unwrap();
+    sketch.accept(1e308);
+    sketch.accept(-1.0);
+    sketch.accept(f64::NAN);
+    assert_eq!(1.0, sketch.get_dropped_count());
+
+    // The scaled values out of the indexable range are clamped, not dropped again.
+    let (scaled, _) = sketch.scale(3.0).unwrap();
+    assert_eq!(2.0, scaled.get_count());
+    assert_eq!(1.0, scaled.get_dropped_count());
+    assert_eq!(1.0, scaled.get_nan_count());
+    assert!(scaled.get_max().unwrap() > 1e308);
+    let min = scaled.get_min().unwrap();
+    assert!((min + 3.0).abs() / 3.0 <= 5e-2);
+}
+
+#[test]
+fn test_sketch_scale_zero_and_statistics() {
+    let mut sketch = DDSketch::builder(2e-2)
+        .exact_summary_statistics(true)
+        .build()
+        .unwrap();
+    sketch.accept(-1.0);
+    sketch.accept(2.0);
+    sketch.accept(4.0);
+
+    let (scaled, _) = DDSketch::merge_all([&sketch]).unwrap().scale(0.0).unwrap();
+    assert_eq!(3.0, scaled.get_count());
+    assert_eq!(Some(0.0), scaled.get_value_at_quantile(0.0));
+    assert_eq!(Some(0.0), scaled.get_value_at_quantile(1.0));
+
+    let variance = sketch.get_variance().unwrap();
+    let (scaled, _) = sketch.scale(-3.0).unwrap();
+    assert_eq!(Some(-15.0), scaled.get_sum());
+    assert_eq!(Some(-12.0), scaled.get_min());
+    assert_eq!(Some(3.0), scaled.get_max());
+    assert!((scaled.get_variance().unwrap() - 9.0 * variance).abs() < 1e-9);
+
+    assert!(DDSketch::unbounded_dense(2e-2)
+        .unwrap()
+        .scale(f64::NAN)
+        .is_err());
+}
+
+#[test]
+fn test_sketch_zero_threshold_encoding() {
+    let builder = DDSketch::builder(2e-2).zero_threshold(1e-3);
+    let mut sketch = builder.clone().encode_zero_threshold(true).build().unwrap();
+    let mut compatible = builder.build().unwrap();
+    for value in [5e-4, 1.0] {
+        sketch.accept(value);
+        compatible.accept(value);
+    }
+    assert_eq!(1e-3, sketch.get_zero_threshold());
+
+    let decoded = DDSketch::decode(&sketch.encode().unwrap()).unwrap();
+    assert_eq!(1e-3, decoded.get_zero_threshold());
+    assert_eq!(Some(0.0), decoded.get_value_at_quantile(0.0));
+    assert_eq!(2.0, decoded.get_count());
+    let encoded = decoded.encode().unwrap();
+    assert_eq!(sketch.encode().unwrap(), encoded);
+    assert_eq!(encoded.len(), decoded.encoded_len());
+
+    // By default, the threshold is left out of the encoding, whose values below it are zeros.
+    let encoded = compatible.encode().unwrap();
+    assert_eq!(encoded.len(), compatible.encoded_len());
+    assert_eq!(sketch.encode().unwrap().len() - 9, encoded.len());
+    let decoded = DDSketch::decode(&encoded).unwrap();
+    assert_eq!(0.0, decoded.get_zero_threshold());
+    assert_eq!(Some(0.0), decoded.get_value_at_quantile(0.0));
+    assert_eq!(2.0, decoded.get_count());
+
+    let mut plain = DDSketch::unbounded_dense(2e-2).unwrap();
+    plain.accept(1.0);
+    let decoded = DDSketch::decode(&plain.encode().unwrap()).unwrap();
+    assert_eq!(0.0, decoded.get_zero_threshold());
+}
+
+#[test]
+fn test_sketch_zero_threshold_merge() {
+    let mut narrow = DDSketch::builder(2e-2)
+        .zero_threshold(1e-6)
+        .encode_zero_threshold(true)
+        .build()
+        .unwrap();
+    let mut wide = DDSketch::builder(2e-2)
+        .zero_threshold(1e-2)
+        .encode_zero_threshold(true)
+        .build()
+        .unwrap();
+    for value in [1e-5, -1e-4, 5e-3, 1e-2, 0.5, 1.0] {
+        narrow.accept(value);
+    }
+    wide.accept(0.0);
+    wide.accept(2.0);
+
+    let encoded_narrow = narrow.encode().unwrap();
+    let encoded_wide = wide.encode().unwrap();
+    let merged_all = DDSketch::merge_all([&narrow, &wide]).unwrap();
+    let merged_all_encoded =
+        DDSketch::merge_all_encoded([encoded_narrow.as_slice(), encoded_wide.as_slice()]).unwrap();
+    let mut decoded = DDSketch::decode(&encoded_wide).unwrap();
+    decoded.decode_and_merge_with(&encoded_narrow).unwrap();
+    narrow.merge_with(&wide).unwrap();
+
+    for merged in [&narrow, &merged_all, &merged_all_encoded, &decoded] {
+        assert_eq!(1e-2, merged.get_zero_threshold());
+        assert_eq!(8.0, merged.get_count());
+        // The values entirely below the larger threshold are now counted as zeros, while the
+        // bin holding the threshold itself is kept.
+        assert_eq!(Some(0.0), merged.get_value_at_quantile(0.0));
+        assert_eq!(Some(0.0), merged.get_value_at_quantile(3.0 / 7.0));
+        let above = merged.get_value_at_quantile(4.0 / 7.0).unwrap();
+        assert!((above - 1e-2).abs() / 1e-2 <= 2e-2);
+    }
+    // Later values below the threshold go to the zero bucket as well.
+    narrow.accept(5e-3);
+    assert_eq!(Some(0.0), narrow.get_value_at_quantile(4.0 / 8.0));
+
+    // A sketch that doesn't encode its threshold does once the merge raised it.
+    let mut plain = DDSketch::unbounded_dense(2e-2).unwrap();
+    plain.accept(5e-3);
+    let mut decoded_plain = DDSketch::decode(&plain.encode().unwrap()).unwrap();
+    decoded_plain.decode_and_merge_with(&encoded_wide).unwrap();
+    let merged_all = DDSketch::merge_all([&plain, &wide]).unwrap();
+    plain.merge_with(&wide).unwrap();
+    for merged in [&plain, &decoded_plain, &merged_all] {
+        let decoded = DDSketch::decode(&merged.encode().unwrap()).unwrap();
+        assert_eq!(1e-2, decoded.get_zero_threshold());
+        assert_eq!(Some(0.0), decoded.get_value_at_quantile(0.5));
+    }
+    // A threshold that is not raised is still left out of the encoding.
+    let mut compatible = DDSketch::builder(2e-2)
+        .zero_threshold(1e-3)
+        .build()
+        .unwrap();
+    compatible
+        .merge_with(&DDSketch::unbounded_dense(2e-2).unwrap())
+        .unwrap();
+    let decoded = DDSketch::decode(&compatible.encode().unwrap()).unwrap();
+    assert_eq!(0.0, decoded.get_zero_threshold());
+}
+
+#[test]
+fn test_sketch_value_counters() {
+    let mut sketch = DDSketch::builder(2e-2).build().unwrap();
+    sketch.accept(1.0);
+    sketch.accept(f64::NAN);
+    sketch.accept_with_count(f64::INFINITY, 2.0);
+    sketch.accept(-f64::MAX);
+    assert_eq!(1.0, sketch.get_count());
+    assert_eq!(4.0, sketch.get_dropped_count());
+    assert_eq!(1.0, sketch.get_nan_count());
+    assert_eq!(2.0, sketch.get_infinite_count());
+    // NaN no longer ends up in the zero bucket.
+    assert_eq!(Some(sketch.get_max().unwrap()), sketch.get_min());
+
+    let mut clamping = DDSketch::builder(2e-2)
+        .out_of_range_policy(OutOfRangePolicy::Clamp)
+        .build()
+        .unwrap();
+    clamping.accept(f64::NEG_INFINITY);
+    clamping.accept(f64::NAN);
+    assert_eq!(1.0, clamping.get_count());
+    assert_eq!(1.0, clamping.get_dropped_count());
+    assert_eq!(1.0, clamping.get_infinite_count());
+
+    sketch.merge_with(&clamping).unwrap();
+    assert_eq!(5.0, sketch.get_dropped_count());
+    assert_eq!(2.0, sketch.get_nan_count());
+    assert_eq!(3.0, sketch.get_infinite_count());
+    sketch.clear();
+    assert_eq!(0.0, sketch.get_dropped_count());
+}
+
+#[test]
+fn test_sketch_max_indexable_value_fine_accuracy() {
+    // At this accuracy the indexes overflow above 1e6, long before the doubles do.
+    let mut sketch = DDSketch::builder(5e-9)
+        .layout(IndexMappingLayout::LOG)
+        .build()
+        .unwrap();
+    sketch.accept(1e5);
+    sketch.accept(1e12);
+    sketch.accept(-f64::MAX / 2.0);
+    assert_eq!(1.0, sketch.get_count());
+    assert_eq!(2.0, sketch.get_dropped_count());
+    let max = sketch.get_max().unwrap();
+    assert!((f64::abs(max - 1e5) / 1e5) <= 5e-9);
+}
+
+#[test]
+fn test_sketch_try_accept() {
+    let mut sketch = DDSketch::builder(2e-2)
+        .out_of_range_policy(OutOfRangePolicy::Error)
+        .build()
+        .unwrap();
+    assert!(sketch.try_accept(1.0).is_ok());
+    assert!(sketch.try_accept(f64::INFINITY).is_err());
+    assert!(sketch.try_accept_with_count(-f64::MAX, 3.0).is_err());
+    assert!(sketch.try_accept(f64::NAN).is_err());
+    sketch.accept(f64::INFINITY);
+    assert_eq!(1.0, sketch.get_count());
+    assert_eq!(6.0, sketch.get_dropped_count());
+
+    let mut dropping = DDSketch::builder(2e-2).build().unwrap();
+    assert!(dropping.try_accept(f64::INFINITY).is_ok());
+    assert!(dropping.try_accept(f64::NAN).is_err());
+}
+
+#[test]
+fn test_sketch_value_counters_encoding() {
+    let builder = DDSketch::builder(2e-2).encode_value_counters(true);
+    let mut sketch = builder.build().unwrap();
+    sketch.accept(1.0);
+    sketch.accept(f64::NAN);
+    sketch.accept(f64::INFINITY);
+
+    let encoded = sketch.encode().unwrap();
+    let decoded = DDSketch::decode(&encoded).unwrap();
+    assert_eq!(2.0, decoded.get_dropped_count());
+    assert_eq!(1.0, decoded.get_nan_count());
+    assert_eq!(1.0, decoded.get_infinite_count());
+    assert_eq!(encoded, decoded.encode().unwrap());
+
+    let mut merged = builder.build().unwrap();
+    merged.decode_and_merge_with(&encoded).unwrap();
+    merged.decode_and_merge_with(&encoded).unwrap();
+    assert_eq!(4.0, merged.get_dropped_count());
+    let merged = DDSketch::merge_all_encoded([encoded.as_slice(), encoded.as_slice()]).unwrap();
+    assert_eq!(2.0, merged.get_nan_count());
+
+    // The counters are left out of the encoding by default.
+    let mut plain = DDSketch::builder(2e-2).build().unwrap();
+    plain.accept(f64::NAN);
+    assert_eq!(
+        0.0,
+        DDSketch::decode(&plain.encode().unwrap())
+            .unwrap()
+            .get_nan_count()
+    );
+}
+
+/// Encodings written by sketches-rust 0.2.3, before any of the encoding extensions.
+const PREVIOUS_ENCODINGS: [(&str, &[u8]); 4] = [
+    (
+        "unbounded_dense_small",
+        &[
+            14, 100, 244, 7, 173, 131, 165, 240, 63, 0, 0, 0, 0, 0, 0, 0, 0, 5, 3, 35, 3, 90, 4,
+            166, 2, 2, 7, 1, 34, 2,
+        ],
+    ),
+    (
+        "unbounded_dense_range",
+        &[
+            14, 100, 244, 7, 173, 131, 165, 240, 63, 0, 0, 0, 0, 0, 0, 0, 0, 13, 117, 0, 2, 2, 0,
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2, 0, 0, 0,
+            0, 0, 0, 2, 0, 0, 0, 0, 0, 2, 0, 0, 0, 0, 2, 0, 0, 0, 2, 0, 0, 2, 0, 0, 2, 0, 0, 2, 0,
+            2, 0, 2, 0, 2, 0, 2, 0, 2, 2, 0, 2, 2, 0, 2, 2, 2, 0, 2, 2, 2, 2, 2, 2, 3, 2, 2, 2, 2,
+            3, 2, 3, 2, 3, 3, 2, 3, 3, 3, 3, 3, 3, 4, 3, 4, 3, 4, 4, 4, 4, 4, 4, 4, 132, 64, 132,
+            64, 4, 3,
+        ],
+    ),
+    (
+        "collapsing_lowest_dense_weighted",
+        &[
+            14, 194, 241, 231, 233, 237, 81, 240, 63, 0, 0, 0, 0, 0, 0, 0, 0, 4, 2, 5, 48, 194, 1,
+            132, 64, 22, 2, 18, 2, 16, 2, 12, 2, 12, 2, 12, 2, 8, 2, 10, 2, 8, 2, 8, 2, 6, 2, 6, 2,
+            6, 2, 6, 2, 6, 2, 6, 2, 4, 2, 4, 2, 6, 2, 4, 2, 2, 4, 2, 2, 4, 2, 4, 2, 4, 2, 2, 2, 4,
+            2, 4, 2, 2, 2, 4, 2, 2, 2, 4, 2, 2, 2, 4, 2, 2, 2, 2, 2, 4, 2, 2, 2, 2, 2, 2, 2, 2, 2,
+            4, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 7, 47, 194, 1, 132, 64, 22, 2, 18, 2, 16, 2, 12, 2,
+            12, 2, 12, 2, 8, 2, 10, 2, 8, 2, 8, 2, 6, 2, 6, 2, 6, 2, 6, 2, 6, 2, 6, 2, 4, 2, 4, 2,
+            6, 2, 4, 2, 4, 2, 4, 2, 4, 2, 4, 2, 2, 2, 4, 2, 4, 2, 2, 2, 4, 2, 2, 2, 4, 2, 2, 2, 4,
+            2, 2, 2, 2, 2, 4, 2, 2, 2, 2, 2, 2, 2, 2, 2, 4, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2,
+        ],
+    ),
+    (
+        "logarithmic_collapsing_highest_dense",
+        &[
+            2, 243, 26, 202, 107, 40, 175, 241, 63, 0, 0, 0, 0, 0, 0, 0, 0, 5, 17, 139, 1, 2, 10,
+            2, 8, 2, 8, 2, 8, 2, 8, 2, 8, 2, 8, 2, 8, 2, 8, 2, 8, 2, 8, 2, 8, 2, 8, 2, 8, 2, 8, 2,
+            4, 137, 16, 15, 44, 13, 2, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2, 0, 0, 0, 0, 2, 0, 0, 2,
+            0, 0, 2, 0, 2, 2, 0, 2, 2, 2, 2, 2, 2, 3, 2, 3, 3, 3, 4, 3, 4, 4, 132, 64, 4,
+        ],
+    ),
+];
+
+fn previous_encoding_builder(name: &str) -> DDSketchBuilder {
+    match name {
+        "unbounded_dense_small" | "unbounded_dense_range" => DDSketch::builder(2e-2),
+        "collapsing_lowest_dense_weighted" => {
+            DDSketch::builder(1e-2).store(StoreType::CollapsingLowestDense(128))
+        }
+        "logarithmic_collapsing_highest_dense" => DDSketch::builder(5e-2)
+            .layout(IndexMappingLayout::LOG)
+            .store(StoreType::CollapsingHighestDense(64)),
+        _ => unreachable!(),
+    }
+}
+
+fn previous_encoding_sketch(name: &str, builder: DDSketchBuilder) -> DDSketch {
+    let mut sketch = builder.build().unwrap();
+    match name {
+        "unbounded_dense_small" => {
+            for value in [0.5, 0.5, 3.0, 3.0, 3.0, 1000.0, -2.0] {
+                sketch.accept(value);
+            }
+        }
+        "unbounded_dense_range" => {
+            for i in 1..=100 {
+                sketch.accept(i as f64);
+            }
+        }
+        "collapsing_lowest_dense_weighted" => {
+            for i in -50..=50 {
+                sketch.accept(i as f64 * 1.7);
+            }
+            sketch.accept_with_count(42.0, 3.0);
+        }
+        "logarithmic_collapsing_highest_dense" => {
+            for i in 0..40 {
+                sketch.accept(1.5f64.powi(i) * 1e-3);
+                sketch.accept(-(i as f64) - 0.5);
+            }
+        }
+        _ => unreachable!(),
+    }
+    sketch
+}
+
+#[test]
+fn test_sketch_encoding_matches_previous_versions() {
+    for (name, encoded) in PREVIOUS_ENCODINGS {
+        let sketch = previous_encoding_sketch(name, previous_encoding_builder(name));
+        assert_eq!(encoded, sketch.encode().unwrap().as_slice(), "{}", name);
+        assert_eq!(
+            encoded,
+            DDSketch::decode(encoded)
+                .unwrap()
+                .encode()
+                .unwrap()
+                .as_slice(),
+            "{}",
+            name
+        );
+
+        // The payloads of previous versions decode to the same sketch.
+        let decoded = DDSketch::decode(encoded).unwrap();
+        assert_eq!(sketch.get_count(), decoded.get_count(), "{}", name);
+        for q in [0.0, 0.1, 0.25, 0.5, 0.75, 0.9, 1.0] {
+            assert_eq!(
+                sketch.get_value_at_quantile(q),
+                decoded.get_value_at_quantile(q),
+                "{} {}",
+                name,
+                q
+            );
+        }
+
+        // The compact encoding, which is opted into, is never larger and decodes the same.
+        let compact = previous_encoding_sketch(
+            name,
+            previous_encoding_builder(name).encode_compact_bins(true),
+        );
+        let compact_encoded = compact.encode().unwrap();
+        assert!(compact_encoded.len() <= encoded.len(), "{}", name);
+        assert_eq!(compact_encoded.len(), compact.encoded_len(), "{}", name);
+        assert_eq!(
+            encoded,
+            DDSketch::decode(&compact_encoded)
+                .unwrap()
+                .encode()
+                .unwrap()
+                .as_slice(),
+            "{}",
+            name
+        );
+
+        // The features that are not opted into the encoding leave it unchanged.
+        let builder = previous_encoding_builder(name)
+            .zero_threshold(1e-9)
+            .out_of_range_policy(OutOfRangePolicy::Clamp);
+        let mut sketch = previous_encoding_sketch(name, builder);
+        sketch.accept(f64::NAN);
+        assert_eq!(encoded, sketch.encode().unwrap().as_slice(), "{}", name);
+    }
+}
+
+#[test]
+fn test_sketch_from_histogram() {
+    let mapping = IndexMapping::with_relative_accuracy(IndexMappingLayout::LOG, 1e-2).unwrap();
+    let bounds = [1.0, 2.0, 5.0, 10.0, f64::INFINITY];
+    let counts = [10.0, 20.0, 30.0, 40.0, 5.0];
+
+    let (sketch, accuracy) = DDSketch::from_histogram(
+        &bounds,
+        &counts,
+        Some(0.0),
+        mapping.clone(),
+        OutOfRangePolicy::Drop,
+    )
+    .unwrap();
+    assert!((sketch.get_count() - 100.0).abs() < 1e-9);
+    assert_eq!(5.0, sketch.get_dropped_count());
+    // The first bucket starts at zero.
+    assert_eq!(1.0, accuracy);
+    let median = sketch.get_value_at_quantile(0.5).unwrap();
+    assert!(median > 2.0 && median <= 5.0 * 1.01);
+    let max = sketch.get_value_at_quantile(1.0).unwrap();
+    assert!(max > 5.0 && max <= 10.0 * 1.01);
+
+    let (clamped, _) = DDSketch::from_histogram(
+        &bounds,
+        &counts,
+        Some(0.0),
+        mapping.clone(),
+        OutOfRangePolicy::Clamp,
+    )
+    .unwrap();
+    assert!((clamped.get_count() - 105.0).abs() < 1e-9);
+    let max = clamped.get_value_at_quantile(1.0).unwrap();
+    assert!((max - 10.0).abs() <= 10.0 * 1e-2);
+
+    assert!(DDSketch::from_histogram(
+        &bounds,
+        &counts,
+        Some(0.0),
+        mapping.clone(),
+        OutOfRangePolicy::Error
+    )
+    .is_err());
+
+    // Without the minimum, the first bucket is unbounded below, like the last one above.
+    let (sketch, _) = DDSketch::from_histogram(
+        &bounds,
+        &counts,
+        None,
+        mapping.clone(),
+        OutOfRangePolicy::Drop,
+    )
+    .unwrap();
+    assert!((sketch.get_count() - 90.0).abs() < 1e-9);
+    assert_eq!(15.0, sketch.get_dropped_count());
+    let (clamped, accuracy) = DDSketch::from_histogram(
+        &bounds,
+        &counts,
+        None,
+        mapping.clone(),
+        OutOfRangePolicy::Clamp,
+    )
+    .unwrap();
+    assert!((clamped.get_count() - 105.0).abs() < 1e-9);
+    assert_eq!(1.0, accuracy);
+    let min = clamped.get_value_at_quantile(0.0).unwrap();
+    assert!((0.99..=1.011).contains(&min));
+
+    // The buckets below the minimum must be empty.
+    assert!(DDSketch::from_histogram(
+        &bounds,
+        &counts,
+        Some(1.5),
+        mapping.clone(),
+        OutOfRangePolicy::Drop
+    )
+    .is_err());
+    let (sketch, _) = DDSketch::from_histogram(
+        &bounds[1..],
+        &counts[1..],
+        Some(1.5),
+        mapping,
+        OutOfRangePolicy::Drop,
+    )
+    .unwrap();
+    let min = sketch.get_value_at_quantile(0.0).unwrap();
+    assert!((1.5 * 0.99..=2.0 * 1.01).contains(&min));
+}
+
+#[test]
+fn test_sketch_accept_histogram_error_policy() {
+    let mut sketch = DDSketch::builder(1e-2)
+        .out_of_range_policy(OutOfRangePolicy::Error)
+        .build()
+        .unwrap();
+    sketch.accept(3.0);
+    let result = sketch.accept_histogram(&[1.0, 2.0, f64::INFINITY], &[1.0, 2.0, 4.0], None);
+    assert!(result.is_err());
+    // The out-of-range buckets are counted as dropped, the others are added.
+    assert_eq!(5.0, sketch.get_dropped_count());
+    assert!((sketch.get_count() - 3.0).abs() < 1e-9);
+
+    let accuracy = sketch
+        .accept_histogram(&[1.0, 2.0], &[1.0, 2.0], Some(0.5))
+        .unwrap();
+    assert!((sketch.get_count() - 6.0).abs() < 1e-9);
+    assert!(accuracy > 1e-2 && accuracy < 1.0);
+    assert!(sketch
+        .accept_histogram(&[1.0], &[1.0], Some(f64::NAN))
+        .is_err());
+}
+
+#[test]
+fn test_sketch_from_histogram_accuracy() {
+    let mapping = IndexMapping::with_relative_accuracy(IndexMappingLayout::LOG, 1e-2).unwrap();
+    let bounds = [-2.0, -1.0, 1.0, 1.5];
+    let counts = [0.0, 3.0, 0.0, 6.0];
+    let (sketch, accuracy) = DDSketch::from_histogram(
+        &bounds,
+        &counts,
+        None,
+        mapping.clone(),
+        OutOfRangePolicy::Drop,
+    )
+    .unwrap();
+    assert!((sketch.get_count() - 9.0).abs() < 1e-9);
+    assert!((-2.0 * 1.01..=-0.99).contains(&sketch.get_value_at_quantile(0.0).unwrap()));
+    assert!((0.99..=1.5 * 1.01).contains(&sketch.get_value_at_quantile(1.0).unwrap()));
+    let expected = 1.0 - 0.99 / (2.0 * 1.01);
+    assert!((accuracy - expected).abs() < 1e-12);
+
+    // A bucket straddling zero puts the part around zero in the zero count.
+    let (sketch, _) = DDSketch::from_histogram(
+        &[1.0],
+        &[4.0],
+        Some(-1.0),
+        mapping.clone(),
+        OutOfRangePolicy::Drop,
+    )
+    .unwrap();
+    assert!((sketch.get_count() - 4.0).abs() < 1e-9);
+
+    assert!(
+        DDSketch::from_histogram(&[1.0], &[], None, mapping.clone(), OutOfRangePolicy::Drop)
+            .is_err()
+    );
+    assert!(DDSketch::from_histogram(
+        &[2.0, 1.0],
+        &[1.0, 1.0],
+        None,
+        mapping.clone(),
+        OutOfRangePolicy::Drop
+    )
+    .is_err());
+    assert!(
+        DDSketch::from_histogram(&[1.0], &[-1.0], None, mapping, OutOfRangePolicy::Drop).is_err()
+    );
+}
+
+#[test]
+fn test_sketch_encode_decode_streaming() {
+    use std::io::{BufReader, Cursor, Read};
+
+    let mut archive = Vec::new();
+    let mut sketches = Vec::new();
+    for n in 1..4 {
+        let mut sketch = DDSketch::unbounded_dense(2e-2).unwrap();
+        for i in 0..n * 100 {
+            sketch.accept(i as f64 - 50.0);
+        }
+        let encoded = sketch.encode().unwrap();
+        let mut streamed = Vec::new();
+        sketch.encode_to(&mut streamed).unwrap();
+        assert_eq!(encoded, streamed);
+
+        archive.extend_from_slice(&(encoded.len() as u32).to_le_bytes());
+        sketch.encode_to(&mut archive).unwrap();
+        sketches.push(sketch);
+    }
+
+    // Concatenated length-prefixed sketches, each read through its own limited reader.
+    let mut reader = BufReader::new(Cursor::new(archive));
+    let mut merged = DDSketch::unbounded_dense(2e-2).unwrap();
+    for sketch in sketches.iter() {
+        let mut len = [0; 4];
+        reader.read_exact(&mut len).unwrap();
+        let limited = (&mut reader).take(u32::from_le_bytes(len) as u64);
+        let decoded = DDSketch::decode_from(limited).unwrap();
+        assert_eq!(sketch.encode().unwrap(), decoded.encode().unwrap());
+        merged.merge_with(&decoded).unwrap();
+    }
+    assert_eq!(0, reader.read(&mut [0]).unwrap());
+
+    let mut from_reader = DDSketch::unbounded_dense(2e-2).unwrap();
+    for sketch in sketches.iter() {
+        let encoded = sketch.encode().unwrap();
+        from_reader
+            .decode_and_merge_with_reader(encoded.as_slice())
+            .unwrap();
+    }
+    assert_eq!(merged.encode().unwrap(), from_reader.encode().unwrap());
+
+    let encoded = sketches[0].encode().unwrap();
+    let truncated = &encoded[..encoded.len() - 1];
+    assert!(DDSketch::decode_from(truncated).is_err());
+}
+
+#[test]
+fn test_sketch_encode_decode_length_prefixed() {
+    use std::io::{Cursor, Read};
+
+    let mut stream = Vec::new();
+    let mut sketches = Vec::new();
+    for n in 1..4 {
+        let mut sketch = DDSketch::unbounded_dense(2e-2).unwrap();
+        for i in 0..n * 100 {
+            sketch.accept(i as f64 - 50.0);
+        }
+        sketch.encode_length_prefixed_to(&mut stream).unwrap();
+        stream.push(n as u8);
+        sketches.push(sketch);
+    }
+
+    // Each sketch is read up to its end, leaving the byte after it to the caller.
+    let mut reader = Cursor::new(stream.clone());
+    for (n, sketch) in sketches.iter().enumerate() {
+        let decoded = DDSketch::decode_length_prefixed_from(&mut reader).unwrap();
+        assert_eq!(sketch.encode().unwrap(), decoded.encode().unwrap());
+        let mut next = [0];
+        reader.read_exact(&mut next).unwrap();
+        assert_eq!(n as u8 + 1, next[0]);
+    }
+    assert_eq!(0, reader.read(&mut [0]).unwrap());
+
+    // The sketches can be merged into one as they are read.
+    let mut reader = Cursor::new(stream.clone());
+    let mut merged = DDSketch::unbounded_dense(2e-2).unwrap();
+    let mut expected = DDSketch::unbounded_dense(2e-2).unwrap();
+    for sketch in sketches.iter() {
+        merged
+            .decode_and_merge_length_prefixed_from(&mut reader)
+            .unwrap();
+        reader.read_exact(&mut [0]).unwrap();
+        expected.merge_with(sketch).unwrap();
+    }
+    assert_eq!(expected.encode().unwrap(), merged.encode().unwrap());
+
+    // A stream ending before the announced length.
+    let len = sketches[0].encoded_len();
+    let truncated = &stream[..len];
+    match DDSketch::decode_length_prefixed_from(truncated) {
+        Err(Error::Truncated { .. }) => {}
+        other => panic!("unexpected result: {:?}", other.map(|_| ())),
+    }
+}
+
+#[test]
+fn test_sketch_decode_from_buffers_the_reads() {
+    use std::io::Read;
+
+    struct CountingReader<'a> {
+        bytes: &'a [u8],
+        reads: usize,
+    }
+
+    impl Read for CountingReader<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            self.reads += 1;
+            self.bytes.read(buf)
+        }
+    }
+
+    let mut sketch = DDSketch::unbounded_dense(1e-2).unwrap();
+    for i in 1..10_000 {
+        sketch.accept(i as f64);
+    }
+    let encoded = sketch.encode().unwrap();
+    let mut reader = CountingReader {
+        bytes: &encoded,
+        reads: 0,
+    };
+    let decoded = DDSketch::decode_from(&mut reader).unwrap();
+    assert_eq!(sketch.get_count(), decoded.get_count());
+    assert!(reader.reads < 10, "{} reads", reader.reads);
+}
+
+#[test]
+fn test_sketch_encoded_len() {
+    let mut sketches = [
+        DDSketch::unbounded_dense(2e-2).unwrap(),
+        DDSketch::collapsing_lowest_dense(1e-2, 32).unwrap(),
+        DDSketch::builder(2e-2)
+            .zero_threshold(0.5)
+            .encode_value_counters(true)
+            .build()
+            .unwrap(),
+    ];
+    for sketch in sketches.iter_mut() {
+        assert_eq!(sketch.encode().unwrap().len(), sketch.encoded_len());
+        sketch.accept(0.0);
+        assert_eq!(sketch.encode().unwrap().len(), sketch.encoded_len());
+        // Far apart bins, encoded sparsely.
+        sketch.accept(1e-3);
+        sketch.accept(1e6);
+        sketch.accept(-7.0);
+        sketch.accept(f64::NAN);
+        assert_eq!(sketch.encode().unwrap().len(), sketch.encoded_len());
+        // Contiguous bins, encoded densely.
+        for i in 0..1000 {
+            sketch.accept_with_count(i as f64 * 0.1 - 20.0, 0.3 * i as f64);
+        }
+        assert_eq!(sketch.encode().unwrap().len(), sketch.encoded_len());
+    }
+}
@@ -0,0 +1,167 @@
+use sketches_rust::{DDSketch, Error, KeyOverflowPolicy, SketchMap, StoreType};
+
+fn encode_key(key: &(String, u16)) -> Vec<u8> {
+    let mut bytes = key.1.to_le_bytes().to_vec();
+    bytes.extend_from_slice(key.0.as_bytes());
+    bytes
+}
+
+fn decode_key(bytes: &[u8]) -> Result<(String, u16), Error> {
+    if bytes.len() < 2 {
+        return Err(Error::InvalidArgument("Key too short."));
+    }
+    let status = u16::from_le_bytes([bytes[0], bytes[1]]);
+    let endpoint = String::from_utf8(bytes[2..].to_vec())
+        .map_err(|_| Error::InvalidArgument("Invalid endpoint."))?;
+    Ok((endpoint, status))
+}
+
+#[test]
+fn test_sketch_map_accept_and_merge() {
+    let builder = DDSketch::builder(2e-2).store(StoreType::CollapsingLowestDense(512));
+    let mut left = SketchMap::new(builder.clone()).unwrap();
+    let mut right = SketchMap::new(builder).unwrap();
+    for i in 1..=100 {
+        left.accept("a", i as f64);
+        right.accept("a", (i + 100) as f64);
+        right.accept_with_count("b", i as f64, 2.0);
+    }
+    left.merge_with(&right).unwrap();
+
+    assert_eq!(2, left.len());
+    assert_eq!(200.0, left.get(&"a").unwrap().get_count());
+    assert_eq!(200.0, left.get(&"b").unwrap().get_count());
+    let median = left.get(&"a").unwrap().get_value_at_quantile(0.5).unwrap();
+    assert!((median - 100.0).abs() / 100.0 <= 2e-2);
+    assert!(left.get_overflow().is_none());
+
+    let mut other = SketchMap::new(DDSketch::builder(1e-2)).unwrap();
+    other.accept("a", 1.0);
+    assert!(left.merge_with(&other).is_err());
+
+    assert!(left.remove(&"b").is_some());
+    assert_eq!(1, left.iter().count());
+    left.clear();
+    assert!(left.is_empty());
+}
+
+#[test]
+fn test_sketch_map_key_limit() {
+    let mut evicting =
+        SketchMap::with_max_keys(DDSketch::builder(2e-2), 2, KeyOverflowPolicy::EvictSmallest)
+            .unwrap();
+    evicting.accept_with_count(1, 1.0, 5.0);
+    evicting.accept_with_count(2, 1.0, 1.0);
+    evicting.accept_with_count(3, 1.0, 3.0);
+    assert_eq!(2, evicting.len());
+    assert!(evicting.get(&2).is_none());
+    assert_eq!(5.0, evicting.get(&1).unwrap().get_count());
+    assert_eq!(3.0, evicting.get(&3).unwrap().get_count());
+    assert_eq!(1.0, evicting.get_evicted_count());
+    // Existing keys are still recorded into their own sketches.
+    evicting.accept(1, 2.0);
+    assert_eq!(6.0, evicting.get(&1).unwrap().get_count());
+    // The counts of the keys are kept up to date, and ties evict the oldest key.
+    evicting.accept_with_count(3, 1.0, 4.0);
+    evicting.accept_with_count(4, 1.0, 2.0);
+    assert!(evicting.get(&1).is_none());
+    assert_eq!(7.0, evicting.get(&3).unwrap().get_count());
+    evicting.accept_with_count(5, 1.0, 2.0);
+    assert!(evicting.get(&4).is_none());
+    evicting.remove(&3);
+    evicting.accept(6, 1.0);
+    assert_eq!(2, evicting.len());
+    assert_eq!(9.0, evicting.get_evicted_count());
+
+    let mut merged =
+        SketchMap::with_max_keys(DDSketch::builder(2e-2), 1, KeyOverflowPolicy::EvictSmallest)
+            .unwrap();
+    merged.merge_with(&evicting).unwrap();
+    assert_eq!(1, merged.len());
+    let remaining: f64 = merged.iter().map(|(_, sketch)| sketch.get_count()).sum();
+    assert_eq!(12.0, remaining + merged.get_evicted_count());
+
+    let mut overflowing = SketchMap::with_max_keys(
+        DDSketch::builder(2e-2),
+        2,
+        KeyOverflowPolicy::OverflowBucket,
+    )
+    .unwrap();
+    for key in 0..10 {
+        overflowing.accept(key, key as f64);
+    }
+    assert_eq!(2, overflowing.len());
+    assert_eq!(8.0, overflowing.get_overflow().unwrap().get_count());
+
+    assert!(SketchMap::<u8>::with_max_keys(
+        DDSketch::builder(2e-2),
+        0,
+        KeyOverflowPolicy::OverflowBucket
+    )
+    .is_err());
+}
+
+#[test]
+fn test_sketch_map_evict_negative_count() {
+    let builder = DDSketch::builder(2e-2);
+    // A map holding the key 1, whose sketch has a zero count of -5, encoded as a VarDouble.
+    let mut bytes = builder.build().unwrap().encode().unwrap();
+    bytes.extend_from_slice(&[0x04, 0x84, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x20]);
+    let mut map_bytes = vec![1, 1, 1, bytes.len() as u8];
+    map_bytes.extend_from_slice(&bytes);
+    map_bytes.push(0);
+
+    let mut evicting =
+        SketchMap::with_max_keys(builder, 2, KeyOverflowPolicy::EvictSmallest).unwrap();
+    evicting
+        .decode_and_merge_with(&map_bytes, |key| Ok(key[0]))
+        .unwrap();
+    assert_eq!(-5.0, evicting.get(&1).unwrap().get_count());
+    evicting.accept(2, 1.0);
+    evicting.accept(3, 1.0);
+    assert!(evicting.get(&1).is_none());
+    assert!(evicting.get(&2).is_some());
+    assert_eq!(-5.0, evicting.get_evicted_count());
+}
+
+#[test]
+fn test_sketch_map_encode_decode() {
+    let builder = DDSketch::builder(2e-2);
+    let mut map =
+        SketchMap::with_max_keys(builder.clone(), 3, KeyOverflowPolicy::OverflowBucket).unwrap();
+    for i in 0..50 {
+        let key = (format!("/endpoint/{}", i % 4), 200 + (i % 2) as u16);
+        map.accept(key, i as f64 - 10.0);
+    }
+    let bytes = map.encode(encode_key).unwrap();
+
+    let mut decoded = SketchMap::new(builder).unwrap();
+    decoded.decode_and_merge_with(&bytes, decode_key).unwrap();
+    assert_eq!(map.len(), decoded.len());
+    for (key, sketch) in map.iter() {
+        let decoded_sketch = decoded.get(key).unwrap();
+        assert_eq!(sketch.get_count(), decoded_sketch.get_count());
+        for quantile in [0.0, 0.5, 1.0] {
+            assert_eq!(
+                sketch.get_value_at_quantile(quantile),
+                decoded_sketch.get_value_at_quantile(quantile)
+            );
+        }
+    }
+    assert_eq!(
+        map.get_overflow().unwrap().get_count(),
+        decoded.get_overflow().unwrap().get_count()
+    );
+
+    // A buffer that fails to decode, here in its overflow sketch, leaves the map as it was.
+    assert!(decoded
+        .decode_and_merge_with(&bytes[..bytes.len() - 1], decode_key)
+        .is_err());
+    for (key, sketch) in map.iter() {
+        assert_eq!(sketch.get_count(), decoded.get(key).unwrap().get_count());
+    }
+    assert_eq!(
+        map.get_overflow().unwrap().get_count(),
+        decoded.get_overflow().unwrap().get_count()
+    );
+}
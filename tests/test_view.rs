@@ -0,0 +1,113 @@
+use sketches_rust::{DDSketch, EncodedSketchView};
+
+fn assert_view_matches(sketch: &DDSketch) {
+    let encoded = sketch.encode().unwrap();
+    let decoded = DDSketch::decode(&encoded).unwrap();
+    let view = EncodedSketchView::new(&encoded).unwrap();
+    assert_eq!(decoded.get_index_mapping(), view.get_index_mapping());
+    assert_eq!(decoded.is_empty(), view.is_empty());
+    assert_eq!(decoded.get_count(), view.get_count());
+    assert_eq!(decoded.get_min(), view.get_min());
+    assert_eq!(decoded.get_max(), view.get_max());
+    for i in 0..=100 {
+        let q = i as f64 / 100.0;
+        assert_eq!(
+            decoded.get_value_at_quantile(q),
+            view.get_value_at_quantile(q),
+            "quantile {}",
+            q
+        );
+    }
+    assert_eq!(None, view.get_value_at_quantile(1.5));
+}
+
+#[test]
+fn test_view_matches_decoded_sketch() {
+    let mut sketch = DDSketch::unbounded_dense(2e-2).unwrap();
+    assert_view_matches(&sketch);
+    for i in -300..700 {
+        sketch.accept_with_count(i as f64 * 0.7, (i % 3 + 1) as f64);
+    }
+    sketch.accept(0.0);
+    assert_view_matches(&sketch);
+
+    let mut positive = DDSketch::collapsing_lowest_dense(1e-2, 64).unwrap();
+    for i in 1..5000 {
+        positive.accept(i as f64);
+    }
+    assert_view_matches(&positive);
+
+    let mut negative = DDSketch::logarithmic_unbounded_size_dense_store(1e-2).unwrap();
+    for i in 1..500 {
+        negative.accept(-(i as f64));
+    }
+    assert_view_matches(&negative);
+
+    let mut thresholded = DDSketch::builder(2e-2).zero_threshold(1.0).build().unwrap();
+    for i in -100..100 {
+        thresholded.accept(i as f64 * 0.1);
+    }
+    assert_view_matches(&thresholded);
+
+    // Bins below the zero threshold of another encoding count as zeros once decoded.
+    let mut small = DDSketch::builder(2e-2).build().unwrap();
+    for i in -100..100 {
+        small.accept(i as f64 * 0.1);
+    }
+    let mut zeros = DDSketch::builder(2e-2).zero_threshold(1.0).build().unwrap();
+    zeros.accept(0.5);
+    let concatenated = [
+        zeros.encode().unwrap().as_slice(),
+        small.encode().unwrap().as_slice(),
+    ]
+    .concat();
+    let decoded = DDSketch::decode(&concatenated).unwrap();
+    assert_view_matches(&decoded);
+    let view = EncodedSketchView::new(&concatenated).unwrap();
+    assert_eq!(decoded.get_count(), view.get_count());
+    for q in [0.0, 0.25, 0.5, 0.75, 1.0] {
+        assert_eq!(
+            decoded.get_value_at_quantile(q),
+            view.get_value_at_quantile(q)
+        );
+    }
+}
+
+#[test]
+fn test_view_invalid_encodings() {
+    let mut sketch = DDSketch::unbounded_dense(2e-2).unwrap();
+    sketch.accept(1.0);
+    sketch.accept(2.0);
+    let encoded = sketch.encode().unwrap();
+
+    assert!(EncodedSketchView::new(&encoded[..encoded.len() - 1]).is_err());
+    assert!(EncodedSketchView::new(&[]).is_err());
+}
+
+#[test]
+fn test_view_unordered_bins() {
+    // Concatenated encodings decode as a merge, their bins being out of order.
+    let mut first = DDSketch::unbounded_dense(2e-2).unwrap();
+    let mut second = DDSketch::unbounded_dense(2e-2).unwrap();
+    for i in -50..200 {
+        first.accept(i as f64 * 1.3);
+        second.accept_with_count(i as f64 * 0.9, 2.0);
+    }
+    second.accept(0.0);
+    let concatenated = [first.encode().unwrap(), second.encode().unwrap()].concat();
+    let decoded = DDSketch::decode(&concatenated).unwrap();
+    let view = EncodedSketchView::new(&concatenated).unwrap();
+    assert_eq!(decoded.get_count(), view.get_count());
+    assert_eq!(decoded.get_min(), view.get_min());
+    assert_eq!(decoded.get_max(), view.get_max());
+    for i in 0..=100 {
+        let q = i as f64 / 100.0;
+        assert_eq!(
+            decoded.get_value_at_quantile(q),
+            view.get_value_at_quantile(q),
+            "quantile {}",
+            q
+        );
+    }
+    assert_view_matches(&decoded);
+}
@@ -0,0 +1,91 @@
+use sketches_rust::{Clock, DDSketch, ManualClock, StoreType, WindowedDDSketch};
+use std::sync::Arc;
+use std::time::Duration;
+
+const MINUTE: Duration = Duration::from_secs(60);
+
+fn windowed(clock: &Arc<ManualClock>) -> WindowedDDSketch<Arc<ManualClock>> {
+    WindowedDDSketch::with_clock(
+        DDSketch::builder(2e-2).store(StoreType::CollapsingLowestDense(256)),
+        MINUTE,
+        5,
+        Arc::clone(clock),
+    )
+    .unwrap()
+}
+
+#[test]
+fn test_windowed_sketch_rotation() {
+    let clock = Arc::new(ManualClock::new(Duration::from_secs(1_000_000)));
+    let mut sketch = windowed(&clock);
+
+    // One value per minute, the value being the minute number.
+    for minute in 1..=8 {
+        if minute > 1 {
+            clock.advance(MINUTE);
+        }
+        sketch.accept_with_count(minute as f64, 10.0);
+    }
+    assert!(clock.now() >= Duration::from_secs(1_000_000) + 7 * MINUTE);
+
+    // Only minutes 4 to 8 are in the window.
+    assert_eq!(50.0, sketch.get_count());
+    let merged = sketch.get_merged();
+    assert!(f64::abs(merged.get_min().unwrap() - 4.0) / 4.0 <= 2e-2);
+    assert!(f64::abs(merged.get_max().unwrap() - 8.0) / 8.0 <= 2e-2);
+    let median = sketch.get_value_at_quantile(0.5).unwrap();
+    assert!(f64::abs(median - 6.0) / 6.0 <= 2e-2);
+
+    let last_two = sketch.get_merged_last(2);
+    assert_eq!(20.0, last_two.get_count());
+    assert!(f64::abs(last_two.get_min().unwrap() - 7.0) / 7.0 <= 2e-2);
+    // Values accepted after a query are part of the next one.
+    sketch.accept(100.0);
+    let last_two = sketch.get_merged_last(2);
+    assert_eq!(21.0, last_two.get_count());
+    assert!(f64::abs(last_two.get_max().unwrap() - 100.0) / 100.0 <= 2e-2);
+    assert_eq!(11.0, sketch.get_merged_last(1).get_count());
+    assert_eq!(0.0, sketch.get_merged_last(0).get_count());
+    assert_eq!(51.0, sketch.get_count());
+
+    // Moving past the whole window empties it.
+    clock.advance(10 * MINUTE);
+    assert_eq!(0.0, sketch.get_count());
+    assert_eq!(None, sketch.get_value_at_quantile(0.5));
+}
+
+#[test]
+fn test_windowed_sketch_cached_merge() {
+    let clock = Arc::new(ManualClock::default());
+    let mut sketch = windowed(&clock);
+    sketch.accept(1.0);
+    clock.advance(MINUTE);
+    sketch.accept(2.0);
+
+    // The closed intervals are cached, values accepted in the current one still show up.
+    assert_eq!(2.0, sketch.get_merged().get_count());
+    sketch.accept(3.0);
+    assert_eq!(3.0, sketch.get_merged().get_count());
+    clock.advance(MINUTE);
+    sketch.accept(4.0);
+    assert_eq!(4.0, sketch.get_merged().get_count());
+    clock.advance(4 * MINUTE);
+    assert_eq!(1.0, sketch.get_merged().get_count());
+
+    // A clock going backwards keeps recording into the current interval.
+    clock.set(Duration::ZERO);
+    sketch.accept(5.0);
+    assert_eq!(2.0, sketch.get_merged().get_count());
+
+    sketch.clear();
+    assert_eq!(0.0, sketch.get_merged().get_count());
+}
+
+#[test]
+fn test_windowed_sketch_invalid() {
+    let builder = DDSketch::builder(2e-2);
+    assert!(WindowedDDSketch::new(builder.clone(), Duration::ZERO, 5).is_err());
+    assert!(WindowedDDSketch::new(builder.clone(), MINUTE, 0).is_err());
+    assert!(WindowedDDSketch::new(DDSketch::builder(2.0), MINUTE, 5).is_err());
+    assert!(WindowedDDSketch::new(DDSketch::builder(2e-2), MINUTE, 5).is_ok());
+}